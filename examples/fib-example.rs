@@ -16,18 +16,8 @@ fn main() -> Result<(), String> {
 
     let cpu_pool = CpuPool::new_num_cpus();
 
-    let fut_main = gui_main(cpu_pool.clone(), gtk_executor.clone())
-        .then(|_| {
-            gtk::main_quit();
-
-            future::ok(())
-        });
-
-    gtk_executor.spawn(fut_main);
-
-    gtk::main();
-
-    Result::Ok(())
+    gtk_executor.run_main_until(gui_main(cpu_pool.clone(), gtk_executor.clone()))
+        .map_err(|_| "gui_main failed".to_string())
 }
 
 fn gui_main(cpu_pool: CpuPool, gtk_executor: GtkEventLoopAsyncExecutor) -> impl Future<Item=(), Error=()> {
@@ -0,0 +1,196 @@
+//! Procedural macros for `gtk-future-executor`.
+//! This crate should be used through the `macros` feature of `gtk-future-executor`, which re-exports its macros.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse_macro_input;
+
+/// Turns `async fn main()` into a synchronous `main` that initializes Gtk+, creates `GtkEventLoopAsyncExecutor`,
+/// runs the async body on Gtk+ main loop and quits the main loop when the body completes.
+///
+/// The function may optionally take the executor as its only argument.
+/// If it returns `Result<(), E>`, the result is returned from `main`.
+///
+/// Example:
+/// ```rust
+/// #[gtk_async_main]
+/// async fn main(executor: GtkEventLoopAsyncExecutor) -> Result<(), String> {
+///     show_main_window(executor).await
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn gtk_async_main(args: TokenStream, item: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(proc_macro2::Span::call_site(), "#[gtk_async_main] does not take arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let input = parse_macro_input!(item as syn::ItemFn);
+
+    match expand_main(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_main(input: syn::ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    if input.sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(&input.sig.fn_token, "#[gtk_async_main] requires an async function"));
+    }
+    if input.sig.inputs.len() > 1 {
+        return Err(syn::Error::new_spanned(&input.sig.inputs, "#[gtk_async_main] function may only take the executor as an argument"));
+    }
+
+    let attrs = &input.attrs;
+    let vis = &input.vis;
+    let name = &input.sig.ident;
+    let output = &input.sig.output;
+
+    let mut inner = input.clone();
+    inner.attrs.clear();
+    inner.vis = syn::Visibility::Inherited;
+    inner.sig.ident = syn::Ident::new("__gtk_async_main", name.span());
+
+    let call = if input.sig.inputs.is_empty() {
+        quote! { __gtk_async_main() }
+    } else {
+        quote! { __gtk_async_main(executor.clone()) }
+    };
+
+    let run = match output {
+        syn::ReturnType::Default => quote! {
+            let _: ::std::result::Result<(), ()> = executor.run_main_until(
+                ::gtk_future_executor::__private::compat(async move {
+                    #call.await;
+                    ::std::result::Result::Ok(())
+                })
+            );
+        },
+        syn::ReturnType::Type(_, _) => quote! {
+            executor.run_main_until(::gtk_future_executor::__private::compat(#call))
+        },
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis fn #name() #output {
+            #inner
+
+            ::gtk_future_executor::__private::gtk::init().expect("Failed to initialize Gtk+");
+            let executor = ::gtk_future_executor::GtkEventLoopAsyncExecutor::new();
+
+            #run
+        }
+    })
+}
+
+struct TestArgs {
+    timeout_ms: u64,
+    headless: bool,
+}
+
+fn parse_test_args(args: syn::AttributeArgs) -> syn::Result<TestArgs> {
+    let mut result = TestArgs {
+        timeout_ms: 5000,
+        headless: false,
+    };
+
+    for arg in args {
+        match arg {
+            syn::NestedMeta::Meta(syn::Meta::Path(ref path)) if path.is_ident("headless") => {
+                result.headless = true;
+            },
+            syn::NestedMeta::Meta(syn::Meta::NameValue(ref name_value)) if name_value.path.is_ident("timeout_ms") => {
+                match name_value.lit {
+                    syn::Lit::Int(ref lit) => result.timeout_ms = lit.base10_parse()?,
+                    ref lit => return Err(syn::Error::new_spanned(lit, "timeout_ms must be an integer")),
+                }
+            },
+            arg => return Err(syn::Error::new_spanned(arg, "expected `timeout_ms = <integer>` or `headless`")),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Turns `async fn` into a test that runs the body on a fresh `GtkEventLoopAsyncExecutor`.
+///
+/// The test runs on a dedicated thread that initializes Gtk+ once and runs the Gtk+ tests of the test binary
+/// one at a time (Gtk+ may only be used from the thread that initialized it, while every test gets a new thread),
+/// or, with the `headless` argument, the executor runs on a new `glib::MainContext` without Gtk+.
+/// The test fails if the body does not complete within `timeout_ms` milliseconds (5000 by default);
+/// the failure message lists tasks that are still pending.
+///
+/// The function may optionally take the executor as its only argument and may return `Result<(), E: Debug>`.
+///
+/// Example:
+/// ```rust
+/// #[gtk_async_test(timeout_ms = 1000)]
+/// async fn saves_after_debounce(executor: GtkEventLoopAsyncExecutor) {
+///     let saver = make_saver(&executor);
+///     saver.changed();
+///     saver.flush().compat().await.unwrap();
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn gtk_async_test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as syn::AttributeArgs);
+    let input = parse_macro_input!(item as syn::ItemFn);
+
+    match parse_test_args(args).and_then(|args| expand_test(args, input)) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_test(args: TestArgs, input: syn::ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    if input.sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(&input.sig.fn_token, "#[gtk_async_test] requires an async function"));
+    }
+    if input.sig.inputs.len() > 1 {
+        return Err(syn::Error::new_spanned(&input.sig.inputs, "#[gtk_async_test] function may only take the executor as an argument"));
+    }
+
+    let attrs = &input.attrs;
+    let name = &input.sig.ident;
+    let timeout_ms = args.timeout_ms;
+    let headless = args.headless;
+
+    let mut inner = input.clone();
+    inner.attrs.clear();
+    inner.vis = syn::Visibility::Inherited;
+    inner.sig.ident = syn::Ident::new("__gtk_async_test", name.span());
+
+    let call = if input.sig.inputs.is_empty() {
+        quote! { { drop(executor); __gtk_async_test() } }
+    } else {
+        quote! { __gtk_async_test(executor) }
+    };
+
+    let body = match input.sig.output {
+        syn::ReturnType::Default => quote! {
+            #call.await;
+            ::std::result::Result::Ok::<(), ::std::string::String>(())
+        },
+        syn::ReturnType::Type(_, _) => quote! {
+            #call.await.map_err(|error| format!("{:?}", error))
+        },
+    };
+
+    Ok(quote! {
+        #[test]
+        #(#attrs)*
+        fn #name() {
+            #inner
+
+            ::gtk_future_executor::__private::run_test(
+                ::std::time::Duration::from_millis(#timeout_ms),
+                #headless,
+                |executor| ::gtk_future_executor::__private::compat(async move { #body })
+            );
+        }
+    })
+}
@@ -0,0 +1,96 @@
+use futures::prelude::*;
+use futures::sync::mpsc;
+use gio::prelude::*;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::logging;
+
+/// Runs `app` with command line arguments of the process and executes `main` as its async main function.
+///
+/// `main` is invoked with the application on its first `activate` signal and the returned future
+/// is executed on `executor`. The application is held (see `gio::Application::hold`) while
+/// the future is pending, so the application does not exit before the future resolves even if no windows are open.
+///
+/// Returns the exit code: the exit status of the application or `1` if the future failed
+/// (the error is printed to stderr).
+///
+/// Example:
+/// ```rust
+/// let app = gtk::Application::new("org.example.App", gio::ApplicationFlags::empty())?;
+/// gtk::init()?;
+/// let executor = GtkEventLoopAsyncExecutor::new();
+///
+/// let code = run_app(&executor, &app, |app| show_main_window(app));
+/// std::process::exit(code);
+/// ```
+pub fn run_app<A, F, R>(executor: &GtkEventLoopAsyncExecutor, app: &A, main: F) -> i32
+    where A: IsA<gio::Application> + Clone + 'static,
+          F: FnOnce(A) -> R + 'static,
+          R: IntoFuture<Item=()> + 'static,
+          R::Error: std::fmt::Display
+{
+    let failed = Rc::new(Cell::new(false));
+    let main = RefCell::new(Some(main));
+
+    {
+        let executor = executor.clone();
+        let failed = failed.clone();
+        app.connect_activate(move |app| {
+            let main = match main.borrow_mut().take() {
+                Some(main) => main,
+                None => return,
+            };
+
+            let app = app.clone();
+            let failed = failed.clone();
+
+            app.hold();
+            executor.spawn(main(app.clone()).into_future().then(move |result| {
+                if let Err(error) = result {
+                    logging::warning(&format!("Application main future failed: {}", error));
+                    failed.set(true);
+                }
+                app.release();
+
+                Ok(())
+            }));
+        });
+    }
+
+    let args: Vec<String> = std::env::args().collect();
+    let code = app.run(&args);
+
+    if failed.get() { 1 } else { code }
+}
+
+/// Returns a future that resolves on the next `startup` signal of `app`.
+/// Useful for setting up application-wide state (actions, menus, styles) from async code.
+pub fn app_startup<A: IsA<gio::Application>>(app: &A) -> impl Future<Item=(), Error=()> {
+    app_signal_stream(app, |app, sender| {
+        app.connect_startup(move |_| {
+            let _ = sender.unbounded_send(());
+        });
+    }).into_future().map(|_| ()).map_err(|_| ())
+}
+
+/// Returns a stream that yields an item every time `app` is activated
+/// (e.g. when a second instance of a single-instance application is launched).
+pub fn app_activations<A: IsA<gio::Application>>(app: &A) -> impl Stream<Item=(), Error=()> {
+    app_signal_stream(app, |app, sender| {
+        app.connect_activate(move |_| {
+            let _ = sender.unbounded_send(());
+        });
+    })
+}
+
+fn app_signal_stream<A, F>(app: &A, connect: F) -> mpsc::UnboundedReceiver<()>
+    where A: IsA<gio::Application>,
+          F: FnOnce(&A, mpsc::UnboundedSender<()>)
+{
+    let (sender, receiver) = mpsc::unbounded();
+    connect(app, sender);
+    receiver
+}
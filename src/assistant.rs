@@ -0,0 +1,255 @@
+use futures::prelude::*;
+use futures::future;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::rc::Weak;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::promise::Promise;
+use crate::task::TaskHandle;
+
+/// Navigation performed by the user in a `gtk::Assistant`, reported by `AssistantFlow::next_page`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssistantChoice {
+    /// The user went forward (Continue or Apply) to the page with this index
+    Forward(i32),
+    /// The user went back to the page with this index
+    Back(i32),
+    /// The user cancelled the assistant (Cancel button, Escape or closing the window)
+    Cancel,
+    /// The user closed the assistant from its summary or confirmation page
+    Close,
+}
+
+type ValidateFn = Rc<Fn() -> Box<Future<Item=bool, Error=()>>>;
+
+struct PageValidator {
+    page: gtk::Widget,
+    validate: ValidateFn,
+    /// Incremented on every validation; used to find out whether a finished validation is still the latest one
+    generation: u64,
+    running: Option<TaskHandle<(), ()>>,
+}
+
+struct AssistantState {
+    assistant: gtk::Assistant,
+    current_page: i32,
+    /// Choices that were made while nobody was waiting for them
+    choices: VecDeque<AssistantChoice>,
+    waiting: Option<Promise<AssistantChoice, ()>>,
+    validators: Vec<PageValidator>,
+}
+
+/// `AssistantFlow` exposes the navigation of a `gtk::Assistant` as an async API,
+/// so that multi-step flows (onboarding, import wizards) become straight-line async code.
+///
+/// The assistant navigates between pages as usual; `next_page()` returns a future of the next navigation
+/// the user performs. Choices are queued, so none is lost while the flow is busy with the previous one.
+/// A page may have an async validator (`set_page_validator()`) that gates its Continue button:
+/// the page is complete only once the validator resolves with `true`.
+///
+/// `AssistantFlow` is cloneable (all clones refer to the same flow) and may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let flow = AssistantFlow::new(&gtk_executor, &assistant);
+/// let entry = server_entry.clone();
+/// flow.set_page_validator(&server_page, move || api::check_server(&entry.get_text().unwrap()));
+/// assistant.show_all();
+///
+/// gtk_executor.spawn(flow.next_page().map(move |choice| {
+///     match choice {
+///         AssistantChoice::Forward(_) => settings.set_server(&server_entry.get_text().unwrap()),
+///         _ => assistant.destroy(),
+///     }
+/// }));
+/// ```
+#[derive(Clone)]
+pub struct AssistantFlow {
+    executor: GtkEventLoopAsyncExecutor,
+    state: Rc<RefCell<AssistantState>>,
+}
+
+impl AssistantFlow {
+    /// Constructs flow for `assistant`. Navigations made before this call are not reported.
+    pub fn new(executor: &GtkEventLoopAsyncExecutor, assistant: &gtk::Assistant) -> Self {
+        let flow = AssistantFlow {
+            executor: executor.clone(),
+            state: Rc::new(RefCell::new(
+                AssistantState {
+                    assistant: assistant.clone(),
+                    current_page: assistant.get_current_page(),
+                    choices: VecDeque::new(),
+                    waiting: None,
+                    validators: Vec::new(),
+                }
+            )),
+        };
+
+        // The assistant's handlers must not keep the flow (and the assistant) alive
+        let weak_state = Rc::downgrade(&flow.state);
+        let executor = executor.clone();
+        let with_flow = move |f: &Fn(&AssistantFlow)| {
+            if let Some(state) = Weak::upgrade(&weak_state) {
+                f(&AssistantFlow { executor: executor.clone(), state });
+            }
+        };
+
+        {
+            let with_flow = with_flow.clone();
+            assistant.connect_prepare(move |assistant, _| {
+                let page = assistant.get_current_page();
+                with_flow(&|flow| flow.page_prepared(page));
+            });
+        }
+        {
+            let with_flow = with_flow.clone();
+            assistant.connect_cancel(move |_| with_flow(&|flow| flow.choose(AssistantChoice::Cancel)));
+        }
+        assistant.connect_close(move |_| with_flow(&|flow| flow.choose(AssistantChoice::Close)));
+
+        flow
+    }
+
+    /// Returns a future that resolves with the next navigation performed by the user
+    pub fn next_page(&self) -> impl Future<Item=AssistantChoice, Error=()> {
+        let mut state = self.state.borrow_mut();
+        if let Some(choice) = state.choices.pop_front() {
+            return future::Either::A(future::ok(choice));
+        }
+
+        let promise = Promise::new();
+        state.waiting = Some(promise.clone());
+        future::Either::B(promise)
+    }
+
+    /// Returns the index of the current page
+    pub fn current_page(&self) -> i32 {
+        self.state.borrow().current_page
+    }
+
+    /// Sets the async validator of `page`. The page is marked incomplete (so that Continue is insensitive)
+    /// while the validator runs and when it resolves with `false`.
+    /// The page is validated now, every time it is shown and when `revalidate()` is called.
+    pub fn set_page_validator<W, F, R>(&self, page: &W, validate: F)
+        where W: IsA<gtk::Widget>,
+              F: Fn() -> R + 'static,
+              R: IntoFuture<Item=bool, Error=()> + 'static
+    {
+        let page: gtk::Widget = page.clone().upcast();
+        let validate: ValidateFn = Rc::new(move || Box::new(validate().into_future()) as Box<Future<Item=bool, Error=()>>);
+        let index = {
+            let mut state = self.state.borrow_mut();
+            match state.validators.iter().position(|validator| validator.page == page) {
+                Some(index) => {
+                    state.validators[index].validate = validate;
+                    index
+                },
+                None => {
+                    state.validators.push(PageValidator {
+                        page,
+                        validate,
+                        generation: 0,
+                        running: None,
+                    });
+                    state.validators.len() - 1
+                },
+            }
+        };
+
+        self.validate_page(index);
+    }
+
+    /// Validates `page` again, e.g. after the user edited its fields
+    pub fn revalidate<W: IsA<gtk::Widget>>(&self, page: &W) {
+        let index = self.state.borrow().validators.iter().position(|validator| &validator.page == page.as_ref());
+        if let Some(index) = index {
+            self.validate_page(index);
+        }
+    }
+
+    fn choose(&self, choice: AssistantChoice) {
+        let waiting = {
+            let mut state = self.state.borrow_mut();
+            match state.waiting.take() {
+                Some(waiting) => waiting,
+                None => {
+                    state.choices.push_back(choice);
+                    return;
+                },
+            }
+        };
+        waiting.resolve(choice);
+    }
+
+    fn page_prepared(&self, page: i32) {
+        let (previous, assistant) = {
+            let mut state = self.state.borrow_mut();
+            let previous = std::mem::replace(&mut state.current_page, page);
+            (previous, state.assistant.clone())
+        };
+
+        let widget = assistant.get_nth_page(page);
+        let index = self.state.borrow().validators.iter().position(|validator| Some(&validator.page) == widget.as_ref());
+        if let Some(index) = index {
+            self.validate_page(index);
+        }
+
+        // The first page is shown when the assistant is shown, which is not a navigation
+        if previous >= 0 && page != previous {
+            self.choose(if page > previous { AssistantChoice::Forward(page) } else { AssistantChoice::Back(page) });
+        }
+    }
+
+    fn validate_page(&self, index: usize) {
+        let (assistant, page, validate, generation, previous) = {
+            let mut state = self.state.borrow_mut();
+            let assistant = state.assistant.clone();
+            let validator = &mut state.validators[index];
+            validator.generation += 1;
+            (assistant, validator.page.clone(), validator.validate.clone(), validator.generation, validator.running.take())
+        };
+
+        // Dropping the handle cancels the outdated validation
+        drop(previous);
+        assistant.set_page_complete(&page, false);
+
+        let flow = self.clone();
+        let running = self.executor.task().name("AssistantFlow").spawn_with_handle(validate().then(move |result| {
+            flow.validation_finished(index, generation, result.unwrap_or(false));
+            Ok::<(), ()>(())
+        }));
+
+        let mut state = self.state.borrow_mut();
+        let validator = &mut state.validators[index];
+        if validator.generation == generation {
+            validator.running = Some(running);
+        } else {
+            // Validator resolved during spawning
+            running.detach();
+        }
+    }
+
+    fn validation_finished(&self, index: usize, generation: u64, complete: bool) {
+        let (assistant, page, running) = {
+            let mut state = self.state.borrow_mut();
+            let assistant = state.assistant.clone();
+            let validator = match state.validators.get_mut(index) {
+                Some(validator) if validator.generation == generation => validator,
+                _ => return,
+            };
+            // Marks the validation as finished for `validate_page`
+            validator.generation += 1;
+            (assistant, validator.page.clone(), validator.running.take())
+        };
+
+        // The task that owns the handle is this one; it finishes anyway
+        if let Some(running) = running {
+            running.detach();
+        }
+
+        assistant.set_page_complete(&page, complete);
+    }
+}
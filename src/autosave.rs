@@ -0,0 +1,229 @@
+use futures::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::logging;
+use crate::promise::Promise;
+use crate::timer::delay;
+
+type SaveFn<E> = Rc<Fn() -> Box<Future<Item=(), Error=E>>>;
+
+/// Upper bound of the delay before retrying a failed save
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+struct SaverState<E> {
+    dirty: bool,
+    saving: bool,
+    flush_waiters: Vec<Promise<(), E>>,
+    /// Set while the debounce task waits to start a save
+    debouncing: bool,
+    /// Set by a change while the debounce task waits; the task then starts waiting from the beginning
+    restart_debounce: bool,
+    debounce_task: Option<futures::task::Task>,
+    /// Number of saves that failed in a row; the debounce interval is doubled for each of them
+    failures: u32,
+}
+
+/// `DebouncedSaver` is the autosave machine of a document-based application.
+///
+/// Call `changed()` whenever the document changes. Once no changes arrive for the debounce interval,
+/// the save function is invoked. Saves never overlap: changes made while saving schedule another save
+/// after the current one finishes. `flush()` saves pending changes immediately and resolves when
+/// the document is saved, which is what should be awaited before quitting.
+///
+/// A failed save is logged and retried after the debounce interval, which is doubled after every failure in a row
+/// (up to 5 minutes), also for changes made meanwhile; a successful save resets it.
+///
+/// The save function returns a future; long-running work should be moved to a background pool
+/// (e.g. with `CpuPool::spawn_fn`), the returned future is awaited on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let saver = DebouncedSaver::new(&gtk_executor, Duration::from_secs(2), move || {
+///     let text = buffer_text(&buffer);
+///     cpu_pool.spawn_fn(move || write_file(&path, &text))
+/// });
+///
+/// buffer.connect_changed(move |_| saver.changed());
+/// ```
+pub struct DebouncedSaver<E> {
+    executor: GtkEventLoopAsyncExecutor,
+    interval: Duration,
+    save: SaveFn<E>,
+    state: Rc<RefCell<SaverState<E>>>,
+}
+
+impl<E> Clone for DebouncedSaver<E> {
+    fn clone(&self) -> Self {
+        DebouncedSaver {
+            executor: self.executor.clone(),
+            interval: self.interval,
+            save: self.save.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<E: Clone + std::fmt::Display + 'static> DebouncedSaver<E> {
+    /// Constructs saver that invokes `save` after `interval` passes without changes
+    pub fn new<F, R>(executor: &GtkEventLoopAsyncExecutor, interval: Duration, save: F) -> Self
+        where F: Fn() -> R + 'static,
+              R: IntoFuture<Item=(), Error=E> + 'static
+    {
+        DebouncedSaver {
+            executor: executor.clone(),
+            interval,
+            save: Rc::new(move || Box::new(save().into_future()) as Box<Future<Item=(), Error=E>>),
+            state: Rc::new(RefCell::new(
+                SaverState {
+                    dirty: false,
+                    saving: false,
+                    flush_waiters: Vec::new(),
+                    debouncing: false,
+                    restart_debounce: false,
+                    debounce_task: None,
+                    failures: 0,
+                }
+            )),
+        }
+    }
+
+    /// Notifies the saver that the document has changed
+    pub fn changed(&self) {
+        self.state.borrow_mut().dirty = true;
+        self.schedule_save();
+    }
+
+    /// Returns `true` if there are changes that are not saved yet
+    pub fn is_dirty(&self) -> bool {
+        let state = self.state.borrow();
+        state.dirty || state.saving
+    }
+
+    /// Saves pending changes without waiting for the debounce interval.
+    /// Returned future resolves when all changes made before this call are saved
+    /// and fails if saving them fails.
+    pub fn flush(&self) -> Promise<(), E> {
+        let promise = Promise::new();
+
+        let start = {
+            let mut state = self.state.borrow_mut();
+            if !state.dirty && !state.saving {
+                promise.resolve(());
+                return promise;
+            }
+            state.flush_waiters.push(promise.clone());
+            !state.saving
+        };
+
+        if start {
+            self.start_save();
+        }
+
+        promise
+    }
+
+    /// Makes the debounce task wait for the debounce interval from now and then start a save.
+    /// There is at most one debounce task; it is restarted rather than spawned again.
+    fn schedule_save(&self) {
+        {
+            let mut state = self.state.borrow_mut();
+            if state.debouncing {
+                state.restart_debounce = true;
+                if let Some(task) = state.debounce_task.take() {
+                    task.notify();
+                }
+                return;
+            }
+            state.debouncing = true;
+        }
+
+        let saver = self.clone();
+        let mut timer = delay(self.debounce_interval());
+        self.executor.task().name("DebouncedSaver").spawn(futures::future::poll_fn(move || {
+            let restart = {
+                let mut state = saver.state.borrow_mut();
+                state.debounce_task = None;
+                std::mem::replace(&mut state.restart_debounce, false)
+            };
+            if restart {
+                timer = delay(saver.debounce_interval());
+            }
+
+            if timer.poll()?.is_not_ready() {
+                saver.state.borrow_mut().debounce_task = Some(futures::task::current());
+                return Ok(Async::NotReady);
+            }
+
+            saver.state.borrow_mut().debouncing = false;
+            saver.start_save();
+            Ok(Async::Ready(()))
+        }));
+    }
+
+    fn debounce_interval(&self) -> Duration {
+        let failures = self.state.borrow().failures.min(16);
+        let backoff = self.interval.checked_mul(2u32.pow(failures)).unwrap_or(MAX_RETRY_INTERVAL);
+        backoff.min(MAX_RETRY_INTERVAL.max(self.interval))
+    }
+
+    fn start_save(&self) {
+        {
+            let mut state = self.state.borrow_mut();
+            if state.saving || !state.dirty {
+                return;
+            }
+            state.saving = true;
+            state.dirty = false;
+        }
+
+        let saver = self.clone();
+        self.executor.spawn((self.save)().then(move |result| {
+            saver.save_finished(result);
+            Ok(())
+        }));
+    }
+
+    fn save_finished(&self, result: Result<(), E>) {
+        let (waiters, save_again) = {
+            let mut state = self.state.borrow_mut();
+            state.saving = false;
+            if result.is_err() {
+                state.dirty = true;
+                state.failures = state.failures.saturating_add(1);
+            } else {
+                state.failures = 0;
+            }
+
+            if state.dirty && result.is_ok() && !state.flush_waiters.is_empty() {
+                // Changes arrived during saving and somebody waits for them to be saved
+                (Vec::new(), true)
+            } else {
+                (std::mem::replace(&mut state.flush_waiters, Vec::new()), false)
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                for waiter in waiters {
+                    waiter.resolve(());
+                }
+            },
+            Err(error) => {
+                logging::warning(&format!("Autosave failed: {}; retrying in {:?}", error, self.debounce_interval()));
+                for waiter in waiters {
+                    waiter.reject(error.clone());
+                }
+            }
+        }
+
+        if save_again {
+            self.start_save();
+        } else if self.state.borrow().dirty {
+            // Changes made during saving (or the failed save) wait for the debounce interval
+            self.schedule_save();
+        }
+    }
+}
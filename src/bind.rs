@@ -0,0 +1,497 @@
+use futures::prelude::*;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::property::Property;
+use crate::task::TaskHandle;
+use crate::weak::WeakCaptured;
+
+/// Interval between applying coalesced updates to widgets, roughly one frame at 60 Hz
+const FRAME_INTERVAL_MS: u32 = 16;
+
+/// Keeps the latest value pushed into it and hands it to `apply` at most once per frame.
+/// Values pushed while an update is already scheduled replace the pending one.
+struct Coalescer<T> {
+    pending: Rc<RefCell<Option<T>>>,
+    apply: Rc<Fn(T)>,
+}
+
+impl<T: 'static> Coalescer<T> {
+    fn new<F: Fn(T) + 'static>(apply: F) -> Self {
+        Coalescer {
+            pending: Rc::new(RefCell::new(None)),
+            apply: Rc::new(apply),
+        }
+    }
+
+    fn push(&self, value: T) {
+        let was_scheduled = self.pending.borrow_mut().replace(value).is_some();
+        if was_scheduled {
+            return;
+        }
+
+        let pending = self.pending.clone();
+        let apply = self.apply.clone();
+        gtk::timeout_add(FRAME_INTERVAL_MS, move || {
+            if let Some(value) = pending.borrow_mut().take() {
+                apply(value);
+            }
+            gtk::Continue(false)
+        });
+    }
+}
+
+/// A future that feeds stream items into `handler` until the stream ends or fails
+struct BoundStream<S, F> {
+    stream: S,
+    handler: F,
+}
+
+impl<S: Stream, F: FnMut(S::Item)> Future for BoundStream<S, F> {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.stream.poll() {
+                Ok(Async::Ready(Some(item))) => (self.handler)(item),
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(_) => return Err(()),
+            }
+        }
+    }
+}
+
+struct ListBoxBindingState<T, K> {
+    listbox: gtk::ListBox,
+    row_builder: Box<FnMut(&T) -> gtk::Widget>,
+    key: Option<Box<Fn(&T) -> K>>,
+    rows: HashMap<K, gtk::ListBoxRow>,
+    clear_on_rebind: bool,
+}
+
+impl<T, K: Hash + Eq> ListBoxBindingState<T, K> {
+    fn clear(&mut self) {
+        for child in self.listbox.get_children() {
+            self.listbox.remove(&child);
+        }
+        self.rows.clear();
+    }
+
+    fn push(&mut self, item: T) {
+        let widget = (self.row_builder)(&item);
+        let key = self.key.as_ref().map(|key| key(&item));
+
+        if let Some(row) = key.as_ref().and_then(|key| self.rows.get(key)) {
+            if let Some(old) = row.get_child() {
+                row.remove(&old);
+            }
+            row.add(&widget);
+            row.show_all();
+            return;
+        }
+
+        let row = gtk::ListBoxRow::new();
+        row.add(&widget);
+        self.listbox.insert(&row, -1);
+        row.show_all();
+
+        if let Some(key) = key {
+            self.rows.insert(key, row);
+        }
+    }
+}
+
+/// Binds a `Stream` of items to a `gtk::ListBox`.
+///
+/// Every item that arrives on the stream is turned into a widget by the row builder
+/// and appended to the list box on Gtk+ main thread.
+/// If a key function is set with `keyed()`, an item whose key is already shown replaces
+/// the contents of the existing row instead of appending a new one.
+///
+/// Calling `bind()` again switches the binding to a new stream; the previous stream is dropped
+/// and, if `clear_on_rebind(true)` was requested, the list box is cleared.
+/// This is the "async search results → list" pattern:
+///
+/// Example:
+/// ```rust
+/// let binding = ListBoxBinding::new(&gtk_executor, &results_listbox, |item: &String| {
+///     gtk::Label::new(item.as_str()).upcast()
+/// }).clear_on_rebind(true);
+///
+/// entry.connect_changed(move |entry| {
+///     binding.bind(search(entry.get_text()));
+/// });
+/// ```
+pub struct ListBoxBinding<T, K = ()> {
+    executor: GtkEventLoopAsyncExecutor,
+    state: Rc<RefCell<ListBoxBindingState<T, K>>>,
+    /// The task that feeds the bound stream into the list box; dropping the handle drops the stream
+    task: Rc<RefCell<Option<TaskHandle<(), ()>>>>,
+}
+
+impl<T, K> Clone for ListBoxBinding<T, K> {
+    fn clone(&self) -> Self {
+        ListBoxBinding {
+            executor: self.executor.clone(),
+            state: self.state.clone(),
+            task: self.task.clone(),
+        }
+    }
+}
+
+impl<T: 'static> ListBoxBinding<T, ()> {
+    /// Creates a binding that is not yet attached to any stream.
+    pub fn new<F, W>(executor: &GtkEventLoopAsyncExecutor, listbox: &gtk::ListBox, mut row_builder: F) -> Self
+        where F: FnMut(&T) -> W + 'static,
+              W: IsA<gtk::Widget>
+    {
+        ListBoxBinding {
+            executor: executor.clone(),
+            state: Rc::new(RefCell::new(
+                ListBoxBindingState {
+                    listbox: listbox.clone(),
+                    row_builder: Box::new(move |item| row_builder(item).upcast()),
+                    key: None,
+                    rows: HashMap::new(),
+                    clear_on_rebind: false,
+                }
+            )),
+            task: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Makes items with equal keys update the row they were first shown in.
+    /// Must be called before the first `bind()`.
+    pub fn keyed<K, KF>(self, key: KF) -> ListBoxBinding<T, K>
+        where K: Hash + Eq + 'static,
+              KF: Fn(&T) -> K + 'static
+    {
+        let state = match Rc::try_unwrap(self.state) {
+            Ok(state) => state.into_inner(),
+            Err(_) => panic!("ListBoxBinding::keyed() must be called before the binding is cloned"),
+        };
+
+        ListBoxBinding {
+            executor: self.executor,
+            state: Rc::new(RefCell::new(
+                ListBoxBindingState {
+                    listbox: state.listbox,
+                    row_builder: state.row_builder,
+                    key: Some(Box::new(key)),
+                    rows: HashMap::new(),
+                    clear_on_rebind: state.clear_on_rebind,
+                }
+            )),
+            task: self.task,
+        }
+    }
+}
+
+impl<T: 'static, K: Hash + Eq + 'static> ListBoxBinding<T, K> {
+    /// Sets whether the list box is cleared each time a new stream is bound.
+    pub fn clear_on_rebind(self, clear: bool) -> Self {
+        self.state.borrow_mut().clear_on_rebind = clear;
+        self
+    }
+
+    /// Starts showing items from `stream`, dropping the previously bound stream (if any).
+    /// Binding ends when the stream finishes or fails.
+    pub fn bind<S: Stream<Item=T> + 'static>(&self, stream: S) {
+        self.unbind();
+
+        {
+            let mut state = self.state.borrow_mut();
+            if state.clear_on_rebind {
+                state.clear();
+            }
+        }
+
+        let state = self.state.clone();
+        let task = self.executor.spawn_with_handle(BoundStream {
+            stream,
+            handler: move |item| state.borrow_mut().push(item),
+        });
+        *self.task.borrow_mut() = Some(task);
+    }
+
+    /// Drops the currently bound stream, leaving already shown rows in place.
+    pub fn unbind(&self) {
+        let task = self.task.borrow_mut().take();
+        // Dropping the handle cancels the task
+        drop(task);
+    }
+
+    /// Removes all rows from the list box.
+    pub fn clear(&self) {
+        self.state.borrow_mut().clear();
+    }
+}
+
+/// Binds `stream` to `listbox`, appending a row built by `row_builder` for every item.
+/// See `ListBoxBinding` for rebinding and keyed updates.
+pub fn bind_listbox<T, S, F, W>(executor: &GtkEventLoopAsyncExecutor, listbox: &gtk::ListBox, stream: S, row_builder: F) -> ListBoxBinding<T>
+    where T: 'static,
+          S: Stream<Item=T> + 'static,
+          F: FnMut(&T) -> W + 'static,
+          W: IsA<gtk::Widget>
+{
+    let binding = ListBoxBinding::new(executor, listbox, row_builder);
+    binding.bind(stream);
+    binding
+}
+
+/// Drives `progress_bar` from a stream of progress values.
+///
+/// `Some(fraction)` items (or plain `f64` items) set the fraction of the progress bar;
+/// `None` items are heartbeats of an operation with unknown progress and pulse the bar.
+/// Updates are coalesced so that the progress bar is redrawn at most once per frame
+/// no matter how often the stream produces items.
+pub fn bind_progress<S>(executor: &GtkEventLoopAsyncExecutor, progress_bar: &gtk::ProgressBar, stream: S)
+    where S: Stream + 'static,
+          S::Item: Into<Option<f64>>
+{
+    let progress_bar = progress_bar.clone();
+    let coalescer = Coalescer::new(move |progress: Option<f64>| {
+        match progress {
+            Some(fraction) => progress_bar.set_fraction(fraction.max(0.0).min(1.0)),
+            None => progress_bar.pulse(),
+        }
+    });
+
+    executor.spawn(BoundStream {
+        stream,
+        handler: move |item: S::Item| coalescer.push(item.into()),
+    });
+}
+
+/// Keeps the text of `entry` and `property` in sync in both directions:
+/// typing updates the property, and setting the property (e.g. from an async task) updates the entry.
+///
+/// Updates caused by the binding itself are suppressed, so there is no change loop and
+/// the cursor position is not reset while the user types. The entry is initialized with the value of the property.
+/// The binding ends when the entry is destroyed.
+pub fn bind_entry_two_way(entry: &gtk::Entry, property: &Property<String>) {
+    let updating = Rc::new(Cell::new(false));
+
+    entry.set_text(&property.get());
+
+    {
+        let property = property.clone();
+        let updating = updating.clone();
+        entry.connect_changed(move |entry| {
+            if updating.get() {
+                return;
+            }
+            let text = entry.get_text().map(|text| text.to_string()).unwrap_or_default();
+            updating.set(true);
+            property.set(text);
+            updating.set(false);
+        });
+    }
+
+    let entry = WeakCaptured::new(entry);
+    property.observe(move |value: &String| {
+        let entry = match entry.get() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if updating.get() {
+            return true;
+        }
+
+        let current = entry.get_text().map(|text| text.to_string()).unwrap_or_default();
+        if current != *value {
+            updating.set(true);
+            entry.set_text(value);
+            updating.set(false);
+        }
+        true
+    });
+}
+
+/// Applies items of `stream` to `widget` with `apply`, at most once per frame (intermediate items are skipped).
+/// The binding ends when the widget is destroyed.
+fn bind_coalesced<W, S, F>(executor: &GtkEventLoopAsyncExecutor, widget: &W, stream: S, apply: F)
+    where W: IsA<gtk::Widget>,
+          S: Stream + 'static,
+          S::Item: 'static,
+          F: Fn(&W, S::Item) + 'static
+{
+    let weak_widget = WeakCaptured::new(widget);
+    let coalescer = Coalescer::new(move |item| {
+        if let Some(widget) = weak_widget.get() {
+            apply(&widget, item);
+        }
+    });
+
+    let task = executor.spawn_with_handle(BoundStream {
+        stream,
+        handler: move |item| coalescer.push(item),
+    });
+
+    // Dropping the handle cancels the task, so the stream is dropped together with the widget
+    let task = RefCell::new(Some(task));
+    widget.connect_destroy(move |_| {
+        let task = task.borrow_mut().take();
+        drop(task);
+    });
+}
+
+/// Shows the latest text from `stream` in `label`, updating at most once per frame.
+/// The binding ends when the stream ends or the label is destroyed.
+pub fn bind_label_text<S>(executor: &GtkEventLoopAsyncExecutor, label: &gtk::Label, stream: S)
+    where S: Stream + 'static,
+          S::Item: AsRef<str> + 'static
+{
+    bind_coalesced(executor, label, stream, |label, text| label.set_text(text.as_ref()));
+}
+
+/// Shows the latest pixbuf from `stream` in `image` (`None` clears the image), updating at most once per frame.
+/// The binding ends when the stream ends or the image is destroyed.
+pub fn bind_image_pixbuf<S>(executor: &GtkEventLoopAsyncExecutor, image: &gtk::Image, stream: S)
+    where S: Stream<Item=Option<gdk_pixbuf::Pixbuf>> + 'static
+{
+    bind_coalesced(executor, image, stream, |image, pixbuf| image.set_from_pixbuf(pixbuf.as_ref()));
+}
+
+/// Sets the value of `range` (e.g. a `gtk::Scale`) to the latest value from `stream`, updating at most once per frame.
+/// The binding ends when the stream ends or the range is destroyed.
+pub fn bind_range_value<R, S>(executor: &GtkEventLoopAsyncExecutor, range: &R, stream: S)
+    where R: IsA<gtk::Range>,
+          S: Stream<Item=f64> + 'static
+{
+    bind_coalesced(executor, range, stream, |range, value| range.set_value(value));
+}
+
+struct CollectionBindingState<T, K> {
+    store: gtk::ListStore,
+    key: Box<Fn(&T) -> K>,
+    columns: Box<Fn(&T) -> Vec<glib::Value>>,
+    /// Keys and items of the rows in the store, in row order
+    shown: Vec<(K, T)>,
+}
+
+impl<T: PartialEq, K: Hash + Eq> CollectionBindingState<T, K> {
+    fn row(&self, index: usize) -> gtk::TreeIter {
+        self.store.iter_nth_child(None, index as i32).expect("ListStore bound with bind_collection() was modified")
+    }
+
+    fn fill(&self, iter: &gtk::TreeIter, item: &T) {
+        for (column, value) in (self.columns)(item).iter().enumerate() {
+            self.store.set_value(iter, column as u32, value);
+        }
+    }
+
+    /// Turns the shown rows into `items` with removals, moves, insertions and updates of changed rows only
+    fn apply(&mut self, items: Vec<T>) {
+        let keys: Vec<K> = items.iter().map(|item| (self.key)(item)).collect();
+
+        // Rows are matched by key, which is ambiguous if keys are duplicated
+        if has_duplicates(keys.iter()) || has_duplicates(self.shown.iter().map(|(key, _)| key)) {
+            self.rebuild(keys, items);
+            return;
+        }
+
+        {
+            let new_keys: HashSet<&K> = keys.iter().collect();
+            for index in (0..self.shown.len()).rev() {
+                if !new_keys.contains(&self.shown[index].0) {
+                    self.store.remove(&self.row(index));
+                    self.shown.remove(index);
+                }
+            }
+        }
+
+        for (index, (key, item)) in keys.into_iter().zip(items).enumerate() {
+            let current = self.shown[index..].iter()
+                .position(|(shown, _)| *shown == key)
+                .map(|offset| index + offset);
+
+            match current {
+                Some(current) => {
+                    if current != index {
+                        self.store.move_before(&self.row(current), Some(&self.row(index)));
+                        let moved = self.shown.remove(current);
+                        self.shown.insert(index, moved);
+                    }
+                    if self.shown[index].1 != item {
+                        self.fill(&self.row(index), &item);
+                        self.shown[index].1 = item;
+                    }
+                },
+                None => {
+                    let iter = self.store.insert(index as i32);
+                    self.fill(&iter, &item);
+                    self.shown.insert(index, (key, item));
+                },
+            }
+        }
+    }
+
+    /// Replaces all rows of the store with `items`
+    fn rebuild(&mut self, keys: Vec<K>, items: Vec<T>) {
+        self.store.clear();
+        self.shown.clear();
+        for (key, item) in keys.into_iter().zip(items) {
+            let iter = self.store.append();
+            self.fill(&iter, &item);
+            self.shown.push((key, item));
+        }
+    }
+}
+
+fn has_duplicates<'a, K: Hash + Eq + 'a, I: Iterator<Item=&'a K>>(mut keys: I) -> bool {
+    let mut seen = HashSet::new();
+    !keys.all(|key| seen.insert(key))
+}
+
+/// Shows snapshots of a collection from `stream` in `store` (e.g. the model of a `gtk::TreeView`).
+///
+/// Each snapshot is diffed against the previous one by `key` and item equality, and only the differences
+/// are applied to the store: rows of removed items are removed, moved items are reordered, new items are inserted
+/// and rows of changed items are updated. Unchanged rows are not touched, so the selection and scroll position
+/// of the view survive async refreshes. `columns` returns the values of the row columns for an item, in column order.
+///
+/// Keys of the items of a snapshot should be unique: a snapshot with duplicate keys (or the first snapshot after it)
+/// can't be diffed and is shown by rebuilding the store, which resets the selection.
+/// The store must not be modified by other code while it is bound.
+/// The binding ends when the stream ends or fails.
+///
+/// Example:
+/// ```rust
+/// let store = gtk::ListStore::new(&[String::static_type(), u64::static_type()]);
+/// tree_view.set_model(Some(&store));
+///
+/// bind_collection(&gtk_executor, poll_downloads(), &store, |download: &Download| download.id, |download| {
+///     vec![download.name.to_value(), download.received.to_value()]
+/// });
+/// ```
+pub fn bind_collection<T, K, S, KF, CF>(executor: &GtkEventLoopAsyncExecutor, stream: S, store: &gtk::ListStore, key: KF, columns: CF)
+    where T: PartialEq + 'static,
+          K: Hash + Eq + 'static,
+          S: Stream<Item=Vec<T>> + 'static,
+          KF: Fn(&T) -> K + 'static,
+          CF: Fn(&T) -> Vec<glib::Value> + 'static
+{
+    store.clear();
+
+    let mut state = CollectionBindingState {
+        store: store.clone(),
+        key: Box::new(key),
+        columns: Box::new(columns),
+        shown: Vec::new(),
+    };
+
+    executor.spawn(BoundStream {
+        stream,
+        handler: move |items| state.apply(items),
+    });
+}
@@ -0,0 +1,89 @@
+use futures::prelude::*;
+use gio::prelude::*;
+use gtk::prelude::*;
+use std::path::Path;
+
+use crate::promise::Promise;
+
+/// Error of loading a UI definition with `builder_from_file_async` or of looking up its objects with `builder_object`
+#[derive(Debug, Clone)]
+pub enum BuilderError {
+    /// The UI definition could not be read
+    Io(glib::Error),
+    /// The UI definition is not valid UTF-8
+    InvalidUtf8,
+    /// The UI definition could not be parsed or its objects could not be constructed
+    Parse(glib::Error),
+    /// The builder has no object with this id
+    MissingObject(String),
+    /// The object with this id is not of the expected type
+    WrongType { id: String, expected: String, actual: String },
+}
+
+impl std::fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            BuilderError::Io(ref error) => write!(f, "failed to read UI definition: {}", error),
+            BuilderError::InvalidUtf8 => write!(f, "UI definition is not valid UTF-8"),
+            BuilderError::Parse(ref error) => write!(f, "failed to load UI definition: {}", error),
+            BuilderError::MissingObject(ref id) => write!(f, "UI definition has no object {:?}", id),
+            BuilderError::WrongType { ref id, ref expected, ref actual } =>
+                write!(f, "object {:?} of UI definition is {}, not {}", id, actual, expected),
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+/// Loads a `gtk::Builder` UI definition from the file at `path`.
+///
+/// The file is read with asynchronous gio I/O; the objects are constructed on Gtk+ main thread once
+/// the whole file is read, so heavyweight UIs can be loaded after the main window is painted.
+/// Returned future must be executed on Gtk+ main thread (e.g. with `GtkEventLoopAsyncExecutor::spawn`).
+///
+/// Example:
+/// ```rust
+/// gtk_executor.spawn_fallible(
+///     builder_from_file_async("ui/preferences.ui")
+///         .and_then(|builder| {
+///             let dialog: gtk::Dialog = builder_object(&builder, "preferences_dialog")?;
+///             let font_button: gtk::FontButton = builder_object(&builder, "font_button")?;
+///             init_preferences(&dialog, &font_button);
+///             Ok(())
+///         })
+/// );
+/// ```
+pub fn builder_from_file_async<P: AsRef<Path>>(path: P) -> impl Future<Item=gtk::Builder, Error=BuilderError> {
+    let contents = Promise::new();
+
+    {
+        let contents = contents.clone();
+        gio::File::new_for_path(path.as_ref()).load_contents_async(None::<&gio::Cancellable>, move |result| {
+            match result {
+                Ok((bytes, _etag)) => contents.resolve(bytes),
+                Err(error) => contents.reject(error),
+            }
+        });
+    }
+
+    contents
+        .map_err(BuilderError::Io)
+        .and_then(|bytes| {
+            let text = String::from_utf8(bytes).map_err(|_| BuilderError::InvalidUtf8)?;
+            let builder = gtk::Builder::new();
+            builder.add_from_string(&text).map_err(BuilderError::Parse)?;
+            Ok(builder)
+        })
+}
+
+/// Returns the object of `builder` with `id` if it is of type `T`
+pub fn builder_object<T: IsA<glib::Object>>(builder: &gtk::Builder, id: &str) -> Result<T, BuilderError> {
+    let object: glib::Object = builder.get_object(id)
+        .ok_or_else(|| BuilderError::MissingObject(id.to_string()))?;
+    let actual = object.get_type().name();
+    object.downcast::<T>().map_err(|_| BuilderError::WrongType {
+        id: id.to_string(),
+        expected: T::static_type().name(),
+        actual,
+    })
+}
@@ -0,0 +1,228 @@
+use futures::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+
+/// Per-widget count of active guards.
+/// Guards may overlap, so the UI is restored only when the last guard touching a widget is dropped.
+struct BusyRegistry {
+    /// Guard count and sensitivity the widget had before the first guard
+    insensitive: HashMap<gtk::Widget, (usize, bool)>,
+    spinning: HashMap<gtk::Spinner, usize>,
+    cursors: HashMap<gtk::Window, usize>,
+}
+
+thread_local! {
+    static BUSY_REGISTRY: RefCell<BusyRegistry> = RefCell::new(
+        BusyRegistry {
+            insensitive: HashMap::new(),
+            spinning: HashMap::new(),
+            cursors: HashMap::new(),
+        }
+    );
+}
+
+/// A set of widgets that indicate that some operation is in progress:
+/// spinners that are started and widgets (usually buttons) that are made insensitive.
+#[derive(Debug, Clone, Default)]
+pub struct BusyTargets {
+    spinners: Vec<gtk::Spinner>,
+    widgets: Vec<gtk::Widget>,
+}
+
+impl BusyTargets {
+    /// Constructs an empty set
+    pub fn new() -> Self {
+        BusyTargets::default()
+    }
+
+    /// Adds a spinner that is active while busy
+    pub fn spinner(mut self, spinner: &gtk::Spinner) -> Self {
+        self.spinners.push(spinner.clone());
+        self
+    }
+
+    /// Adds a widget that is insensitive while busy
+    pub fn desensitize<W: IsA<gtk::Widget>>(mut self, widget: &W) -> Self {
+        self.widgets.push(widget.clone().upcast());
+        self
+    }
+}
+
+/// `BusyGuard` puts the UI into "busy" state while it is alive:
+/// spinners from `BusyTargets` are started and widgets are made insensitive.
+/// When the guard is dropped the UI is restored.
+///
+/// Guards may overlap: a widget is restored only when every guard that touched it is dropped.
+/// `BusyGuard` may only be used on Gtk+ main thread.
+pub struct BusyGuard {
+    targets: BusyTargets,
+}
+
+impl BusyGuard {
+    /// Puts `targets` into busy state
+    pub fn new(targets: &BusyTargets) -> Self {
+        BUSY_REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+
+            for spinner in &targets.spinners {
+                let count = registry.spinning.entry(spinner.clone()).or_insert(0);
+                if *count == 0 {
+                    spinner.start();
+                }
+                *count += 1;
+            }
+
+            for widget in &targets.widgets {
+                let entry = registry.insensitive.entry(widget.clone()).or_insert((0, widget.get_sensitive()));
+                entry.0 += 1;
+                widget.set_sensitive(false);
+            }
+        });
+
+        BusyGuard {
+            targets: targets.clone(),
+        }
+    }
+}
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        BUSY_REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+
+            for spinner in &self.targets.spinners {
+                let remaining = match registry.spinning.get_mut(spinner) {
+                    Some(count) => {
+                        *count -= 1;
+                        *count
+                    },
+                    None => continue,
+                };
+                if remaining == 0 {
+                    registry.spinning.remove(spinner);
+                    spinner.stop();
+                }
+            }
+
+            for widget in &self.targets.widgets {
+                let restore = match registry.insensitive.get_mut(widget) {
+                    Some(entry) => {
+                        entry.0 -= 1;
+                        if entry.0 == 0 { Some(entry.1) } else { None }
+                    },
+                    None => continue,
+                };
+                if let Some(sensitive) = restore {
+                    registry.insensitive.remove(widget);
+                    widget.set_sensitive(sensitive);
+                }
+            }
+        });
+    }
+}
+
+/// Wraps `f` so that `targets` are busy from the first poll until the future completes, fails or is dropped.
+pub fn with_busy<F: Future>(targets: &BusyTargets, f: F) -> impl Future<Item=F::Item, Error=F::Error> {
+    let targets = targets.clone();
+    let mut guard = None;
+    let mut f = f;
+
+    futures::future::poll_fn(move || {
+        if guard.is_none() {
+            guard = Some(BusyGuard::new(&targets));
+        }
+
+        let result = f.poll();
+        match result {
+            Ok(Async::NotReady) => {},
+            _ => {
+                guard.take();
+            }
+        }
+        result
+    })
+}
+
+/// Executes `f` on `executor` keeping `targets` busy until it finishes.
+/// Unlike toggling widgets by hand, in-flight state display can't get out of sync with task completion.
+pub fn spawn_busy<F: Future<Item=(), Error=()> + 'static>(executor: &GtkEventLoopAsyncExecutor, targets: &BusyTargets, f: F) {
+    executor.spawn(with_busy(targets, f));
+}
+
+fn set_cursor(window: &gtk::Window, cursor_type: Option<gdk::CursorType>) {
+    if let Some(gdk_window) = window.get_window() {
+        let cursor = cursor_type.map(|cursor_type| gdk::Cursor::new_for_display(&gdk_window.get_display(), cursor_type));
+        gdk_window.set_cursor(cursor.as_ref());
+    }
+}
+
+/// `BusyCursorGuard` shows the watch cursor over a window while it is alive.
+/// Guards may overlap: the cursor is cleared when the last guard for the window is dropped.
+pub struct BusyCursorGuard {
+    window: gtk::Window,
+}
+
+impl BusyCursorGuard {
+    /// Sets the watch cursor on `window`
+    pub fn new<W: IsA<gtk::Window>>(window: &W) -> Self {
+        let window: gtk::Window = window.clone().upcast();
+
+        BUSY_REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+            let count = registry.cursors.entry(window.clone()).or_insert(0);
+            if *count == 0 {
+                set_cursor(&window, Some(gdk::CursorType::Watch));
+            }
+            *count += 1;
+        });
+
+        BusyCursorGuard {
+            window,
+        }
+    }
+}
+
+impl Drop for BusyCursorGuard {
+    fn drop(&mut self) {
+        BUSY_REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+            let remaining = match registry.cursors.get_mut(&self.window) {
+                Some(count) => {
+                    *count -= 1;
+                    *count
+                },
+                None => return,
+            };
+            if remaining == 0 {
+                registry.cursors.remove(&self.window);
+                set_cursor(&self.window, None);
+            }
+        });
+    }
+}
+
+/// Wraps `f` so that `window` shows the watch cursor while the future is pending.
+/// The cursor is cleared when the future completes, fails or is dropped without completing.
+pub fn with_busy_cursor<W: IsA<gtk::Window>, F: Future>(window: &W, f: F) -> impl Future<Item=F::Item, Error=F::Error> {
+    let window: gtk::Window = window.clone().upcast();
+    let mut guard = None;
+    let mut f = f;
+
+    futures::future::poll_fn(move || {
+        if guard.is_none() {
+            guard = Some(BusyCursorGuard::new(&window));
+        }
+
+        let result = f.poll();
+        match result {
+            Ok(Async::NotReady) => {},
+            _ => {
+                guard.take();
+            }
+        }
+        result
+    })
+}
@@ -0,0 +1,249 @@
+use futures::future::Either;
+use futures::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+use std::rc::Weak;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::task::JoinError;
+use crate::task::SharedHandle;
+
+enum CacheEntry<V, E> {
+    Loading {
+        load: u64,
+        handle: SharedHandle<V, E>,
+    },
+    Ready {
+        value: V,
+        loaded_at: Instant,
+        /// Value of `CacheState::uses` when the entry was last returned; the smallest one is evicted first
+        last_used: u64,
+    },
+}
+
+struct CacheState<K, V, E> {
+    entries: HashMap<K, CacheEntry<V, E>>,
+    capacity: Option<usize>,
+    ttl: Option<Duration>,
+    uses: u64,
+    next_load: u64,
+}
+
+impl<K: Hash + Eq + Clone, V, E> CacheState<K, V, E> {
+    fn is_expired(&self, loaded_at: Instant) -> bool {
+        self.ttl.map(|ttl| loaded_at.elapsed() >= ttl).unwrap_or(false)
+    }
+
+    fn finish(&mut self, key: K, load: u64, value: Option<V>) {
+        let is_current = match self.entries.get(&key) {
+            Some(CacheEntry::Loading { load: current, .. }) => *current == load,
+            _ => false,
+        };
+        if !is_current {
+            // The entry was invalidated while loading
+            return;
+        }
+
+        match value {
+            Some(value) => {
+                self.uses += 1;
+                let last_used = self.uses;
+                self.entries.insert(key, CacheEntry::Ready { value, loaded_at: Instant::now(), last_used });
+                self.evict();
+            },
+            // Failures are not cached
+            None => {
+                self.entries.remove(&key);
+            },
+        }
+    }
+
+    fn evict(&mut self) {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => return,
+        };
+
+        let mut ready: Vec<(u64, K)> = self.entries.iter()
+            .filter_map(|(key, entry)| match entry {
+                CacheEntry::Ready { last_used, .. } => Some((*last_used, key.clone())),
+                CacheEntry::Loading { .. } => None,
+            })
+            .collect();
+        if ready.len() <= capacity {
+            return;
+        }
+
+        ready.sort_by_key(|(last_used, _)| *last_used);
+        let excess = ready.len() - capacity;
+        for (_, key) in ready.into_iter().take(excess) {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// `AsyncCache` memoizes asynchronously loaded resources (thumbnails, metadata, ...) by key.
+///
+/// `get_or_load()` returns a cached value or starts the loader in a task on Gtk+ main loop.
+/// Concurrent requests for a key that is being loaded share the same load. Loaded values are cached
+/// until they are invalidated, expire (`with_ttl()`) or are evicted as the least recently used
+/// when the cache is full (`with_capacity()`). Failed loads are not cached.
+///
+/// `AsyncCache` is cloneable (all clones refer to the same cache) and may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let thumbnails = AsyncCache::new(&gtk_executor).with_capacity(500);
+///
+/// for (path, image) in visible_files {
+///     gtk_executor.spawn(
+///         thumbnails.get_or_load(path.clone(), |path| cpu_pool.spawn_fn(move || make_thumbnail(&path)))
+///             .map(move |pixbuf| image.set_from_pixbuf(Some(&pixbuf)))
+///             .map_err(|_| ())
+///     );
+/// }
+/// ```
+pub struct AsyncCache<K, V, E> {
+    executor: GtkEventLoopAsyncExecutor,
+    state: Rc<RefCell<CacheState<K, V, E>>>,
+}
+
+impl<K, V, E> Clone for AsyncCache<K, V, E> {
+    fn clone(&self) -> Self {
+        AsyncCache {
+            executor: self.executor.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K, V, E> AsyncCache<K, V, E>
+    where K: Hash + Eq + Clone + 'static,
+          V: Clone + 'static,
+          E: Clone + 'static
+{
+    /// Constructs an empty cache without capacity limit and expiration
+    pub fn new(executor: &GtkEventLoopAsyncExecutor) -> Self {
+        AsyncCache {
+            executor: executor.clone(),
+            state: Rc::new(RefCell::new(
+                CacheState {
+                    entries: HashMap::new(),
+                    capacity: None,
+                    ttl: None,
+                    uses: 0,
+                    next_load: 0,
+                }
+            )),
+        }
+    }
+
+    /// Limits the number of cached values; the least recently used values are evicted first
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        {
+            let mut state = self.state.borrow_mut();
+            state.capacity = Some(capacity);
+            state.evict();
+        }
+        self
+    }
+
+    /// Makes cached values expire `ttl` after they were loaded; expired values are loaded again
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        self.state.borrow_mut().ttl = Some(ttl);
+        self
+    }
+
+    /// Returns a future that resolves with the value for `key`, loading it with `loader` if it is not cached.
+    /// If the value is being loaded already, the future waits for that load and `loader` is not called.
+    pub fn get_or_load<F, R>(&self, key: K, loader: F) -> impl Future<Item=V, Error=JoinError<E>>
+        where F: FnOnce(&K) -> R,
+              R: IntoFuture<Item=V, Error=E> + 'static
+    {
+        let load = {
+            let mut state = self.state.borrow_mut();
+            state.uses += 1;
+            let uses = state.uses;
+
+            let ttl = state.ttl;
+            let mut expired = false;
+            match state.entries.get_mut(&key) {
+                Some(CacheEntry::Ready { value, loaded_at, last_used }) => {
+                    expired = ttl.map(|ttl| loaded_at.elapsed() >= ttl).unwrap_or(false);
+                    if !expired {
+                        *last_used = uses;
+                        return Either::A(futures::future::ok(value.clone()));
+                    }
+                },
+                Some(CacheEntry::Loading { handle, .. }) => return Either::B(handle.clone()),
+                None => {},
+            }
+            if expired {
+                state.entries.remove(&key);
+            }
+
+            state.next_load += 1;
+            state.next_load
+        };
+
+        let state = Rc::downgrade(&self.state);
+        let loaded_key = key.clone();
+        let handle = self.executor.task().name("AsyncCache").spawn_shared(loader(&key).into_future().then(move |result| {
+            finish_load(&state, loaded_key, load, result.as_ref().ok().cloned());
+            result
+        }));
+
+        let mut state = self.state.borrow_mut();
+        state.entries.insert(key.clone(), CacheEntry::Loading { load, handle: handle.clone() });
+        if handle.is_finished() {
+            // The load completed on its first poll inside `spawn_shared()` (before its entry existed)
+            // or could not be spawned because the executor is shutting down
+            let value = match handle.clone().poll() {
+                Ok(Async::Ready(value)) => Some(value),
+                _ => None,
+            };
+            state.finish(key, load, value);
+        }
+
+        Either::B(handle)
+    }
+
+    /// Returns the cached value for `key` without loading it
+    pub fn get(&self, key: &K) -> Option<V> {
+        let state = self.state.borrow();
+        match state.entries.get(key) {
+            Some(CacheEntry::Ready { value, loaded_at, .. }) if !state.is_expired(*loaded_at) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Removes the value for `key`. A load in progress is not cancelled, but its result is not cached.
+    pub fn invalidate(&self, key: &K) {
+        self.state.borrow_mut().entries.remove(key);
+    }
+
+    /// Removes all values
+    pub fn clear(&self) {
+        self.state.borrow_mut().entries.clear();
+    }
+
+    /// Returns the number of cached values and loads in progress
+    pub fn len(&self) -> usize {
+        self.state.borrow().entries.len()
+    }
+
+    /// Returns `true` if nothing is cached or being loaded
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn finish_load<K: Hash + Eq + Clone, V, E>(state: &Weak<RefCell<CacheState<K, V, E>>>, key: K, load: u64, value: Option<V>) {
+    if let Some(state) = state.upgrade() {
+        state.borrow_mut().finish(key, load, value);
+    }
+}
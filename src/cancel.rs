@@ -0,0 +1,89 @@
+use futures::prelude::*;
+use std::sync::Arc;
+
+use crate::sync::Mutex;
+
+#[derive(Debug)]
+struct CancellationTokenBackend {
+    cancelled: bool,
+    waiting_tasks: Vec<futures::task::Task>,
+}
+
+/// `CancellationToken` is a flag that is used to ask a long-running operation to stop.
+///
+/// `CancellationToken` is freely cloneable (all clones refer to the same flag) and is thread-safe.
+/// Once cancelled, the token stays cancelled.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    backend: Arc<Mutex<CancellationTokenBackend>>,
+}
+
+impl CancellationToken {
+    /// Construct a new token that is not cancelled
+    pub fn new() -> CancellationToken {
+        CancellationToken {
+            backend: Arc::new(
+                Mutex::new(
+                    CancellationTokenBackend {
+                        cancelled: false,
+                        waiting_tasks: vec![],
+                    }
+                )
+            )
+        }
+    }
+
+    /// Request cancellation. Wakes all futures returned by `cancelled()`.
+    pub fn cancel(&self) {
+        let mut backend = self.backend.lock();
+
+        backend.cancelled = true;
+        for task in &backend.waiting_tasks {
+            task.notify();
+        }
+
+        backend.waiting_tasks.clear();
+    }
+
+    /// Returns `true` if `cancel()` was called on this token or any of its clones
+    pub fn is_cancelled(&self) -> bool {
+        self.backend.lock().cancelled
+    }
+
+    /// Returns a future that resolves when the token is cancelled
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken::new()
+    }
+}
+
+/// Future returned by `CancellationToken::cancelled()`
+#[derive(Debug)]
+pub struct Cancelled {
+    token: CancellationToken,
+}
+
+impl Future for Cancelled {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let mut backend = self.token.backend.lock();
+
+        if backend.cancelled {
+            std::result::Result::Ok(Async::Ready(()))
+        } else {
+            if !backend.waiting_tasks.iter().any(|task| task.will_notify_current()) {
+                backend.waiting_tasks.push(futures::task::current());
+            }
+            std::result::Result::Ok(Async::NotReady)
+        }
+    }
+}
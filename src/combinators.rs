@@ -0,0 +1,123 @@
+use futures::future::Either;
+use futures::prelude::*;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::promise::Promise;
+
+fn spawn_continuation<F, C, R>(executor: &GtkEventLoopAsyncExecutor, f: F, continuation: C) -> Promise<R::Item, R::Error>
+    where F: Future + 'static,
+          C: FnOnce(Result<F::Item, F::Error>) -> R + 'static,
+          R: IntoFuture + 'static
+{
+    let promise = Promise::new();
+
+    {
+        let promise = promise.clone();
+        executor.spawn(f.then(continuation).then(move |result| {
+            match result {
+                Ok(item) => promise.resolve(item),
+                Err(error) => promise.reject(error),
+            }
+            Ok(())
+        }));
+    }
+
+    promise
+}
+
+/// Waits for both `a` and `b` and calls `continuation` with their results on Gtk+ main thread.
+///
+/// With plain `a.join(b).then(...)` the continuation runs wherever the joined future is polled, so if it ends up
+/// on another executor (e.g. a thread pool) the continuation can't touch widgets. Here the joined future is
+/// executed on `executor`, so `continuation` always runs on Gtk+ main thread regardless of where
+/// `a` and `b` are completed. If either future fails, `continuation` receives the first error.
+///
+/// Returned `Promise` resolves with the result of the future returned by `continuation`; it may be awaited from any executor.
+/// Must be called on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// join_on_main(&gtk_executor, cpu_pool.spawn(parse(text)), fetch_styles(), move |result| {
+///     match result {
+///         Ok((document, styles)) => view.show(&document, &styles),
+///         Err(error) => view.show_error(&error),
+///     }
+///     Ok::<(), ()>(())
+/// });
+/// ```
+pub fn join_on_main<A, B, C, R>(executor: &GtkEventLoopAsyncExecutor, a: A, b: B, continuation: C) -> Promise<R::Item, R::Error>
+    where A: Future + 'static,
+          B: Future<Error=A::Error> + 'static,
+          C: FnOnce(Result<(A::Item, B::Item), A::Error>) -> R + 'static,
+          R: IntoFuture + 'static
+{
+    spawn_continuation(executor, a.join(b), continuation)
+}
+
+/// Waits for the first of `a` and `b` to complete and calls `continuation` with its result on Gtk+ main thread.
+/// The other future is dropped.
+///
+/// Like `join_on_main`, the combined future is executed on `executor`, so `continuation` can safely use widgets
+/// even if the component futures are completed on other threads. Must be called on Gtk+ main thread.
+pub fn select_on_main<A, B, C, R>(executor: &GtkEventLoopAsyncExecutor, a: A, b: B, continuation: C) -> Promise<R::Item, R::Error>
+    where A: Future + 'static,
+          B: Future<Item=A::Item, Error=A::Error> + 'static,
+          C: FnOnce(Result<A::Item, A::Error>) -> R + 'static,
+          R: IntoFuture + 'static
+{
+    let first = a.select(b)
+        .map(|(item, _other)| item)
+        .map_err(|(error, _other)| error);
+
+    spawn_continuation(executor, first, continuation)
+}
+
+impl<A: 'static, B: 'static, E: 'static> Promise<Either<A, B>, E> {
+    /// Returns a promise that completes with the result of whichever of `a` and `b` completes first;
+    /// the other future is dropped. Unlike `Future::select`, the futures may have different item types,
+    /// and an error is returned as is, without the other future.
+    ///
+    /// The futures are executed on `executor`, so the promise is completed on Gtk+ main thread.
+    /// Must be called on Gtk+ main thread.
+    ///
+    /// Example:
+    /// ```rust
+    /// let choice = Promise::select(&gtk_executor, window_closed(&window), choose_file(&window));
+    /// gtk_executor.spawn(choice.map(|choice| match choice {
+    ///     Either::A(()) => {},
+    ///     Either::B(path) => open(path),
+    /// }));
+    /// ```
+    pub fn select<FA, FB>(executor: &GtkEventLoopAsyncExecutor, a: FA, b: FB) -> Self
+        where FA: IntoFuture<Item=A, Error=E> + 'static,
+              FB: IntoFuture<Item=B, Error=E> + 'static,
+              FA::Future: 'static,
+              FB::Future: 'static
+    {
+        let first = a.into_future().map(Either::A)
+            .select(b.into_future().map(Either::B))
+            .map(|(item, _other)| item)
+            .map_err(|(error, _other)| error);
+
+        spawn_continuation(executor, first, |result| result)
+    }
+}
+
+impl<T: 'static, E: 'static> Promise<(usize, T), E> {
+    /// Returns a promise that completes with the index and the result of whichever of `candidates` completes first;
+    /// the other futures are dropped. The futures are executed on `executor`, so the promise is completed
+    /// on Gtk+ main thread. Must be called on Gtk+ main thread.
+    ///
+    /// Panics if `candidates` is empty.
+    pub fn select_all<I>(executor: &GtkEventLoopAsyncExecutor, candidates: I) -> Self
+        where I: IntoIterator,
+              I::Item: IntoFuture<Item=T, Error=E>,
+              <I::Item as IntoFuture>::Future: 'static
+    {
+        let first = futures::future::select_all(candidates)
+            .map(|(item, index, _other)| (index, item))
+            .map_err(|(error, _index, _other)| error);
+
+        spawn_continuation(executor, first, |result| result)
+    }
+}
@@ -0,0 +1,48 @@
+//! Shims between futures 0.1 (used by this crate and by gtk-rs callback code of its era)
+//! and `std::future::Future` / futures 0.3 (used by async/await libraries).
+//!
+//! Unlike the general-purpose shims of `futures::compat`, these don't require `Send` or `Unpin`,
+//! since futures executed on Gtk+ main thread usually capture widgets.
+//!
+//! ```rust
+//! use gtk_future_executor::compat::IntoFuture01;
+//!
+//! gtk_executor.spawn(async move {
+//!     let feed = client.fetch(&url).await?;
+//!     view.show(&feed);
+//!     Ok(())
+//! }.into_future01().map_err(|error: FetchError| eprintln!("{}", error)));
+//! ```
+
+use futures03::compat::Compat;
+use futures03::compat::Compat01As03;
+use futures03::future::FutureExt;
+use std::pin::Pin;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+
+/// Converts a futures 0.1 future into a `std::future::Future` that can be `.await`ed
+pub trait IntoFuture03: futures::Future + Sized {
+    /// Wraps the future; it resolves with `Ok(item)` or `Err(error)`
+    fn into_future03(self) -> Compat01As03<Self> {
+        Compat01As03::new(self)
+    }
+}
+
+impl<F: futures::Future> IntoFuture03 for F {}
+
+/// Converts a `std::future::Future` that resolves with `Result` into a futures 0.1 future
+/// that can be spawned on `GtkEventLoopAsyncExecutor` or combined with this crate's helpers
+pub trait IntoFuture01<T, E>: std::future::Future<Output=Result<T, E>> + Sized + 'static {
+    /// Pins the future on the heap and wraps it
+    fn into_future01(self) -> Compat<Pin<Box<Self>>> {
+        Compat::new(Box::pin(self))
+    }
+}
+
+impl<F, T, E> IntoFuture01<T, E> for F where F: std::future::Future<Output=Result<T, E>> + 'static {}
+
+/// Executes an async block (or any `std::future::Future`) on Gtk+ main thread
+pub fn spawn_async<F: std::future::Future<Output=()> + 'static>(executor: &GtkEventLoopAsyncExecutor, f: F) {
+    executor.spawn(f.unit_error().into_future01());
+}
@@ -0,0 +1,80 @@
+use futures::prelude::*;
+
+use crate::executor::note_forced_yield;
+
+/// Stream returned by `cooperative()`
+#[must_use = "streams do nothing unless polled"]
+pub struct Cooperative<S> {
+    inner: S,
+    ops_per_slice: usize,
+    /// Items since the task last yielded
+    ops: usize,
+}
+
+/// Wraps a stream so that it yields back to Gtk+ main loop after every `ops_per_slice` items.
+///
+/// A task is polled until it returns `NotReady`, so a stream whose items are always ready (e.g. a channel
+/// with a huge backlog) consumed with `for_each()` runs until the stream is exhausted, and the window
+/// is not redrawn meanwhile. The wrapper returns `NotReady` (and wakes the task right away) once the budget
+/// of the slice is spent, which lets the main loop handle pending events before the task continues.
+/// The budget is refilled every time the task yields, either forced or because the inner stream is not ready.
+/// Forced yields are not taken for a busy loop, so the task keeps its priority however long the backlog is.
+///
+/// Only streams can be wrapped: a future is polled again only after it returned `NotReady`,
+/// so there is no run of polls within one task poll that could be split.
+///
+/// Panics if `ops_per_slice` is 0.
+///
+/// Example:
+/// ```rust
+/// gtk_executor.spawn(
+///     cooperative(rows_rx, 100).for_each(move |row| {
+///         update_row(&store, &row);
+///         Ok(())
+///     })
+/// );
+/// ```
+pub fn cooperative<S: Stream>(inner: S, ops_per_slice: usize) -> Cooperative<S> {
+    assert!(ops_per_slice > 0, "cooperative(): ops_per_slice must be positive");
+    Cooperative {
+        inner,
+        ops_per_slice,
+        ops: 0,
+    }
+}
+
+impl<S> Cooperative<S> {
+    /// Returns `true` and schedules the current task to be polled again if the budget of the slice is spent
+    fn should_yield(&mut self) -> bool {
+        if self.ops < self.ops_per_slice {
+            return false;
+        }
+        self.ops = 0;
+        note_forced_yield();
+        futures::task::current().notify();
+        true
+    }
+
+    /// Returns the wrapped stream
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Stream> Stream for Cooperative<S> {
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        if self.should_yield() {
+            return Ok(Async::NotReady);
+        }
+        let result = self.inner.poll();
+        match result {
+            Ok(Async::NotReady) => self.ops = 0,
+            Ok(Async::Ready(Some(_))) | Err(_) => self.ops += 1,
+            Ok(Async::Ready(None)) => {},
+        }
+        result
+    }
+}
@@ -0,0 +1,221 @@
+use futures::prelude::*;
+use glib::translate::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::c_char;
+use std::ptr;
+use std::rc::Rc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+
+/// D-Bus error that is sent as the reply to a failed method call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DBusMethodError {
+    /// D-Bus error name, e.g. `org.freedesktop.DBus.Error.InvalidArgs`
+    pub name: String,
+    /// Human-readable message
+    pub message: String,
+}
+
+impl DBusMethodError {
+    /// Constructs an error with specified D-Bus error name and message
+    pub fn new(name: &str, message: &str) -> Self {
+        DBusMethodError {
+            name: name.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Constructs an `org.freedesktop.DBus.Error.Failed` error
+    pub fn failed(message: &str) -> Self {
+        DBusMethodError::new("org.freedesktop.DBus.Error.Failed", message)
+    }
+}
+
+impl fmt::Display for DBusMethodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+impl From<glib::Error> for DBusMethodError {
+    fn from(error: glib::Error) -> Self {
+        DBusMethodError::failed(&error.to_string())
+    }
+}
+
+type MethodHandler = Rc<Fn(Option<glib::Variant>) -> Box<Future<Item=Option<glib::Variant>, Error=DBusMethodError>>>;
+
+/// Dispatches incoming D-Bus method calls to async handlers executed on Gtk+ main thread.
+///
+/// A handler receives the call parameters and returns a future; the reply is sent when the future resolves
+/// (its item is the return value, `None` for methods without return values), or an error reply if it fails.
+/// While the future is pending, the main loop keeps running, so handlers may show windows or await other tasks.
+///
+/// The service receives calls once it is registered on a connection with `register_object()`;
+/// incoming calls may also be passed to `dispatch()` directly.
+///
+/// `DBusService` is cloneable (all clones refer to the same service) and may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let service = DBusService::new(&gtk_executor);
+/// service.add_method("org.example.App", "OpenFile", move |parameters| {
+///     let path: String = parameters.and_then(|p| p.get_child_value(0).get_str().map(String::from)).unwrap_or_default();
+///     open_file(&path).map(|_| None).map_err(DBusMethodError::from)
+/// });
+/// let _registration = service.register_object(&connection, "/org/example/App", r#"
+///     <node>
+///       <interface name="org.example.App">
+///         <method name="OpenFile"><arg type="s" name="path" direction="in"/></method>
+///       </interface>
+///     </node>
+/// "#)?;
+/// ```
+#[derive(Clone)]
+pub struct DBusService {
+    executor: GtkEventLoopAsyncExecutor,
+    handlers: Rc<RefCell<HashMap<(String, String), MethodHandler>>>,
+}
+
+impl DBusService {
+    /// Constructs a service with no methods whose handlers are executed on `executor`
+    pub fn new(executor: &GtkEventLoopAsyncExecutor) -> Self {
+        DBusService {
+            executor: executor.clone(),
+            handlers: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Registers the handler of `method` of `interface`, replacing the previous one
+    pub fn add_method<F, R>(&self, interface: &str, method: &str, handler: F)
+        where F: Fn(Option<glib::Variant>) -> R + 'static,
+              R: IntoFuture<Item=Option<glib::Variant>, Error=DBusMethodError> + 'static,
+              R::Future: 'static
+    {
+        let handler: MethodHandler = Rc::new(move |parameters| Box::new(handler(parameters).into_future()));
+        self.handlers.borrow_mut().insert((interface.to_string(), method.to_string()), handler);
+    }
+
+    /// Exports the service on `connection` at `object_path` with every interface described by `introspection_xml`
+    /// (D-Bus introspection data, a `<node>` element); incoming calls are dispatched with `dispatch()`.
+    /// The object stays registered until the returned registration is dropped.
+    pub fn register_object(&self, connection: &gio::DBusConnection, object_path: &str, introspection_xml: &str)
+        -> Result<DBusRegistration, glib::Error>
+    {
+        let mut registration = DBusRegistration {
+            connection: connection.clone(),
+            ids: Vec::new(),
+        };
+
+        let xml = CString::new(introspection_xml).expect("introspection XML contains a NUL byte");
+        let object_path = CString::new(object_path).expect("object path contains a NUL byte");
+        unsafe {
+            let mut error = ptr::null_mut();
+            let node = gio_sys::g_dbus_node_info_new_for_xml(xml.as_ptr(), &mut error);
+            if node.is_null() {
+                return Err(from_glib_full(error));
+            }
+
+            let vtable = gio_sys::GDBusInterfaceVTable {
+                method_call: Some(method_call_trampoline),
+                get_property: None,
+                set_property: None,
+                padding: [ptr::null_mut(); 8],
+            };
+
+            // Registration copies the vtable; dropping `registration` on failure unregisters the interfaces so far
+            let mut interface = (*node).interfaces;
+            while !interface.is_null() && !(*interface).is_null() {
+                let user_data = Box::into_raw(Box::new(self.clone()));
+                let id = gio_sys::g_dbus_connection_register_object(
+                    connection.to_glib_none().0,
+                    object_path.as_ptr(),
+                    *interface,
+                    &vtable,
+                    user_data as glib_sys::gpointer,
+                    Some(free_service),
+                    &mut error
+                );
+                if id == 0 {
+                    // A failed registration does not take ownership of the user data
+                    free_service(user_data as glib_sys::gpointer);
+                    gio_sys::g_dbus_node_info_unref(node);
+                    return Err(from_glib_full(error));
+                }
+                registration.ids.push(id);
+                interface = interface.offset(1);
+            }
+
+            gio_sys::g_dbus_node_info_unref(node);
+            Ok(registration)
+        }
+    }
+
+    /// Executes the handler of the called method and replies to `invocation` when it completes.
+    /// Calls of unknown methods are replied with `org.freedesktop.DBus.Error.UnknownMethod`.
+    pub fn dispatch(&self, invocation: gio::DBusMethodInvocation) {
+        let interface = invocation.get_interface_name().map(|s| s.to_string()).unwrap_or_default();
+        let method = invocation.get_method_name().map(|s| s.to_string()).unwrap_or_default();
+
+        let handler = self.handlers.borrow().get(&(interface.clone(), method.clone())).cloned();
+        let handler = match handler {
+            Some(handler) => handler,
+            None => {
+                invocation.return_dbus_error(
+                    "org.freedesktop.DBus.Error.UnknownMethod",
+                    &format!("No such method: {}.{}", interface, method)
+                );
+                return;
+            }
+        };
+
+        let reply = handler(invocation.get_parameters());
+        self.executor.spawn_named(&format!("D-Bus {}.{}", interface, method), reply.then(move |result| {
+            match result {
+                Ok(value) => invocation.return_value(value.as_ref()),
+                Err(error) => invocation.return_dbus_error(&error.name, &error.message),
+            }
+            Ok(())
+        }));
+    }
+}
+
+/// Registration of a `DBusService` on a connection made by `DBusService::register_object`.
+/// Dropping it unregisters the object.
+#[must_use = "the object is unregistered when the registration is dropped"]
+pub struct DBusRegistration {
+    connection: gio::DBusConnection,
+    ids: Vec<u32>,
+}
+
+impl Drop for DBusRegistration {
+    fn drop(&mut self) {
+        for &id in &self.ids {
+            unsafe {
+                gio_sys::g_dbus_connection_unregister_object(self.connection.to_glib_none().0, id);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn method_call_trampoline(
+    _connection: *mut gio_sys::GDBusConnection,
+    _sender: *const c_char,
+    _object_path: *const c_char,
+    _interface_name: *const c_char,
+    _method_name: *const c_char,
+    _parameters: *mut glib_sys::GVariant,
+    invocation: *mut gio_sys::GDBusMethodInvocation,
+    user_data: glib_sys::gpointer,
+) {
+    let service = &*(user_data as *const DBusService);
+    // The handler owns the invocation
+    service.dispatch(from_glib_full(invocation));
+}
+
+unsafe extern "C" fn free_service(user_data: glib_sys::gpointer) {
+    drop(Box::from_raw(user_data as *mut DBusService));
+}
@@ -0,0 +1,107 @@
+use futures::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::promise::Promise;
+
+/// Shows `dialog` and returns a future that resolves with the response chosen by the user.
+/// The dialog is hidden after the response (it is not destroyed, so it may be shown again).
+///
+/// This is the async replacement for `gtk::Dialog::run`, which blocks in a nested main loop.
+///
+/// Example:
+/// ```rust
+/// choose_future(&confirm_dialog).map(move |response| {
+///     if response == gtk::ResponseType::Accept {
+///         document.discard_changes();
+///     }
+/// })
+/// ```
+pub fn choose_future<D: IsA<gtk::Dialog>>(dialog: &D) -> impl Future<Item=gtk::ResponseType, Error=()> {
+    let promise = Promise::new();
+
+    {
+        // The handler disconnects itself after the first response, so that reusing the dialog doesn't accumulate handlers
+        let handler_id = Rc::new(RefCell::new(None));
+        let pending = RefCell::new(Some(promise.clone()));
+        let handler = {
+            let handler_id = handler_id.clone();
+            dialog.connect_response(move |dialog, response| {
+                if let Some(id) = handler_id.borrow_mut().take() {
+                    dialog.disconnect(id);
+                }
+                if let Some(promise) = pending.borrow_mut().take() {
+                    dialog.hide();
+                    promise.resolve(response);
+                }
+            })
+        };
+        *handler_id.borrow_mut() = Some(handler);
+    }
+
+    dialog.show_all();
+
+    promise
+}
+
+/// Shows a modal message dialog over `parent` with `text` and one button per `(label, response)` pair,
+/// and returns a future that resolves with the chosen response. The dialog is destroyed afterwards.
+/// Closing the dialog resolves with `gtk::ResponseType::DeleteEvent`.
+pub fn message_choice<W: IsA<gtk::Window>>(parent: Option<&W>, message_type: gtk::MessageType, text: &str, responses: &[(&str, gtk::ResponseType)])
+    -> impl Future<Item=gtk::ResponseType, Error=()>
+{
+    let dialog = gtk::MessageDialog::new(
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        message_type,
+        gtk::ButtonsType::None,
+        text
+    );
+    for &(label, response) in responses {
+        dialog.add_button(label, response);
+    }
+
+    choose_future(&dialog).map(move |response| {
+        dialog.destroy();
+        response
+    })
+}
+
+/// Shows a modal color chooser dialog over `parent` with `initial` selected, and returns a future
+/// that resolves with the chosen color, or `None` if the user cancelled. The dialog is destroyed afterwards.
+///
+/// Example:
+/// ```rust
+/// choose_color(Some(&window), &style.background()).map(move |color| {
+///     if let Some(color) = color {
+///         style.set_background(color);
+///     }
+/// })
+/// ```
+pub fn choose_color<W: IsA<gtk::Window>>(parent: Option<&W>, initial: &gdk::RGBA) -> impl Future<Item=Option<gdk::RGBA>, Error=()> {
+    let dialog = gtk::ColorChooserDialog::new(None, parent);
+    dialog.set_modal(true);
+    dialog.set_rgba(initial);
+
+    choose_future(&dialog).map(move |response| {
+        let color = if response == gtk::ResponseType::Ok { Some(dialog.get_rgba()) } else { None };
+        dialog.destroy();
+        color
+    })
+}
+
+/// Shows a modal font chooser dialog over `parent` with `initial` selected (a Pango font description, e.g. "Sans 12"),
+/// and returns a future that resolves with the description of the chosen font, or `None` if the user cancelled.
+/// The dialog is destroyed afterwards.
+pub fn choose_font<W: IsA<gtk::Window>>(parent: Option<&W>, initial: &str) -> impl Future<Item=Option<String>, Error=()> {
+    let dialog = gtk::FontChooserDialog::new(None, parent);
+    dialog.set_modal(true);
+    dialog.set_font(initial);
+
+    choose_future(&dialog).map(move |response| {
+        let font = if response == gtk::ResponseType::Ok { dialog.get_font().map(|font| font.to_string()) } else { None };
+        dialog.destroy();
+        font
+    })
+}
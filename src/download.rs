@@ -0,0 +1,88 @@
+//! Simple downloads of URIs (e.g. `https://`, `sftp://`, `file://`) through GIO, driven by the glib main loop,
+//! without embedding tokio and hyper.
+//!
+//! This is not an HTTP client: downloads go through GIO's URI support (`gio::File::new_for_uri`), which is implemented
+//! by GVfs (installed by default on GNOME desktops). Without GVfs, downloads of remote URIs fail
+//! with `gio::IOErrorEnum::NotSupported`. Only the body is available: there is no access to status codes or headers,
+//! and HTTP errors surface as I/O errors. Applications that need those should use an HTTP client library.
+//! Returned futures and streams must be executed on Gtk+ main thread.
+//!
+//! Example:
+//! ```rust
+//! gtk_executor.spawn(
+//!     download::open("https://example.org/feed.xml")
+//!         .and_then(|response| response.bytes())
+//!         .map(move |body| show_feed(&view, &body))
+//!         .map_err(|error| eprintln!("Download failed: {}", error))
+//! );
+//! ```
+
+use futures::prelude::*;
+use gio::prelude::*;
+
+use crate::promise::Promise;
+use crate::text::read_chunk;
+use crate::text::DEFAULT_CHUNK_SIZE;
+
+/// A download that has been opened successfully
+pub struct Download {
+    stream: gio::InputStream,
+}
+
+impl Download {
+    /// Returns the body as a stream of chunks of at most `chunk_size` bytes
+    pub fn body(self, chunk_size: usize) -> impl Stream<Item=glib::Bytes, Error=glib::Error> {
+        let stream = self.stream;
+        let mut pending: Option<Promise<glib::Bytes, glib::Error>> = None;
+        let mut done = false;
+
+        futures::stream::poll_fn(move || {
+            if done {
+                return Ok(Async::Ready(None));
+            }
+
+            let mut read = pending.take().unwrap_or_else(|| read_chunk(&stream, chunk_size, None));
+            match read.poll() {
+                Ok(Async::NotReady) => {
+                    pending = Some(read);
+                    Ok(Async::NotReady)
+                },
+                Ok(Async::Ready(ref bytes)) if bytes.is_empty() => {
+                    done = true;
+                    Ok(Async::Ready(None))
+                },
+                Ok(Async::Ready(bytes)) => Ok(Async::Ready(Some(bytes))),
+                Err(error) => {
+                    done = true;
+                    Err(error)
+                },
+            }
+        })
+    }
+
+    /// Returns a future that resolves with the whole body
+    pub fn bytes(self) -> impl Future<Item=Vec<u8>, Error=glib::Error> {
+        self.body(DEFAULT_CHUNK_SIZE).fold(Vec::new(), |mut body, chunk| {
+            body.extend_from_slice(&chunk);
+            Ok::<Vec<u8>, glib::Error>(body)
+        })
+    }
+}
+
+/// Starts downloading `uri`. Returned future resolves once the resource is opened (for HTTP, once the response
+/// headers are received) and fails if it can't be opened.
+pub fn open(uri: &str) -> impl Future<Item=Download, Error=glib::Error> {
+    let opened = Promise::new();
+
+    {
+        let opened = opened.clone();
+        gio::File::new_for_uri(uri).read_async(glib::PRIORITY_DEFAULT, None::<&gio::Cancellable>, move |result| {
+            match result {
+                Ok(stream) => opened.resolve(Download { stream: stream.upcast() }),
+                Err(error) => opened.reject(error),
+            }
+        });
+    }
+
+    opened
+}
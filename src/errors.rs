@@ -0,0 +1,258 @@
+use gtk::prelude::*;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::logging;
+
+/// Error of a task future: the error of the spawned future rendered for `TaskError`,
+/// or `None` if the future's error carries no information (e.g. futures spawned with `spawn()`)
+pub(crate) type TaskFailure = Option<ErrorText>;
+
+/// An error rendered with `Display` and `Debug`, with its chain of causes if the error type has one
+pub(crate) struct ErrorText {
+    pub display: String,
+    pub debug: String,
+    pub causes: Vec<String>,
+}
+
+impl ErrorText {
+    pub(crate) fn new<E: fmt::Display + fmt::Debug>(error: &E) -> Self {
+        ErrorText {
+            display: error.to_string(),
+            debug: format!("{:?}", error),
+            causes: Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "anyhow")]
+    pub(crate) fn from_anyhow(error: &anyhow::Error) -> Self {
+        ErrorText {
+            display: error.to_string(),
+            debug: format!("{:?}", error),
+            causes: error.chain().skip(1).map(|cause| cause.to_string()).collect(),
+        }
+    }
+
+    #[cfg(feature = "failure")]
+    pub(crate) fn from_failure(error: &failure::Error) -> Self {
+        ErrorText {
+            display: error.to_string(),
+            debug: format!("{:?}", error),
+            causes: error.iter_causes().map(|cause| cause.to_string()).collect(),
+        }
+    }
+}
+
+/// Description of a failed task that is passed to the task error policy.
+///
+/// `Display` renders a one-line summary including the causes of the error; the alternate form (`{:#}`) renders a multi-line report
+/// with all available context, suitable for logs and bug reports.
+#[derive(Debug, Clone)]
+pub struct TaskError {
+    /// Id of the failed task
+    pub id: usize,
+    /// Name of the task if it was spawned with a name
+    pub name: Option<String>,
+    /// Error message produced by the task (the error rendered with `Display`), if available
+    pub message: Option<String>,
+    /// The error rendered with `Debug`, if available
+    pub debug: Option<String>,
+    /// Messages of the causes of the error, from the outermost one; available for errors of tasks spawned
+    /// with `spawn_anyhow()` (feature `anyhow`) or `spawn_failure()` (feature `failure`)
+    pub causes: Vec<String>,
+    /// Time between spawning the task and its failure
+    pub elapsed: Duration,
+    /// Backtrace of the `spawn()` call; captured only with feature `spawn-backtrace`
+    pub spawn_backtrace: Option<String>,
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "Task {} ({}) failed", self.id, name)?,
+            None => write!(f, "Task {} failed", self.id)?,
+        }
+        if let Some(ref message) = self.message {
+            write!(f, ": {}", message)?;
+        }
+        for cause in &self.causes {
+            write!(f, ": {}", cause)?;
+        }
+        if !f.alternate() {
+            return Ok(());
+        }
+
+        write!(f, "\n  elapsed: {:?}", self.elapsed)?;
+        if let Some(ref debug) = self.debug {
+            write!(f, "\n  error: {}", debug)?;
+        }
+        if let Some(ref backtrace) = self.spawn_backtrace {
+            write!(f, "\n  spawned at:\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Determines how errors of spawned tasks are surfaced to the user.
+/// Set with `GtkEventLoopAsyncExecutor::set_task_error_policy`.
+pub enum TaskErrorPolicy {
+    /// Show a modal error dialog over the specified window
+    ShowDialog(gtk::Window),
+    /// Show the error in the info bar, using the label to display the message
+    InfoBar(gtk::InfoBar, gtk::Label),
+    /// Print the error report (see `TaskError`) to stderr or the GLib log (see `set_glib_logging`); the default
+    LogOnly,
+    /// Pass the error to the callback
+    Custom(Box<Fn(&TaskError)>),
+}
+
+impl Default for TaskErrorPolicy {
+    fn default() -> Self {
+        TaskErrorPolicy::LogOnly
+    }
+}
+
+impl TaskErrorPolicy {
+    pub(crate) fn report(&self, error: &TaskError) {
+        match *self {
+            TaskErrorPolicy::ShowDialog(ref parent) => {
+                let dialog = gtk::MessageDialog::new(
+                    Some(parent),
+                    gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+                    gtk::MessageType::Error,
+                    gtk::ButtonsType::Close,
+                    &error.to_string()
+                );
+                dialog.connect_response(|dialog, _| dialog.destroy());
+                dialog.show_all();
+            },
+            TaskErrorPolicy::InfoBar(ref bar, ref label) => {
+                label.set_text(&error.to_string());
+                bar.set_message_type(gtk::MessageType::Error);
+                bar.show_all();
+            },
+            TaskErrorPolicy::LogOnly => {
+                logging::warning(&format!("{:#}", error));
+            },
+            TaskErrorPolicy::Custom(ref handler) => {
+                handler(error);
+            },
+        }
+    }
+}
+
+/// Errors shown in an info bar by `bind_errors_to_infobar`: distinct messages with the number of occurrences,
+/// the first one is displayed
+struct InfoBarErrors {
+    bar: gtk::InfoBar,
+    label: gtk::Label,
+    messages: Vec<(String, usize)>,
+}
+
+impl InfoBarErrors {
+    fn add(&mut self, message: String) {
+        match self.messages.iter_mut().find(|(queued, _)| *queued == message) {
+            Some((_, count)) => *count += 1,
+            None => self.messages.push((message, 1)),
+        }
+        self.update();
+    }
+
+    fn dismiss(&mut self) {
+        if !self.messages.is_empty() {
+            self.messages.remove(0);
+        }
+        self.update();
+    }
+
+    fn update(&self) {
+        let (message, count) = match self.messages.first() {
+            Some(first) => first,
+            None => {
+                self.bar.hide();
+                return;
+            },
+        };
+
+        let mut text = message.clone();
+        if *count > 1 {
+            text.push_str(&format!(" ({} times)", count));
+        }
+        if self.messages.len() > 1 {
+            text.push_str(&format!(" (+{} more)", self.messages.len() - 1));
+        }
+        self.label.set_text(&text);
+        self.bar.set_message_type(gtk::MessageType::Error);
+        self.bar.show_all();
+    }
+}
+
+/// Shows errors of tasks of `executor` in `bar`, replacing the task error policy.
+///
+/// The message of the first error is displayed in `label` until the user dismisses it with the close button
+/// of the info bar; then the next error is displayed, and the bar is hidden once all errors are dismissed.
+/// Repeated identical errors are coalesced into one message with the number of occurrences.
+/// Tasks with their own error handler (`TaskBuilder::on_error`) are not affected.
+///
+/// Example:
+/// ```rust
+/// let error_bar = gtk::InfoBar::new();
+/// let error_label = gtk::Label::new(None);
+/// error_bar.get_content_area().unwrap().add(&error_label);
+/// bind_errors_to_infobar(&gtk_executor, &error_bar, &error_label);
+/// ```
+pub fn bind_errors_to_infobar(executor: &GtkEventLoopAsyncExecutor, bar: &gtk::InfoBar, label: &gtk::Label) {
+    let errors = Rc::new(RefCell::new(
+        InfoBarErrors {
+            bar: bar.clone(),
+            label: label.clone(),
+            messages: Vec::new(),
+        }
+    ));
+
+    bar.set_show_close_button(true);
+    {
+        let errors = Rc::downgrade(&errors);
+        bar.connect_response(move |_, _| {
+            if let Some(errors) = errors.upgrade() {
+                errors.borrow_mut().dismiss();
+            }
+        });
+    }
+    bar.hide();
+
+    executor.set_task_error_policy(TaskErrorPolicy::Custom(Box::new(move |error| {
+        errors.borrow_mut().add(error.to_string());
+    })));
+}
+
+/// Determines what happens when a task panics while it is polled. Set per task with `TaskBuilder::on_panic`.
+///
+/// Either way the task is removed from the executor before the panic is handled,
+/// so the executor stays consistent. See also `TaskBuilder::spawn_restartable` for restarting panicked tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Resume the panic on Gtk+ main thread (the default)
+    Rethrow,
+    /// Swallow the panic and report it like a task failure (to the task's error handler or the task error policy);
+    /// `TaskError::message` is the panic message
+    Report,
+}
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        PanicPolicy::Rethrow
+    }
+}
+
+/// Returns the message of a panic payload if the panic was raised with a message
+pub(crate) fn panic_message(payload: &(Any + Send)) -> Option<&str> {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => Some(message),
+        None => payload.downcast_ref::<String>().map(|message| message.as_str()),
+    }
+}
@@ -1,15 +1,457 @@
+use crossbeam_queue::SegQueue;
 use futures::prelude::*;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
+use std::any::Any;
+use std::cell::Cell;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::rc::Weak;
+use std::time::Duration;
+use std::time::Instant;
 
-type BoxUnitFuture = Box<Future<Item=(), Error=()>>;
+use crate::errors::panic_message;
+use crate::errors::ErrorText;
+use crate::errors::PanicPolicy;
+use crate::errors::TaskError;
+use crate::errors::TaskFailure;
+use crate::errors::TaskErrorPolicy;
+use crate::instrumentation::PollStats;
+use crate::logging;
+use crate::instrumentation::PollTiming;
+use crate::instrumentation::TaskInstrumentation;
+use crate::task::Priority;
+use crate::task::SharedHandle;
+use crate::task::TaskBuilder;
+use crate::task::TaskHandle;
+use crate::task::TaskOptions;
+use crate::task::TimeLimited;
+#[cfg(feature = "task-arena")]
+use crate::task_slot::TaskArena;
+use crate::task_slot::TaskSlot;
+use crate::task_table::TaskTable;
+use crate::timer::duration_to_ms;
+use crate::windows::WindowManager;
 
+
+/// Lifecycle event of a task spawned on `GtkEventLoopAsyncExecutor`.
+/// Observers registered with `GtkEventLoopAsyncExecutor::add_task_observer` receive these events.
+/// The id of a task is reused for tasks spawned after it has finished, failed or was cancelled.
+#[derive(Debug, Clone, Copy)]
+pub enum TaskEvent<'a> {
+    /// Task was spawned
+    Started { id: usize, name: Option<&'a str> },
+    /// Task completed successfully
+    Finished { id: usize, name: Option<&'a str> },
+    /// Task returned error
+    Failed { id: usize, name: Option<&'a str> },
+    /// Task was dropped before completion (e.g. by shutdown)
+    Cancelled { id: usize, name: Option<&'a str> },
+}
+
+impl<'a> std::fmt::Display for TaskEvent<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (id, name, event) = match *self {
+            TaskEvent::Started { id, name } => (id, name, "started"),
+            TaskEvent::Finished { id, name } => (id, name, "finished"),
+            TaskEvent::Failed { id, name } => (id, name, "failed"),
+            TaskEvent::Cancelled { id, name } => (id, name, "cancelled"),
+        };
+        match name {
+            Some(name) => write!(f, "Task {} ({}) {}", id, name, event),
+            None => write!(f, "Task {} {}", id, event),
+        }
+    }
+}
+
+/// Error returned by `GtkEventLoopAsyncExecutor::run_until` when the future did not resolve
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunUntilError {
+    /// No more events could be dispatched without blocking, but the future is still pending
+    Stalled,
+    /// The future is still pending after the maximum number of iterations
+    IterationLimitReached,
+}
+
+impl std::fmt::Display for RunUntilError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            RunUntilError::Stalled => write!(f, "future stalled: no more events to dispatch"),
+            RunUntilError::IterationLimitReached => write!(f, "future did not resolve within the iteration limit"),
+        }
+    }
+}
+
+impl std::error::Error for RunUntilError {}
+
+/// Error returned by `GtkEventLoopAsyncExecutor::try_spawn` when the future could not be spawned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// The executor was used on a thread other than the one that created it
+    WrongThread { thread: std::thread::ThreadId, owner: std::thread::ThreadId },
+    /// The executor is shutting down and does not accept new tasks
+    ShuttingDown,
+    /// The number of tasks reached the limit set with `GtkEventLoopAsyncExecutor::set_max_tasks`
+    AtCapacity,
+}
+
+impl std::fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            SpawnError::WrongThread { thread, owner } =>
+                write!(f, "GtkEventLoopAsyncExecutor used on thread {:?}, but it belongs to thread {:?}", thread, owner),
+            SpawnError::ShuttingDown => write!(f, "executor is shutting down"),
+            SpawnError::AtCapacity => write!(f, "executor has reached its task limit"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+/// Outcome of `GtkEventLoopAsyncExecutor::shutdown_with_timeout`
+#[derive(Debug, Clone)]
+pub struct ShutdownReport {
+    /// Tasks that did not finish before the deadline and were cancelled: id and name (if any)
+    pub cancelled_tasks: Vec<(usize, Option<String>)>,
+}
+
+struct ShutdownState {
+    /// Tasks that await the shutdown and therefore are not waited for
+    exempt: HashSet<usize>,
+    report: Option<ShutdownReport>,
+    waiting_tasks: Vec<futures::task::Task>,
+}
+
+/// A spawned future; its error is reported in `TaskError::message` and `TaskError::debug`
+struct Task {
+    spawn: futures::executor::Spawn<TaskSlot>,
+    /// Distinguishes this task from other tasks that have used (or will use) the same id
+    generation: u64,
+    name: Option<String>,
+    priority: Priority,
+    on_error: Option<Rc<Fn(&TaskError)>>,
+    panic_policy: PanicPolicy,
+    spawned_at: Instant,
+    /// Call stack of `spawn()`, resolved only if the task fails
+    #[cfg(feature = "spawn-backtrace")]
+    spawn_backtrace: backtrace::Backtrace,
+    /// Number of consecutive polls that returned `NotReady` after the task woke itself during the poll
+    self_wakes: u32,
+    /// Priority the task had before it was demoted as a busy loop
+    demoted_from: Option<Priority>,
+    /// Time spent polling the task so far
+    poll_stats: PollStats,
+}
+
+/// An operation on a task that can't be applied while the task is being polled
+enum PendingOp {
+    Cancel { id: usize, generation: u64 },
+}
+
+/// Executor state. Borrows of its cells are never held while user code (futures, observers, hooks,
+/// error handlers, destructors of futures) runs, so that user code may reentrantly spawn, cancel and inspect tasks.
 struct GtkEventLoopAsyncExecutorBackend {
-    next_id: AtomicUsize,
-    spawns: RefCell<HashMap<usize, futures::executor::Spawn<BoxUnitFuture>>>,
+    /// Key of the executor in the `EXECUTORS` registry of the owner thread
+    executor_id: usize,
+    /// Main context on which tasks are polled; the default one for Gtk+ main loop
+    context: glib::MainContext,
+    wake_queue: Arc<WakeQueue>,
+    /// Cleared when the executor starts shutting down or is dropped; read by `RemoteSpawner` on other threads
+    accepting_remote: Arc<AtomicBool>,
+    /// Thread that created the executor; the only thread that may touch the backend
+    owner_thread: std::thread::ThreadId,
+    next_generation: Cell<u64>,
+    spawns: RefCell<TaskTable<Task>>,
+    /// Time spent polling tasks (including completed ones) by task name
+    poll_stats_by_name: RefCell<HashMap<Option<String>, PollStats>>,
+    /// Memory blocks for futures of tasks
+    #[cfg(feature = "task-arena")]
+    arena: Rc<TaskArena>,
+    observers: RefCell<Vec<Rc<Fn(&TaskEvent)>>>,
+    /// Set by `quit_when_idle()`; contains the window manager whose windows must be closed as well
+    quit_when_idle: RefCell<Option<Option<WindowManager>>>,
+    /// Id of the task that is being polled right now
+    current_task: Cell<Option<usize>>,
+    /// Ids, generations and names of tasks whose poll is in progress (more than one when a task runs a nested main loop).
+    /// Such tasks are taken out of the task table for the duration of the poll.
+    polling: RefCell<HashMap<(usize, u64), Option<String>>>,
+    /// Tasks that were woken while their poll was in progress; they are re-woken once the poll returns
+    woken_while_polling: RefCell<HashSet<usize>>,
+    /// Number of wakes that arrived after their task had completed
+    stale_wakes: Cell<usize>,
+    /// Operations on tasks that were being polled when requested; applied once the poll returns
+    pending_ops: RefCell<Vec<PendingOp>>,
+    shutdown: RefCell<Option<ShutdownState>>,
+    error_policy: RefCell<Rc<TaskErrorPolicy>>,
+    /// Senders of streams returned by `errors()`
+    error_subscribers: RefCell<Vec<futures::sync::mpsc::UnboundedSender<TaskError>>>,
+    instrumentation: RefCell<Option<Rc<TaskInstrumentation>>>,
+    max_tasks: Cell<Option<usize>>,
+    /// Tasks waiting in `spawn_when_ready()` for the number of tasks to drop below `max_tasks`
+    capacity_waiters: RefCell<Vec<futures::task::Task>>,
+    /// Set on the first dispatch of the main context
+    loop_running: Cell<bool>,
+    /// Set while a newly spawned task is polled inside `spawn()`
+    polling_spawned: Cell<bool>,
+    /// Number of nested dispatches of the executor's sources that are running right now
+    dispatch_depth: Cell<usize>,
+    loop_start_hooks: RefCell<Vec<Box<FnOnce()>>>,
+    /// First polls of tasks spawned before the main loop started: id, generation and priority
+    deferred_wakes: RefCell<Vec<(usize, u64, Priority)>>,
+    /// Gtk+ was not initialized when the executor was created, so helpers that need Gtk+ are disabled
+    headless: bool,
+}
+
+/// Task storage is not shrunk below this capacity
+const MIN_TASK_CAPACITY: usize = 64;
+
+/// Number of consecutive self-wakes after which a task is considered a busy loop
+const BUSY_LOOP_THRESHOLD: u32 = 1000;
+
+/// Default time after which woken tasks of low priorities are polled even if the main loop is busy
+const DEFAULT_AGING_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A handle for spawning futures on `GtkEventLoopAsyncExecutor` from any thread, created by `GtkEventLoopAsyncExecutor::remote()`.
+///
+/// `RemoteSpawner` is cloneable, `Send` and `Sync`. Spawned futures must be `Send`: they are moved to Gtk+ main thread
+/// and spawned there on the next main loop iteration. Futures that are sent after the executor is dropped are dropped.
+#[derive(Clone)]
+pub struct RemoteSpawner {
+    executor_id: usize,
+    context: glib::MainContext,
+    accepting: Arc<AtomicBool>,
+    owner_thread: std::thread::ThreadId,
+}
+
+impl RemoteSpawner {
+    /// Sends `f` to Gtk+ main thread and executes it there.
+    /// Fails with `SpawnError::ShuttingDown` if the executor is shutting down or was dropped.
+    pub fn spawn<F: Future<Item=(), Error=()> + Send + 'static>(&self, f: F) -> Result<(), SpawnError> {
+        self.status()?;
+
+        let executor_id = self.executor_id;
+        let mut f = Some(f);
+        let source = glib::source::idle_source_new(None, glib::PRIORITY_DEFAULT_IDLE, move || {
+            if let (Some(executor), Some(f)) = (GtkEventLoopAsyncExecutor::lookup(executor_id), f.take()) {
+                executor.spawn_task(TaskOptions::default(), f.map_err(|()| None));
+            }
+            glib::source::Continue(false)
+        });
+        source.attach(Some(&self.context));
+
+        Ok(())
+    }
+
+    /// Returns the thread of the executor
+    pub(crate) fn owner_thread(&self) -> std::thread::ThreadId {
+        self.owner_thread
+    }
+
+    /// Returns `Ok` if futures can be spawned
+    pub fn status(&self) -> Result<(), SpawnError> {
+        if self.accepting.load(Ordering::SeqCst) {
+            Ok(())
+        } else {
+            Err(SpawnError::ShuttingDown)
+        }
+    }
+}
+
+/// A task wake waiting to be dispatched
+struct Wake {
+    id: usize,
+    generation: u64,
+    woken_at: Instant,
+}
+
+/// Wakes of one priority waiting for dispatch, in wake order.
+///
+/// Any thread pushes wakes onto the lock-free queue; at most one idle source of the lane's priority is attached
+/// at a time to drain it ("armed" on the first wake after the queue was drained). A dispatch takes all queued wakes
+/// and disarms the lane before polling them, so wakes that arrive meanwhile (also from tasks that run a nested
+/// main loop) arm a new source. The number of glib sources is thus bounded by the number of batches
+/// rather than the number of woken tasks.
+///
+/// Lanes of priorities lower than `Priority::DEFAULT` age: if the lane is still not dispatched after the aging
+/// interval (because sources of higher priority are ready all the time), a timeout source of `Priority::DEFAULT`
+/// dispatches it. The sources of an arming are destroyed when either of them dispatches the lane.
+///
+/// If the executor limits the number of polls per dispatch, wakes of a batch beyond the limit are carried over
+/// to the next dispatch of the lane, which is armed right away, so that events of higher priority (e.g. redrawing)
+/// are handled in between.
+struct WakeLane {
+    executor_id: usize,
+    context: glib::MainContext,
+    priority: Priority,
+    wakes: SegQueue<Wake>,
+    /// Wakes left over by the previous dispatch; they are dispatched before the queued ones
+    carry_over: Mutex<VecDeque<Wake>>,
+    armed: AtomicBool,
+    /// Incremented every time the lane is armed; sources of earlier armings don't dispatch
+    epoch: AtomicUsize,
+    /// Sources attached by the current arming
+    sources: Mutex<Vec<glib::Source>>,
+    settings: Arc<WakeSettings>,
+}
+
+/// Dispatch settings and counters of an executor shared by its lanes
+struct WakeSettings {
+    /// Aging interval in milliseconds; 0 disables aging
+    aging_ms: AtomicUsize,
+    /// Maximum number of wakes dispatched at once by a lane; 0 means unlimited
+    max_polls_per_dispatch: AtomicUsize,
+    /// Number of wakes carried over to a follow-up dispatch because of `max_polls_per_dispatch`
+    carried_over: AtomicUsize,
+}
+
+impl WakeLane {
+    fn push(lane: &Arc<WakeLane>, wake: Wake) {
+        lane.wakes.push(wake);
+        WakeLane::arm(lane);
+    }
+
+    fn arm(lane: &Arc<WakeLane>) {
+        if lane.armed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let epoch = lane.epoch.fetch_add(1, Ordering::SeqCst) + 1;
+
+        // Locked while the sources are attached, so that a dispatch on the owner thread finds them
+        let mut sources = lane.sources.lock().unwrap();
+
+        let dispatched = lane.clone();
+        let source = glib::source::idle_source_new(None, lane.priority.to_glib(), move || {
+            WakeLane::dispatch(&dispatched, epoch);
+            glib::source::Continue(false)
+        });
+        source.attach(Some(&lane.context));
+        sources.push(source);
+
+        let aging_ms = lane.settings.aging_ms.load(Ordering::SeqCst);
+        if aging_ms > 0 && lane.priority > Priority::DEFAULT {
+            let dispatched = lane.clone();
+            let source = glib::source::timeout_source_new(aging_ms as u32, None, Priority::DEFAULT.to_glib(), move || {
+                WakeLane::dispatch(&dispatched, epoch);
+                glib::source::Continue(false)
+            });
+            source.attach(Some(&lane.context));
+            sources.push(source);
+        }
+    }
+
+    /// Destroys the sources of the current arming, so that the one that did not dispatch doesn't linger
+    fn disarm(lane: &Arc<WakeLane>) {
+        let sources = std::mem::replace(&mut *lane.sources.lock().unwrap(), Vec::new());
+        for source in sources {
+            source.destroy();
+        }
+        lane.armed.store(false, Ordering::SeqCst);
+    }
+
+    fn dispatch(lane: &Arc<WakeLane>, epoch: usize) {
+        if !lane.armed.load(Ordering::SeqCst) || lane.epoch.load(Ordering::SeqCst) != epoch {
+            // The other source of this arming has already dispatched the lane
+            return;
+        }
+
+        // The context is iterated by the owner thread, so the executor is found in its registry.
+        // If it isn't, the executor has been dropped and the wakes are stale.
+        let executor = match GtkEventLoopAsyncExecutor::lookup(lane.executor_id) {
+            Some(executor) => executor,
+            None => {
+                lane.carry_over.lock().unwrap().clear();
+                while lane.wakes.pop().is_ok() {}
+                WakeLane::disarm(lane);
+                return;
+            },
+        };
+
+        let batch: Vec<Wake> = {
+            let mut carry_over = lane.carry_over.lock().unwrap();
+            let mut batch = Vec::with_capacity(carry_over.len() + lane.wakes.len());
+            batch.extend(carry_over.drain(..));
+            while let Ok(wake) = lane.wakes.pop() {
+                batch.push(wake);
+            }
+
+            let max_polls = lane.settings.max_polls_per_dispatch.load(Ordering::SeqCst);
+            if max_polls > 0 && batch.len() > max_polls {
+                carry_over.extend(batch.drain(max_polls..));
+                lane.settings.carried_over.fetch_add(carry_over.len(), Ordering::SeqCst);
+            }
+            batch
+        };
+
+        WakeLane::disarm(lane);
+        // Carried-over wakes need a follow-up dispatch; a wake pushed after the queue was drained
+        // may have seen the lane armed and not attached a source
+        if !lane.wakes.is_empty() || !lane.carry_over.lock().unwrap().is_empty() {
+            WakeLane::arm(lane);
+        }
+
+        let _dispatching = executor.enter_dispatch();
+        for wake in batch {
+            executor.invoke(wake.id, wake.generation, wake.woken_at);
+        }
+    }
+}
+
+/// Marks the executor as dispatching its tasks until dropped (also when a task panics through it)
+struct DispatchGuard {
+    executor: GtkEventLoopAsyncExecutor,
+}
+
+impl Drop for DispatchGuard {
+    fn drop(&mut self) {
+        let depth = &self.executor.backend.dispatch_depth;
+        depth.set(depth.get() - 1);
+    }
+}
+
+/// Wake lanes of an executor by priority
+struct WakeQueue {
+    executor_id: usize,
+    context: glib::MainContext,
+    lanes: Mutex<HashMap<Priority, Arc<WakeLane>>>,
+    settings: Arc<WakeSettings>,
+}
+
+impl WakeQueue {
+    fn new(executor_id: usize, context: glib::MainContext) -> Self {
+        WakeQueue {
+            executor_id,
+            context,
+            lanes: Mutex::new(HashMap::new()),
+            settings: Arc::new(WakeSettings {
+                aging_ms: AtomicUsize::new(duration_to_ms(DEFAULT_AGING_INTERVAL) as usize),
+                max_polls_per_dispatch: AtomicUsize::new(0),
+                carried_over: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    fn lane(&self, priority: Priority) -> Arc<WakeLane> {
+        self.lanes.lock().unwrap()
+            .entry(priority)
+            .or_insert_with(|| Arc::new(WakeLane {
+                executor_id: self.executor_id,
+                context: self.context.clone(),
+                priority,
+                wakes: SegQueue::new(),
+                carry_over: Mutex::new(VecDeque::new()),
+                armed: AtomicBool::new(false),
+                epoch: AtomicUsize::new(0),
+                sources: Mutex::new(Vec::new()),
+                settings: self.settings.clone(),
+            }))
+            .clone()
+    }
 }
 
 /// An executor that executes futures on Gtk+ main loop.
@@ -22,6 +464,9 @@ struct GtkEventLoopAsyncExecutorBackend {
 /// 
 /// GtkEventLoopAsyncExecutor ensures memory- and thread-safety by being not shareable or sendable between threads.
 /// This is a requirement for GUI code.
+///
+/// Tasks of the same priority are polled in the order they were woken: if one event wakes a "clear list" task
+/// and then an "append results" task, the list is cleared first.
 /// 
 /// Example: 
 /// ```rust
@@ -140,18 +585,56 @@ struct GtkEventLoopAsyncExecutorBackend {
 /// ```
 #[derive(Clone)]
 pub struct GtkEventLoopAsyncExecutor {
-    backend: Arc<GtkEventLoopAsyncExecutorBackend>,
+    backend: Rc<GtkEventLoopAsyncExecutorBackend>,
 }
 
+static NEXT_EXECUTOR_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Executors created on this thread, by executor id.
+    /// Wakes are delivered to the owner thread as plain ids and resolved here,
+    /// so the backend itself never crosses threads.
+    static EXECUTORS: RefCell<HashMap<usize, Weak<GtkEventLoopAsyncExecutorBackend>>> = RefCell::new(HashMap::new());
+}
+
+thread_local! {
+    /// Set when the task that is being polled yields on purpose (see `cooperative()`)
+    static FORCED_YIELD: Cell<bool> = Cell::new(false);
+}
+
+/// Marks the current poll as a forced yield: the task wakes itself to let the main loop run, which is not a busy loop
+pub(crate) fn note_forced_yield() {
+    FORCED_YIELD.with(|forced_yield| forced_yield.set(true));
+}
+
+impl Drop for GtkEventLoopAsyncExecutorBackend {
+    fn drop(&mut self) {
+        self.accepting_remote.store(false, Ordering::SeqCst);
+        let executor_id = self.executor_id;
+        let _ = EXECUTORS.try_with(|executors| {
+            executors.borrow_mut().remove(&executor_id);
+        });
+    }
+}
+
+/// The cross-thread part of the executor: it only carries ids and the wake lane of the task,
+/// which schedules `invoke()` on the main context. The executor is looked up on the owner thread when the wake is dispatched.
 #[derive(Clone)]
 struct GtkEventLoopAsyncExecutorNotifier {
-    executor: GtkEventLoopAsyncExecutor,
+    /// Lane of the priority of the task that is woken through this notifier
+    lane: Arc<WakeLane>,
+    /// Generation of the task that is woken through this notifier; wakes of other generations are stale
+    generation: u64,
+    /// Set when a wake is delivered through this notifier
+    woken: Arc<AtomicBool>,
 }
 
 impl GtkEventLoopAsyncExecutorNotifier {
-    pub fn new(executor: GtkEventLoopAsyncExecutor) -> Self {
+    pub fn new(executor: &GtkEventLoopAsyncExecutor, priority: Priority, generation: u64) -> Self {
         GtkEventLoopAsyncExecutorNotifier {
-            executor
+            lane: executor.backend.wake_queue.lane(priority),
+            generation,
+            woken: Arc::new(AtomicBool::new(false)),
         }
     }
 }
@@ -162,76 +645,1136 @@ impl GtkEventLoopAsyncExecutor {
     pub fn new() -> Self {
         assert!(gtk::is_initialized_main_thread(), "GtkEventLoopAsyncExecutor::new() may only be called on Gtk+ main thread");
 
-        GtkEventLoopAsyncExecutor {
-            backend: Arc::new(
+        GtkEventLoopAsyncExecutor::with_context(glib::MainContext::default())
+    }
+
+    /// Instantiates new executor like `new()` if Gtk+ is initialized; otherwise falls back to an executor
+    /// for the default `glib::MainContext`, which must then be iterated by the current thread (e.g. by a `glib::MainLoop`).
+    /// This lets library code that takes a `GtkEventLoopAsyncExecutor` run both in the GUI application
+    /// and in its headless companion daemon.
+    ///
+    /// Without Gtk+ the executor is headless (see `is_headless()`): helpers that need Gtk+ are disabled.
+    /// *Panics* if Gtk+ is not initialized and the default main context is owned by another thread.
+    ///
+    /// Example:
+    /// ```rust
+    /// let _ = gtk::init();
+    /// let gtk_executor = GtkEventLoopAsyncExecutor::new_or_headless();
+    /// gtk_executor.spawn(sync_library(&gtk_executor));
+    /// if gtk_executor.is_headless() {
+    ///     glib::MainLoop::new(None, false).run();
+    /// } else {
+    ///     gtk::main();
+    /// }
+    /// ```
+    pub fn new_or_headless() -> Self {
+        if gtk::is_initialized_main_thread() {
+            return GtkEventLoopAsyncExecutor::new();
+        }
+
+        let context = glib::MainContext::default();
+        assert!(context.acquire(), "GtkEventLoopAsyncExecutor::new_or_headless(): Gtk+ is not initialized and the default main context is owned by another thread");
+        GtkEventLoopAsyncExecutor::with_context(context)
+    }
+
+    /// Returns `true` if the executor was created without Gtk+ (see `new_or_headless()`).
+    /// A headless executor replaces task error policies that show widgets with `TaskErrorPolicy::LogOnly`,
+    /// and `run_main_until()` and `quit_when_idle()` panic.
+    pub fn is_headless(&self) -> bool {
+        self.backend.headless
+    }
+
+    fn assert_not_headless(&self, method: &str) {
+        assert!(!self.backend.headless, "{}() requires Gtk+, but the executor is headless", method);
+    }
+
+    /// Instantiates executor that polls tasks on `context`. Does not require Gtk+.
+    /// The caller must make sure that `context` is iterated by the current thread.
+    pub(crate) fn with_context(context: glib::MainContext) -> Self {
+        let executor_id = NEXT_EXECUTOR_ID.fetch_add(1, Ordering::SeqCst);
+        let executor = GtkEventLoopAsyncExecutor {
+            backend: Rc::new(
                 GtkEventLoopAsyncExecutorBackend {
-                    next_id: AtomicUsize::new(0),
-                    spawns: RefCell::new(HashMap::new())
+                    executor_id,
+                    wake_queue: Arc::new(WakeQueue::new(executor_id, context.clone())),
+                    context,
+                    accepting_remote: Arc::new(AtomicBool::new(true)),
+                    owner_thread: std::thread::current().id(),
+                    next_generation: Cell::new(0),
+                    spawns: RefCell::new(TaskTable::new()),
+                    poll_stats_by_name: RefCell::new(HashMap::new()),
+                    #[cfg(feature = "task-arena")]
+                    arena: Rc::new(TaskArena::new()),
+                    observers: RefCell::new(Vec::new()),
+                    quit_when_idle: RefCell::new(None),
+                    current_task: Cell::new(None),
+                    polling: RefCell::new(HashMap::new()),
+                    woken_while_polling: RefCell::new(HashSet::new()),
+                    stale_wakes: Cell::new(0),
+                    pending_ops: RefCell::new(Vec::new()),
+                    shutdown: RefCell::new(None),
+                    error_policy: RefCell::new(Rc::new(TaskErrorPolicy::LogOnly)),
+                    error_subscribers: RefCell::new(Vec::new()),
+                    instrumentation: RefCell::new(None),
+                    max_tasks: Cell::new(None),
+                    capacity_waiters: RefCell::new(Vec::new()),
+                    loop_running: Cell::new(false),
+                    polling_spawned: Cell::new(false),
+                    dispatch_depth: Cell::new(0),
+                    loop_start_hooks: RefCell::new(Vec::new()),
+                    deferred_wakes: RefCell::new(Vec::new()),
+                    headless: !gtk::is_initialized_main_thread(),
                 }
             )
+        };
+
+        EXECUTORS.with(|executors| {
+            executors.borrow_mut().insert(executor_id, Rc::downgrade(&executor.backend));
+        });
+
+        // Idle sources are dispatched only once the context is iterated, i.e. when the main loop has started
+        let source = glib::source::idle_source_new(None, glib::PRIORITY_HIGH, move || {
+            if let Some(executor) = GtkEventLoopAsyncExecutor::lookup(executor_id) {
+                executor.start_loop();
+            }
+            glib::source::Continue(false)
+        });
+        source.attach(Some(&executor.backend.context));
+
+        executor
+    }
+
+    /// Finds the executor with `executor_id` created on the current thread
+    fn lookup(executor_id: usize) -> Option<GtkEventLoopAsyncExecutor> {
+        EXECUTORS.with(|executors| executors.borrow().get(&executor_id).and_then(Weak::upgrade))
+            .map(|backend| GtkEventLoopAsyncExecutor { backend })
+    }
+
+    /// Returns `true` once the main loop (or the main context, for executors that are stepped manually)
+    /// has started dispatching events
+    pub fn is_loop_running(&self) -> bool {
+        self.backend.loop_running.get()
+    }
+
+    /// Registers a callback that is invoked when the main loop starts, before the tasks spawned so far are polled.
+    /// If the loop is already running, `f` is invoked immediately.
+    pub fn on_loop_start<F: FnOnce() + 'static>(&self, f: F) {
+        if self.is_loop_running() {
+            f();
+        } else {
+            self.backend.loop_start_hooks.borrow_mut().push(Box::new(f));
+        }
+    }
+
+    fn enter_dispatch(&self) -> DispatchGuard {
+        self.backend.dispatch_depth.set(self.backend.dispatch_depth.get() + 1);
+        DispatchGuard { executor: self.clone() }
+    }
+
+    fn start_loop(&self) {
+        if self.backend.loop_running.replace(true) {
+            return;
+        }
+        let _dispatching = self.enter_dispatch();
+
+        let hooks = std::mem::replace(&mut *self.backend.loop_start_hooks.borrow_mut(), Vec::new());
+        for hook in hooks {
+            hook();
+        }
+
+        let deferred = std::mem::replace(&mut *self.backend.deferred_wakes.borrow_mut(), Vec::new());
+        for (id, generation, priority) in deferred {
+            use futures::executor::Notify;
+            GtkEventLoopAsyncExecutorNotifier::new(self, priority, generation).notify(id);
+        }
+    }
+
+    /// Always-on check for the entry points that touch task storage (`spawn` and `invoke`).
+    /// Comparing thread ids is cheap compared to a silent `RefCell` race.
+    pub(crate) fn check_owner_thread(&self) -> Result<(), SpawnError> {
+        let thread = std::thread::current().id();
+        if thread == self.backend.owner_thread {
+            Ok(())
+        } else {
+            Err(SpawnError::WrongThread { thread, owner: self.backend.owner_thread })
+        }
+    }
+
+    /// Returns the main context on which tasks are polled
+    pub(crate) fn context(&self) -> glib::MainContext {
+        self.backend.context.clone()
+    }
+
+    /// Returns a handle that spawns `Send` futures on this executor from any thread
+    pub fn remote(&self) -> RemoteSpawner {
+        RemoteSpawner {
+            executor_id: self.backend.executor_id,
+            context: self.backend.context.clone(),
+            accepting: self.backend.accepting_remote.clone(),
+            owner_thread: self.backend.owner_thread,
         }
     }
 
-    /// Executes specified future on Gtk+ main thread (using event loop to schedule callbacks)
+    /// Returns ids and names of tasks that have not completed yet, ordered by spawn time.
+    /// The task that is being polled right now (i.e. the caller, when called from a task) is not included.
+    /// Ids of completed tasks are reused, so an id identifies a task only while the task is pending.
+    pub fn pending_tasks(&self) -> Vec<(usize, Option<String>)> {
+        let mut tasks: Vec<(u64, usize, Option<String>)> = self.backend.spawns.borrow().iter()
+            .map(|(id, task)| (task.generation, id, task.name.clone()))
+            .collect();
+        tasks.sort_by_key(|&(generation, _, _)| generation);
+        tasks.into_iter().map(|(_, id, name)| (id, name)).collect()
+    }
+
+    /// Returns names of pending named tasks, ordered by spawn time.
+    /// Useful in tests: "after clicking Save, exactly one 'autosave' task is pending".
+    pub fn pending_task_names(&self) -> Vec<String> {
+        self.pending_tasks().into_iter().filter_map(|(_, name)| name).collect()
+    }
+
+    /// Returns the number of pending tasks (excluding the task that is being polled right now)
+    pub fn pending_task_count(&self) -> usize {
+        self.backend.spawns.borrow().len()
+    }
+
+    /// Returns ids, names and time spent polling of tasks that have not completed yet, most expensive first.
+    /// The task that is being polled right now is not included.
+    pub fn pending_task_poll_stats(&self) -> Vec<(usize, Option<String>, PollStats)> {
+        let mut tasks: Vec<(usize, Option<String>, PollStats)> = self.backend.spawns.borrow().iter()
+            .map(|(id, task)| (id, task.name.clone(), task.poll_stats))
+            .collect();
+        tasks.sort_by(|a, b| b.2.total.cmp(&a.2.total));
+        tasks
+    }
+
+    /// Returns time spent polling tasks since the executor was created, summed by task name
+    /// (unnamed tasks are summed under `None`), most expensive first. Completed tasks are included,
+    /// so this shows which features are responsible for main thread time.
+    pub fn poll_stats_by_name(&self) -> Vec<(Option<String>, PollStats)> {
+        let mut stats: Vec<(Option<String>, PollStats)> = self.backend.poll_stats_by_name.borrow().iter()
+            .map(|(name, stats)| (name.clone(), *stats))
+            .collect();
+        stats.sort_by(|a, b| b.1.total.cmp(&a.1.total));
+        stats
+    }
+
+    /// Returns the number of wakes that arrived after their task had already completed or was cancelled.
+    /// Such wakes are normal (e.g. the losing branch of `select` notifies the task later) and are ignored.
+    pub fn stale_wake_count(&self) -> usize {
+        self.backend.stale_wakes.get()
+    }
+
+    /// *Panics* if any task (other than the caller) is pending, listing the pending tasks.
+    /// Useful for catching leaked or orphaned tasks in tests.
+    pub fn assert_idle(&self) {
+        let tasks = self.pending_tasks();
+        if !tasks.is_empty() {
+            let descriptions: Vec<String> = tasks.into_iter()
+                .map(|(id, name)| match name {
+                    Some(name) => format!("{} ({})", id, name),
+                    None => id.to_string(),
+                })
+                .collect();
+            panic!("executor is not idle; pending tasks: {}", descriptions.join(", "));
+        }
+    }
+
+    /// Executes specified future on Gtk+ main thread (using event loop to schedule callbacks).
+    /// When called from a task (or another callback dispatched by the executor), the future is polled for the first time
+    /// right away, inside `spawn()` (regardless of the task priority), so that futures that are already complete
+    /// don't wait for the main loop; futures spawned during such a first poll are polled first on the main loop.
+    /// Elsewhere the first poll happens on the main loop.
     pub fn spawn<F: Future<Item=(), Error=()> + Sized + 'static>(&self, f: F) {
-        let id = self.backend.next_id.fetch_add(1, Ordering::SeqCst);
+        self.spawn_task(TaskOptions::default(), f.map_err(|()| None));
+    }
+
+    /// Executes specified future on Gtk+ main thread like `spawn()`.
+    /// If the future fails, its error is rendered with `Display` and `Debug` and passed to the task error policy
+    /// as `TaskError::message` and `TaskError::debug`, so callers don't need to `.map_err(|_| ())` and lose the error.
+    pub fn spawn_fallible<F>(&self, f: F)
+        where F: Future<Item=()> + Sized + 'static,
+              F::Error: std::fmt::Display + std::fmt::Debug
+    {
+        self.task().spawn_fallible(f);
+    }
+
+    /// Executes specified future on Gtk+ main thread like `spawn_fallible()`. The chain of causes of the error
+    /// is passed to the task error policy as `TaskError::causes`, so context added in backend layers
+    /// (`.context("loading settings")`) reaches the UI.
+    #[cfg(feature = "anyhow")]
+    pub fn spawn_anyhow<F: Future<Item=(), Error=anyhow::Error> + Sized + 'static>(&self, f: F) {
+        self.task().spawn_anyhow(f);
+    }
+
+    /// Executes specified future on Gtk+ main thread like `spawn_fallible()`. The chain of causes of the error
+    /// is passed to the task error policy as `TaskError::causes`.
+    #[cfg(feature = "failure")]
+    pub fn spawn_failure<F: Future<Item=(), Error=failure::Error> + Sized + 'static>(&self, f: F) {
+        self.task().spawn_failure(f);
+    }
+
+    /// Executes specified future on Gtk+ main thread like `spawn()`, but returns an error instead of panicking
+    /// when called on a thread other than the one that created the executor,
+    /// instead of dropping the future with a warning when the executor is shutting down,
+    /// and when the task limit set with `set_max_tasks()` is reached.
+    pub fn try_spawn<F: Future<Item=(), Error=()> + Sized + 'static>(&self, f: F) -> Result<(), SpawnError> {
+        self.task().try_spawn(f)
+    }
+
+    /// Executes specified future on Gtk+ main thread like `spawn()`.
+    /// The name is passed to task observers and is used in diagnostics.
+    pub fn spawn_named<F: Future<Item=(), Error=()> + Sized + 'static>(&self, name: &str, f: F) {
+        self.task().name(name).spawn(f);
+    }
+
+    /// Executes specified future on Gtk+ main thread and returns a handle that owns the task.
+    /// The task is cancelled when the handle is dropped, unless it is detached with `TaskHandle::detach()`.
+    /// The handle is a future that resolves with the result of the task.
+    pub fn spawn_with_handle<F: Future + 'static>(&self, f: F) -> TaskHandle<F::Item, F::Error> {
+        self.task().spawn_with_handle(f)
+    }
+
+    /// Executes specified future on Gtk+ main thread once and returns a cloneable handle;
+    /// every clone of the handle is a future that resolves with a clone of the result. See `SharedHandle`.
+    pub fn spawn_shared<F>(&self, f: F) -> SharedHandle<F::Item, F::Error>
+        where F: Future + 'static,
+              F::Item: Clone + 'static,
+              F::Error: Clone + 'static
+    {
+        self.task().spawn_shared(f)
+    }
+
+    /// Consumes `stream` on Gtk+ main thread, calling `on_item` for every item (e.g. to update widgets).
+    /// If the stream fails, `on_error` is called with the error and consumption stops.
+    ///
+    /// Returns a handle that owns the consuming task: dropping it stops consumption
+    /// (call `TaskHandle::detach()` to consume the stream until it ends).
+    ///
+    /// Example:
+    /// ```rust
+    /// let handle = gtk_executor.spawn_stream(
+    ///     log_lines,
+    ///     move |line| append_line(&buffer, &line),
+    ///     |error| eprintln!("Log stream failed: {}", error),
+    /// );
+    /// ```
+    pub fn spawn_stream<S, F, E>(&self, stream: S, on_item: F, on_error: E) -> TaskHandle<(), ()>
+        where S: Stream + 'static,
+              F: FnMut(S::Item) + 'static,
+              E: FnOnce(S::Error) + 'static
+    {
+        let mut on_item = on_item;
+        self.spawn_with_handle(
+            stream
+                .for_each(move |item| {
+                    on_item(item);
+                    Ok(())
+                })
+                .or_else(move |error| {
+                    on_error(error);
+                    Ok::<(), ()>(())
+                })
+        )
+    }
+
+    /// Returns a builder for spawning a task with per-task options (name, priority, error handler)
+    pub fn task(&self) -> TaskBuilder {
+        TaskBuilder::new(self)
+    }
+
+    /// Executes specified future on Gtk+ main thread and runs Gtk+ main loop until the future resolves.
+    /// Returns the result of the future after the main loop exits.
+    ///
+    /// This replaces the usual `spawn(f.then(|_| gtk::main_quit()))` + `gtk::main()` sequence.
+    /// *Panics* if the main loop is quit by other code before the future resolves.
+    pub fn run_main_until<F: Future + 'static>(&self, f: F) -> Result<F::Item, F::Error> {
+        self.assert_not_headless("run_main_until");
+        let result = Rc::new(RefCell::new(None));
+
+        {
+            let result = result.clone();
+            self.spawn(f.then(move |r| {
+                *result.borrow_mut() = Some(r);
+                gtk::main_quit();
+
+                Ok(())
+            }));
+        }
+
+        // The future may have resolved inside `spawn()`, quitting the main loop before it started
+        if result.borrow().is_none() {
+            gtk::main();
+        }
+
+        let r = result.borrow_mut().take();
+        r.expect("Gtk+ main loop was quit before the future passed to run_main_until() resolved")
+    }
+
+    /// Iterates the main context without blocking until no more events can be dispatched,
+    /// i.e. until no task can make progress without waiting for timers or I/O.
+    /// Returns the number of dispatched iterations.
+    ///
+    /// This allows unit tests to deterministically step async GUI logic without calling `gtk::main()`.
+    pub fn run_until_stalled(&self) -> usize {
+        // Timers created by tasks attach to the thread-default context, which must be the iterated one
+        let context = &self.backend.context;
+        context.push_thread_default();
+        let mut iterations = 0;
+        while context.iteration(false) {
+            iterations += 1;
+        }
+        context.pop_thread_default();
+        iterations
+    }
+
+    /// Executes specified future and iterates the main context without blocking until the future resolves.
+    /// Fails if the future is still pending once no more events can be dispatched
+    /// or after `max_iterations` iterations.
+    pub fn run_until<F: Future + 'static>(&self, f: F, max_iterations: usize) -> Result<Result<F::Item, F::Error>, RunUntilError> {
+        let result = Rc::new(RefCell::new(None));
+
+        {
+            let result = result.clone();
+            self.spawn(f.then(move |r| {
+                *result.borrow_mut() = Some(r);
+
+                Ok(())
+            }));
+        }
+
+        // Timers created by tasks attach to the thread-default context, which must be the iterated one
+        let context = &self.backend.context;
+        context.push_thread_default();
+        let mut outcome = Err(RunUntilError::IterationLimitReached);
+        for _ in 0..max_iterations {
+            if result.borrow().is_some() {
+                outcome = Ok(());
+                break;
+            }
+            if !context.iteration(false) {
+                outcome = Err(RunUntilError::Stalled);
+                break;
+            }
+        }
+        context.pop_thread_default();
+
+        let r = result.borrow_mut().take();
+        match (r, outcome) {
+            (Some(r), _) => Ok(r),
+            (None, Ok(())) => unreachable!(),
+            (None, Err(error)) => Err(error),
+        }
+    }
+
+    /// Makes the executor call `gtk::main_quit()` once there are no pending tasks
+    /// and (if `windows` is given) all windows registered in `windows` are closed.
+    ///
+    /// The condition is first checked when the main loop becomes idle, so this may be called before `gtk::main()`.
+    /// The main loop is quit only once; call this method again to re-arm it.
+    pub fn quit_when_idle(&self, windows: Option<&WindowManager>) {
+        self.assert_not_headless("quit_when_idle");
+        *self.backend.quit_when_idle.borrow_mut() = Some(windows.cloned());
+
+        if let Some(windows) = windows {
+            let executor = self.clone();
+            windows.connect_all_closed(move || executor.check_idle());
+        }
+
+        let executor = self.clone();
+        gtk::idle_add(move || {
+            executor.check_idle();
+            gtk::Continue(false)
+        });
+    }
+
+    fn check_idle(&self) {
+        let is_idle = match *self.backend.quit_when_idle.borrow() {
+            None => return,
+            Some(ref windows) => {
+                self.backend.spawns.borrow().is_empty() &&
+                    windows.as_ref().map(|windows| windows.is_empty()).unwrap_or(true)
+            }
+        };
+
+        if is_idle {
+            *self.backend.quit_when_idle.borrow_mut() = None;
+            gtk::main_quit();
+        }
+    }
+
+    /// Starts graceful shutdown of the executor.
+    ///
+    /// After this call the executor stops accepting new tasks (futures passed to `spawn()` are dropped).
+    /// Tasks that are already running may continue until `timeout` elapses; tasks that are still pending at the deadline
+    /// are cancelled (dropped). Returned future resolves with the report of cancelled tasks once
+    /// all tasks are finished or cancelled.
+    ///
+    /// The returned future may itself be executed on this executor: the task that awaits the shutdown is not waited for.
+    /// Needed for "quit but finish saving" flows.
+    pub fn shutdown_with_timeout(&self, timeout: Duration) -> impl Future<Item=ShutdownReport, Error=()> {
+        let already_started = self.backend.shutdown.borrow().is_some();
+
+        if !already_started {
+            *self.backend.shutdown.borrow_mut() = Some(ShutdownState {
+                exempt: HashSet::new(),
+                report: None,
+                waiting_tasks: Vec::new(),
+            });
+            self.backend.accepting_remote.store(false, Ordering::SeqCst);
+
+            // The deadline is a source of the executor's context, which is not the global default one
+            // for executors on their own main context
+            let executor = self.clone();
+            let deadline = glib::source::timeout_source_new(duration_to_ms(timeout), None, glib::PRIORITY_DEFAULT, move || {
+                executor.cancel_stragglers();
+                glib::source::Continue(false)
+            });
+            deadline.attach(Some(&self.backend.context));
+        }
+
+        ShutdownFuture {
+            executor: self.clone(),
+        }
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.backend.shutdown.borrow().is_some()
+    }
+
+    fn cancel_stragglers(&self) {
+        let exempt = match *self.backend.shutdown.borrow() {
+            Some(ShutdownState { report: None, ref exempt, .. }) => exempt.clone(),
+            _ => return,
+        };
+
+        let cancelled_tasks = self.cancel_tasks(&exempt);
+        self.finish_shutdown(ShutdownReport { cancelled_tasks });
+    }
+
+    /// Drops all pending tasks except `exempt` ones. Returns ids and names of cancelled tasks.
+    /// Tasks whose poll is in progress (e.g. the one that runs a nested main loop) are dropped once their poll returns.
+    fn cancel_tasks(&self, exempt: &HashSet<usize>) -> Vec<(usize, Option<String>)> {
+        let cancelled: Vec<(usize, Task)> = {
+            let mut spawns = self.backend.spawns.borrow_mut();
+            let ids: Vec<usize> = spawns.ids().filter(|id| !exempt.contains(id)).collect();
+            ids.into_iter().map(|id| (id, spawns.remove(id).unwrap())).collect()
+        };
+
+        let mut cancelled_tasks = Vec::new();
+        for (&(id, generation), name) in self.backend.polling.borrow().iter() {
+            if !exempt.contains(&id) {
+                self.backend.pending_ops.borrow_mut().push(PendingOp::Cancel { id, generation });
+                cancelled_tasks.push((id, name.clone()));
+            }
+        }
+
+        for (id, task) in cancelled {
+            let Task { spawn, name, .. } = task;
+            // Drop the future before reporting, so that its destructors (e.g. busy guards) run first
+            drop(spawn);
+            self.notify_observers(TaskEvent::Cancelled { id, name: name.as_ref().map(|s| s.as_str()) });
+            self.backend.spawns.borrow_mut().release(id);
+            cancelled_tasks.push((id, name));
+        }
+        self.task_removed();
+
+        cancelled_tasks
+    }
+
+    /// Shrinks task storage after a burst and lets waiting `spawn_when_ready()` futures proceed
+    fn task_removed(&self) {
         {
             let mut spawns = self.backend.spawns.borrow_mut();
-            let spawn = futures::executor::spawn(Box::new(f) as BoxUnitFuture);
-            spawns.insert(id, spawn);
+            if spawns.capacity() > MIN_TASK_CAPACITY && spawns.len() * 4 < spawns.capacity() {
+                spawns.shrink_to_fit();
+            }
+        }
+
+        let waiting_tasks = std::mem::replace(&mut *self.backend.capacity_waiters.borrow_mut(), Vec::new());
+        for task in waiting_tasks {
+            task.notify();
+        }
+    }
+
+    fn record_poll(&self, task: &mut Task, poll_duration: Duration) {
+        task.poll_stats.record(poll_duration);
+
+        let mut by_name = self.backend.poll_stats_by_name.borrow_mut();
+        if let Some(stats) = by_name.get_mut(&task.name) {
+            stats.record(poll_duration);
+            return;
+        }
+        let mut stats = PollStats::default();
+        stats.record(poll_duration);
+        by_name.insert(task.name.clone(), stats);
+    }
+
+    /// Releases memory that task storage kept after a burst of spawns.
+    /// Storage is also shrunk automatically when it becomes mostly empty.
+    pub fn compact(&self) {
+        self.backend.spawns.borrow_mut().shrink_to_fit();
+        #[cfg(feature = "task-arena")]
+        self.backend.arena.clear();
+    }
+
+    /// Sets the time after which woken tasks of priorities lower than `Priority::DEFAULT` (which includes
+    /// the default task priority `Priority::DEFAULT_IDLE`) are polled at `Priority::DEFAULT` if they still
+    /// haven't been polled because the main loop is busy with events of higher priority. This keeps background work (cache cleanup, prefetching)
+    /// progressing under sustained interaction. `None` disables aging; the default is 500 ms.
+    pub fn set_priority_aging(&self, interval: Option<Duration>) {
+        let aging_ms = interval.map(|interval| duration_to_ms(interval).max(1) as usize).unwrap_or(0);
+        self.backend.wake_queue.settings.aging_ms.store(aging_ms, Ordering::SeqCst);
+    }
+
+    /// Limits the number of tasks polled by a single dispatch of woken tasks of one priority
+    /// (`None`, the default, means unlimited). Woken tasks beyond the limit are polled by a follow-up dispatch
+    /// at the same priority, so a burst of wakes doesn't delay redrawing and input handling for the whole burst.
+    pub fn set_max_polls_per_dispatch(&self, max_polls: Option<usize>) {
+        let max_polls = max_polls.map(|max_polls| max_polls.max(1)).unwrap_or(0);
+        self.backend.wake_queue.settings.max_polls_per_dispatch.store(max_polls, Ordering::SeqCst);
+    }
+
+    /// Returns the number of wakes that were carried over to a follow-up dispatch because the number of tasks woken
+    /// at once exceeded the limit set with `set_max_polls_per_dispatch()`. A wake carried over several times
+    /// is counted every time.
+    pub fn carried_over_wake_count(&self) -> usize {
+        self.backend.wake_queue.settings.carried_over.load(Ordering::SeqCst)
+    }
+
+    /// Sets the maximum number of tasks that may exist at once for `try_spawn()` and `spawn_when_ready()`
+    /// (`None`, the default, means unlimited). `spawn()` is not limited.
+    pub fn set_max_tasks(&self, max_tasks: Option<usize>) {
+        self.backend.max_tasks.set(max_tasks);
+        self.task_removed();
+    }
+
+    fn task_count(&self) -> usize {
+        self.backend.spawns.borrow().len() + self.backend.polling.borrow().len()
+    }
+
+    pub(crate) fn has_capacity(&self) -> bool {
+        self.backend.max_tasks.get().map(|max_tasks| self.task_count() < max_tasks).unwrap_or(true)
+    }
+
+    /// Returns a future that spawns `f` as soon as the number of tasks is below the limit set with `set_max_tasks()`,
+    /// and resolves once `f` is spawned. This applies backpressure to producers of many short tasks
+    /// (e.g. per-row thumbnail loads). Fails if the executor is shutting down.
+    pub fn spawn_when_ready<F: Future<Item=(), Error=()> + Sized + 'static>(&self, f: F) -> impl Future<Item=(), Error=SpawnError> {
+        let executor = self.clone();
+        let mut f = Some(f);
+
+        futures::future::poll_fn(move || {
+            if !executor.has_capacity() {
+                executor.backend.capacity_waiters.borrow_mut().push(futures::task::current());
+                return Ok(Async::NotReady);
+            }
+
+            let f = f.take().expect("spawn_when_ready() future polled after completion");
+            executor.try_spawn_task(TaskOptions::default(), f.map_err(|()| None))?;
+            Ok(Async::Ready(()))
+        })
+    }
+
+    /// Drops all pending tasks. Returns ids and names of cancelled tasks.
+    pub(crate) fn cancel_all(&self) -> Vec<(usize, Option<String>)> {
+        self.cancel_tasks(&HashSet::new())
+    }
+
+    fn finish_shutdown(&self, report: ShutdownReport) {
+        let waiting_tasks = match *self.backend.shutdown.borrow_mut() {
+            Some(ref mut shutdown) if shutdown.report.is_none() => {
+                shutdown.report = Some(report);
+                std::mem::replace(&mut shutdown.waiting_tasks, Vec::new())
+            },
+            _ => return,
+        };
+
+        for task in waiting_tasks {
+            task.notify();
+        }
+    }
+
+    fn check_shutdown(&self) {
+        let finished = match *self.backend.shutdown.borrow() {
+            Some(ShutdownState { report: None, ref exempt, .. }) => {
+                self.backend.spawns.borrow().ids().all(|id| exempt.contains(&id)) &&
+                    self.backend.polling.borrow().keys().all(|&(id, _)| exempt.contains(&id))
+            },
+            _ => return,
+        };
+
+        if finished {
+            self.finish_shutdown(ShutdownReport { cancelled_tasks: Vec::new() });
+        }
+    }
+
+    /// Sets how errors of failed tasks are presented to the user. By default they are printed to stderr.
+    /// Headless executors (see `is_headless()`) use `TaskErrorPolicy::LogOnly` instead of policies that show widgets.
+    pub fn set_task_error_policy(&self, policy: TaskErrorPolicy) {
+        let policy = match policy {
+            TaskErrorPolicy::ShowDialog(..) | TaskErrorPolicy::InfoBar(..) if self.backend.headless => {
+                logging::warning("Executor is headless, task errors are logged instead of shown");
+                TaskErrorPolicy::LogOnly
+            },
+            policy => policy,
+        };
+        *self.backend.error_policy.borrow_mut() = Rc::new(policy);
+    }
+
+    /// Returns a stream of errors of failed tasks, for rendering them asynchronously
+    /// (batching, deduplicating, sending telemetry). The stream receives the errors that are passed
+    /// to the task error policy, i.e. of tasks without their own error handler (`TaskBuilder::on_error`),
+    /// in addition to the policy; set a `TaskErrorPolicy::Custom` policy that does nothing to only use the stream.
+    /// The stream ends when the executor is dropped.
+    pub fn errors(&self) -> impl Stream<Item=TaskError, Error=()> {
+        let (sender, receiver) = futures::sync::mpsc::unbounded();
+        self.backend.error_subscribers.borrow_mut().push(sender);
+        receiver
+    }
+
+    fn report_error(&self, error: TaskError) {
+        // Subscribers whose stream was dropped are removed
+        self.backend.error_subscribers.borrow_mut().retain(|sender| sender.unbounded_send(error.clone()).is_ok());
+
+        let policy = self.backend.error_policy.borrow().clone();
+        policy.report(&error);
+    }
+
+    /// Installs hooks that are invoked around every poll of every task (replacing previously installed ones).
+    /// Useful for measuring poll durations and wake latencies of a real application.
+    pub fn set_instrumentation<I: TaskInstrumentation + 'static>(&self, instrumentation: I) {
+        *self.backend.instrumentation.borrow_mut() = Some(Rc::new(instrumentation));
+    }
+
+    /// Registers a callback that is invoked on Gtk+ main thread whenever a task starts, finishes or fails
+    pub fn add_task_observer<F: Fn(&TaskEvent) + 'static>(&self, observer: F) {
+        self.backend.observers.borrow_mut().push(Rc::new(observer));
+    }
+
+    fn notify_observers(&self, event: TaskEvent) {
+        if logging::is_glib_logging() {
+            logging::debug(&event.to_string());
+        }
+        let observers = self.backend.observers.borrow().clone();
+        for observer in observers {
+            observer(&event);
+        }
+    }
+
+    /// Spawns a task and returns its id and generation, or `None` if the executor is shutting down and the future was dropped.
+    /// *Panics* if called on a thread other than the one that created the executor.
+    pub(crate) fn spawn_task<F: Future<Item=(), Error=TaskFailure> + Sized + 'static>(&self, options: TaskOptions, f: F) -> Option<(usize, u64)> {
+        let name = options.name.clone();
+        match self.try_spawn_task(options, f) {
+            Ok(task) => Some(task),
+            Err(SpawnError::ShuttingDown) => {
+                logging::warning(&format!(
+                    "Executor is shutting down, spawned future {} is dropped",
+                    name.as_ref().map(|s| s.as_str()).unwrap_or("<unnamed>")
+                ));
+                None
+            },
+            Err(error) => panic!("spawn(): {}", error),
         }
+    }
 
-        let handle = GtkEventLoopAsyncExecutorNotifier::new(self.clone());
+    /// Spawns a task and returns its id and generation
+    pub(crate) fn try_spawn_task<F: Future<Item=(), Error=TaskFailure> + Sized + 'static>(&self, options: TaskOptions, f: F) -> Result<(usize, u64), SpawnError> {
+        self.check_owner_thread()?;
+
+        let TaskOptions { name, priority, on_error, panic_policy, timeout } = options;
+        let f = TimeLimited::new(f, timeout);
+
+        if self.is_shutting_down() {
+            return Err(SpawnError::ShuttingDown);
+        }
 
-        use futures::executor::Notify;
+        let id = self.backend.spawns.borrow_mut().allocate();
+        let generation = self.backend.next_generation.get();
+        self.backend.next_generation.set(generation + 1);
+        self.notify_observers(TaskEvent::Started { id, name: name.as_ref().map(|s| s.as_str()) });
+        #[cfg(feature = "task-arena")]
+        let spawn = futures::executor::spawn(TaskSlot::in_arena(f, &self.backend.arena));
+        #[cfg(not(feature = "task-arena"))]
+        let spawn = futures::executor::spawn(TaskSlot::new(f));
+        let task = Task {
+            spawn,
+            generation,
+            name,
+            priority,
+            on_error,
+            panic_policy,
+            spawned_at: Instant::now(),
+            #[cfg(feature = "spawn-backtrace")]
+            spawn_backtrace: backtrace::Backtrace::new_unresolved(),
+            self_wakes: 0,
+            demoted_from: None,
+            poll_stats: PollStats::default(),
+        };
 
-        handle.notify(id);
+        if !self.is_loop_running() {
+            // The first poll is buffered until the main loop starts, so that the task observes a running loop
+            self.backend.spawns.borrow_mut().insert(id, task);
+            self.backend.deferred_wakes.borrow_mut().push((id, generation, priority));
+        } else if self.backend.dispatch_depth.get() > 0 && !self.backend.polling_spawned.replace(true) {
+            // Fast path: many futures (resolved promises, `future::ok`) complete on their first poll,
+            // so poll right away; the task gets into the task table only if it is not complete.
+            // It is taken only while the executor dispatches its tasks: code outside of the main loop
+            // (e.g. `run_main_until()` before `gtk::main()`) relies on spawned futures not running inside `spawn()`.
+            // Tasks spawned during this poll take the slow path, so that spawning can't recurse.
+            self.poll_task(id, generation, task, Instant::now());
+            self.backend.polling_spawned.set(false);
+        } else {
+            self.backend.spawns.borrow_mut().insert(id, task);
+            let handle = GtkEventLoopAsyncExecutorNotifier::new(self, priority, generation);
+
+            use futures::executor::Notify;
+
+            handle.notify(id);
+        }
+
+        Ok((id, generation))
     }
 
-    fn invoke(&self, id: usize) {
-        let opt_spawn = self.backend.spawns.borrow_mut().remove(&id);
+    /// Removes task `id` if it belongs to `generation`
+    fn take_task(&self, id: usize, generation: u64) -> Option<Task> {
+        let mut spawns = self.backend.spawns.borrow_mut();
+        match spawns.get(id) {
+            Some(task) if task.generation == generation => spawns.remove(id),
+            _ => None,
+        }
+    }
+
+    /// Drops the pending task `id` of `generation`. Returns `false` if the task has already completed.
+    /// If the task is being polled right now, it is dropped once its poll returns.
+    pub(crate) fn cancel_task(&self, id: usize, generation: u64) -> bool {
+        if self.backend.polling.borrow().contains_key(&(id, generation)) {
+            self.backend.pending_ops.borrow_mut().push(PendingOp::Cancel { id, generation });
+            return true;
+        }
+
+        let task = self.take_task(id, generation);
+        match task {
+            Some(task) => {
+                let Task { spawn, name, .. } = task;
+                drop(spawn);
+                self.notify_observers(TaskEvent::Cancelled { id, name: name.as_ref().map(|s| s.as_str()) });
+                self.backend.spawns.borrow_mut().release(id);
+                self.task_removed();
+                self.check_shutdown();
+                self.check_idle();
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns `true` if task `id` of `generation` has not completed yet
+    pub(crate) fn is_task_pending(&self, id: usize, generation: u64) -> bool {
+        self.backend.polling.borrow().contains_key(&(id, generation)) ||
+            self.backend.spawns.borrow().get(id).map(|task| task.generation == generation).unwrap_or(false)
+    }
+
+    /// Demotes a task that keeps waking itself from its own poll (e.g. a buggy future that notifies the current task
+    /// before returning `NotReady`) to `Priority::LOW`, so that it can't starve redrawing and input handling.
+    /// The task's priority is restored once it waits for something else.
+    fn track_busy_loop(&self, id: usize, task: &mut Task, woke_itself: bool) {
+        if !woke_itself {
+            task.self_wakes = 0;
+            if let Some(priority) = task.demoted_from.take() {
+                task.priority = priority;
+            }
+            return;
+        }
+
+        task.self_wakes = task.self_wakes.saturating_add(1);
+        if task.self_wakes >= BUSY_LOOP_THRESHOLD && task.demoted_from.is_none() && task.priority < Priority::LOW {
+            logging::warning(&format!(
+                "Task {} ({}) woke itself {} times in a row without waiting for anything; demoting it to low priority",
+                id, task.name.as_ref().map(|s| s.as_str()).unwrap_or("<unnamed>"), task.self_wakes
+            ));
+            task.demoted_from = Some(task.priority);
+            task.priority = Priority::LOW;
+        }
+    }
+
+    /// Applies operations that user code requested on tasks that were being polled at the time
+    fn apply_pending_ops(&self) {
+        let ops = std::mem::replace(&mut *self.backend.pending_ops.borrow_mut(), Vec::new());
+        for op in ops {
+            match op {
+                PendingOp::Cancel { id, generation } => {
+                    self.cancel_task(id, generation);
+                },
+            }
+        }
+    }
+
+    fn invoke(&self, id: usize, generation: u64, woken_at: Instant) {
+        if let Err(error) = self.check_owner_thread() {
+            panic!("invoke(): {}", error);
+        }
+
+        let opt_spawn = self.take_task(id, generation);
         match opt_spawn {
+            None if self.backend.polling.borrow().contains_key(&(id, generation)) => {
+                // The task is blocked in its poll running a nested main loop; poll it again once that poll returns
+                self.backend.woken_while_polling.borrow_mut().insert(id);
+            },
             None => {
-                eprintln!("Attempted to invoke non-existing spawn {}", id);
+                // The task of this generation has already completed or was cancelled
+                self.backend.stale_wakes.set(self.backend.stale_wakes.get() + 1);
+            },
+            Some(task) => self.poll_task(id, generation, task, woken_at),
+        }
+    }
+
+    /// Polls `task`; puts it back into the task table if it is not complete
+    fn poll_task(&self, id: usize, generation: u64, mut task: Task, woken_at: Instant) {
+        let instrumentation = self.backend.instrumentation.borrow().clone();
+        if let Some(ref instrumentation) = instrumentation {
+            instrumentation.poll_started(id, task.name.as_ref().map(|s| s.as_str()));
+        }
+        let poll_started_at = Instant::now();
+
+        let notifier = GtkEventLoopAsyncExecutorNotifier::new(self, task.priority, generation);
+        let previous_task = self.backend.current_task.replace(Some(id));
+        let previous_forced_yield = FORCED_YIELD.with(|forced_yield| forced_yield.replace(false));
+        self.backend.polling.borrow_mut().insert((id, generation), task.name.clone());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            task.spawn.poll_future_notify(
+                &futures::executor::NotifyHandle::from(Arc::new(notifier.clone())),
+                id
+            )
+        }));
+        self.backend.polling.borrow_mut().remove(&(id, generation));
+        self.backend.current_task.set(previous_task);
+        let forced_yield = FORCED_YIELD.with(|forced_yield| forced_yield.replace(previous_forced_yield));
+        let woken_while_polling = self.backend.woken_while_polling.borrow_mut().remove(&id);
+
+        let poll_duration = poll_started_at.elapsed();
+        self.record_poll(&mut task, poll_duration);
+        if let Some(ref instrumentation) = instrumentation {
+            instrumentation.poll_finished(&PollTiming {
+                id,
+                name: task.name.as_ref().map(|s| s.as_str()),
+                poll_duration,
+                wake_latency: poll_started_at.duration_since(woken_at),
+            });
+        }
+
+        let result = match result {
+            Ok(result) => result,
+            Err(payload) => {
+                self.task_panicked(id, task, payload);
+                return;
+            },
+        };
+
+        match result {
+            Ok(Async::Ready(_)) => {
+                self.notify_observers(TaskEvent::Finished { id, name: task.name.as_ref().map(|s| s.as_str()) });
+                self.backend.spawns.borrow_mut().release(id);
+                self.task_removed();
+                self.apply_pending_ops();
+                self.check_shutdown();
+                self.check_idle();
             },
-            Some(mut spawn) => {
-                let result = spawn.poll_future_notify(
-                    &futures::executor::NotifyHandle::from(
-                        Arc::new(GtkEventLoopAsyncExecutorNotifier::new(self.clone()))
-                    ),
-                    id
-                );
-                
-                match result {
-                    Ok(Async::Ready(_)) => {
-                        // Do nothing
-                    },
-                    Ok(Async::NotReady) => {
-                        self.backend.spawns.borrow_mut().insert(id, spawn);
-                    },
-                    Err(_) => {
-                        eprintln!("Spawned future {} returned error", id);
-                    }
+            Ok(Async::NotReady) => {
+                if !forced_yield {
+                    self.track_busy_loop(id, &mut task, notifier.woken.load(Ordering::SeqCst));
                 }
-            }
+                self.backend.spawns.borrow_mut().insert(id, task);
+                if woken_while_polling {
+                    use futures::executor::Notify;
+                    notifier.notify(id);
+                }
+                self.apply_pending_ops();
+            },
+            Err(failure) => self.task_failed(id, task, failure),
+        }
+    }
+
+    /// Reports the failure of task `id` that was taken out of the task table and removes the task
+    fn task_failed(&self, id: usize, task: Task, failure: TaskFailure) {
+        self.notify_observers(TaskEvent::Failed { id, name: task.name.as_ref().map(|s| s.as_str()) });
+        #[cfg(feature = "spawn-backtrace")]
+        let spawn_backtrace = {
+            let mut backtrace = task.spawn_backtrace;
+            backtrace.resolve();
+            Some(format!("{:?}", backtrace))
+        };
+        #[cfg(not(feature = "spawn-backtrace"))]
+        let spawn_backtrace = None;
+        let (message, debug, causes) = match failure {
+            Some(text) => (Some(text.display), Some(text.debug), text.causes),
+            None => (None, None, Vec::new()),
+        };
+        let error = TaskError {
+            id,
+            name: task.name,
+            message,
+            debug,
+            causes,
+            elapsed: task.spawned_at.elapsed(),
+            spawn_backtrace,
+        };
+        match task.on_error {
+            Some(handler) => handler(&error),
+            None => self.report_error(error),
         }
+        self.backend.spawns.borrow_mut().release(id);
+        self.task_removed();
+        self.apply_pending_ops();
+        self.check_shutdown();
+        self.check_idle();
     }
+
+    /// Handles a panic of task `id` that was taken out of the task table according to the task's panic policy
+    fn task_panicked(&self, id: usize, task: Task, payload: Box<Any + Send>) {
+        match task.panic_policy {
+            PanicPolicy::Report => {
+                let message = format!("panicked: {}", panic_message(&*payload).unwrap_or("<no message>"));
+                self.task_failed(id, task, Some(ErrorText { display: message.clone(), debug: message, causes: Vec::new() }));
+            },
+            PanicPolicy::Rethrow => {
+                self.notify_observers(TaskEvent::Failed { id, name: task.name.as_ref().map(|s| s.as_str()) });
+                drop(task);
+                self.backend.spawns.borrow_mut().release(id);
+                self.task_removed();
+                self.apply_pending_ops();
+                self.check_shutdown();
+                std::panic::resume_unwind(payload);
+            },
+        }
+    }
+}
+
+/// Future returned by `GtkEventLoopAsyncExecutor::shutdown_with_timeout`
+struct ShutdownFuture {
+    executor: GtkEventLoopAsyncExecutor,
 }
 
-// safety rationale:
-// GtkEventLoopAsyncExecutorNotifier ensures that GtkEventLoopAsyncExecutor is only ever called from Gtk+ main loop.
-// GtkEventLoopAsyncExecutor may only be created on Gtk+ main thread and main loop runs on main thread.
-// Hence dereference of `executor` Arc happens only happens on the same thread that created GtkEventLoopAsyncExecutor.
-unsafe impl Send for GtkEventLoopAsyncExecutorNotifier{}
-unsafe impl Sync for GtkEventLoopAsyncExecutorNotifier{}
+impl Future for ShutdownFuture {
+    type Item = ShutdownReport;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<ShutdownReport, ()> {
+        {
+            let mut guard = self.executor.backend.shutdown.borrow_mut();
+            let shutdown = guard.as_mut().unwrap();
+
+            if let Some(ref report) = shutdown.report {
+                return Ok(Async::Ready(report.clone()));
+            }
+
+            if let Some(id) = self.executor.backend.current_task.get() {
+                shutdown.exempt.insert(id);
+            }
+            shutdown.waiting_tasks.push(futures::task::current());
+        }
+
+        self.executor.check_shutdown();
+
+        match self.executor.backend.shutdown.borrow().as_ref().unwrap().report {
+            Some(ref report) => Ok(Async::Ready(report.clone())),
+            None => Ok(Async::NotReady),
+        }
+    }
+}
 
 impl futures::executor::Notify for GtkEventLoopAsyncExecutorNotifier {
     fn notify(&self, id: usize) {
-        let handle = self.clone();
-        glib::source::idle_add(move || {
-            handle.executor.invoke(id);
-            glib::source::Continue(false)
-        });
+        self.woken.store(true, Ordering::SeqCst);
+        WakeLane::push(&self.lane, Wake { id, generation: self.generation, woken_at: Instant::now() });
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use futures::prelude::*;
+    use futures::future;
+    use futures::stream;
+    use std::cell::Cell;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use crate::cooperative::cooperative;
+    use super::GtkEventLoopAsyncExecutor;
+    use super::BUSY_LOOP_THRESHOLD;
+
+    struct DropFlag(Rc<Cell<bool>>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    fn is_demoted(executor: &GtkEventLoopAsyncExecutor, name: &str) -> bool {
+        let spawns = executor.backend.spawns.borrow();
+        let (_, task) = spawns.iter()
+            .find(|(_, task)| task.name.as_ref().map(|s| s.as_str()) == Some(name))
+            .expect("task is not pending");
+        task.demoted_from.is_some()
+    }
+
+    #[test]
+    fn forced_cooperative_yields_are_not_busy_loops() {
+        let executor = GtkEventLoopAsyncExecutor::with_context(glib::MainContext::new());
+        executor.spawn_named("busy", future::poll_fn(|| {
+            futures::task::current().notify();
+            Ok(Async::NotReady)
+        }));
+        executor.spawn_named("cooperative", cooperative(stream::repeat::<(), ()>(()), 1).for_each(|()| Ok(())));
+
+        let iterations = 4 * BUSY_LOOP_THRESHOLD as usize;
+        assert!(executor.run_until(future::empty::<(), ()>(), iterations).is_err());
+
+        assert!(is_demoted(&executor, "busy"));
+        assert!(!is_demoted(&executor, "cooperative"));
+    }
+
+    #[test]
+    fn shutdown_cancels_task_whose_poll_is_in_progress() {
+        let context = glib::MainContext::new();
+        let executor = GtkEventLoopAsyncExecutor::with_context(context.clone());
+        let report = Rc::new(RefCell::new(None));
+        let dropped = Rc::new(Cell::new(false));
+
+        {
+            let executor = executor.clone();
+            let report = report.clone();
+            let flag = DropFlag(dropped.clone());
+            let mut started = false;
+            executor.clone().spawn_named("modal", future::poll_fn(move || {
+                let _ = &flag;
+                if std::mem::replace(&mut started, true) {
+                    return Ok(Async::NotReady);
+                }
+
+                // Shuts down from inside this task's poll, which runs a nested main loop until shutdown finishes
+                let main_loop = glib::MainLoop::new(Some(&context), false);
+                {
+                    let report = report.clone();
+                    let main_loop = main_loop.clone();
+                    executor.spawn(executor.shutdown_with_timeout(Duration::from_millis(10)).map(move |shutdown_report| {
+                        *report.borrow_mut() = Some(shutdown_report);
+                        main_loop.quit();
+                    }));
+                }
+                main_loop.run();
+                Ok(Async::NotReady)
+            }));
+        }
+
+        executor.run_until_stalled();
+
+        let report = report.borrow_mut().take().expect("shutdown did not finish");
+        let cancelled: Vec<Option<String>> = report.cancelled_tasks.into_iter().map(|(_, name)| name).collect();
+        assert_eq!(cancelled, vec![Some("modal".to_string())]);
+        assert!(dropped.get());
+    }
+}
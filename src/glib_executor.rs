@@ -0,0 +1,70 @@
+use futures::prelude::*;
+
+use crate::errors::TaskErrorPolicy;
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::executor::RunUntilError;
+use crate::executor::TaskEvent;
+
+/// An executor that executes futures on a plain `glib::MainContext` without requiring Gtk+.
+///
+/// It has the same semantics as `GtkEventLoopAsyncExecutor` (woken tasks are polled in batches from idle sources
+/// of the context, one source per priority), which makes it suitable for integration tests and command line tools
+/// that share async code with the GUI application but must run without a display server.
+///
+/// Like `GtkEventLoopAsyncExecutor`, it is neither `Send` nor `Sync`: it must be used on the thread that owns the context.
+#[derive(Clone)]
+pub struct GlibMainContextExecutor {
+    executor: GtkEventLoopAsyncExecutor,
+}
+
+impl GlibMainContextExecutor {
+    /// Instantiates executor for `context`. The context is acquired by the current thread.
+    /// *Panics* if the context is owned by another thread.
+    pub fn new(context: &glib::MainContext) -> Self {
+        assert!(context.acquire(), "GlibMainContextExecutor::new(): main context is owned by another thread");
+
+        GlibMainContextExecutor {
+            executor: GtkEventLoopAsyncExecutor::with_context(context.clone()),
+        }
+    }
+
+    /// Returns the main context on which tasks are executed
+    pub fn context(&self) -> glib::MainContext {
+        self.executor.context()
+    }
+
+    /// Executes specified future on the main context
+    pub fn spawn<F: Future<Item=(), Error=()> + Sized + 'static>(&self, f: F) {
+        self.executor.spawn(f);
+    }
+
+    /// Executes specified future on the main context like `spawn()`.
+    /// The name is passed to task observers and is used in diagnostics.
+    pub fn spawn_named<F: Future<Item=(), Error=()> + Sized + 'static>(&self, name: &str, f: F) {
+        self.executor.spawn_named(name, f);
+    }
+
+    /// Registers a callback that is invoked whenever a task starts, finishes or fails
+    pub fn add_task_observer<F: Fn(&TaskEvent) + 'static>(&self, observer: F) {
+        self.executor.add_task_observer(observer);
+    }
+
+    /// Sets how errors of failed tasks are reported. By default they are printed to stderr.
+    /// Policies that show widgets must not be used without Gtk+.
+    pub fn set_task_error_policy(&self, policy: TaskErrorPolicy) {
+        self.executor.set_task_error_policy(policy);
+    }
+
+    /// Iterates the main context without blocking until no task can make progress.
+    /// See `GtkEventLoopAsyncExecutor::run_until_stalled`.
+    pub fn run_until_stalled(&self) -> usize {
+        self.executor.run_until_stalled()
+    }
+
+    /// Executes specified future and iterates the main context without blocking until the future resolves.
+    /// Fails if the future is still pending once no more events can be dispatched
+    /// or after `max_iterations` iterations. See `GtkEventLoopAsyncExecutor::run_until`.
+    pub fn run_until<F: Future + 'static>(&self, f: F, max_iterations: usize) -> Result<Result<F::Item, F::Error>, RunUntilError> {
+        self.executor.run_until(f, max_iterations)
+    }
+}
@@ -0,0 +1,109 @@
+use glib::prelude::*;
+use glib::subclass;
+use glib::subclass::prelude::*;
+use glib::translate::*;
+use std::cell::RefCell;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::executor::TaskEvent;
+use crate::logging;
+
+/// Instance data of `ExecutorObject`
+pub struct ExecutorObjectPrivate {
+    executor: RefCell<Option<GtkEventLoopAsyncExecutor>>,
+}
+
+impl ObjectSubclass for ExecutorObjectPrivate {
+    const NAME: &'static str = "GtkFutureExecutor";
+    type ParentType = glib::Object;
+    type Instance = subclass::simple::InstanceStruct<Self>;
+    type Class = subclass::simple::ClassStruct<Self>;
+
+    glib_object_subclass!();
+
+    fn class_init(klass: &mut Self::Class) {
+        klass.add_signal(
+            "task-started",
+            glib::SignalFlags::RUN_LAST,
+            &[u64::static_type(), String::static_type()],
+            glib::Type::Unit,
+        );
+        klass.add_signal(
+            "task-finished",
+            glib::SignalFlags::RUN_LAST,
+            &[u64::static_type(), String::static_type(), bool::static_type()],
+            glib::Type::Unit,
+        );
+    }
+
+    fn new() -> Self {
+        ExecutorObjectPrivate {
+            executor: RefCell::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for ExecutorObjectPrivate {
+    glib_object_impl!();
+}
+
+glib_wrapper! {
+    /// `GtkEventLoopAsyncExecutor` wrapped into a GObject.
+    ///
+    /// It can be stored in GObject properties and object data, passed through `gtk::Builder`
+    /// and observed by language bindings in hybrid applications.
+    ///
+    /// Signals:
+    /// * `task-started(id: u64, name: String)` - a task was spawned (unnamed tasks have empty name)
+    /// * `task-finished(id: u64, name: String, succeeded: bool)` - a task completed, failed or was cancelled
+    pub struct ExecutorObject(Object<subclass::simple::InstanceStruct<ExecutorObjectPrivate>, subclass::simple::ClassStruct<ExecutorObjectPrivate>, ExecutorObjectClass>);
+
+    match fn {
+        get_type => || ExecutorObjectPrivate::get_type().to_glib(),
+    }
+}
+
+impl ExecutorObject {
+    /// Wraps `executor`. The object emits signals for tasks of the executor while it is alive.
+    pub fn new(executor: &GtkEventLoopAsyncExecutor) -> Self {
+        let object = glib::Object::new(Self::static_type(), &[])
+            .expect("Failed to create GtkFutureExecutor object")
+            .downcast::<ExecutorObject>()
+            .unwrap();
+
+        let private = ExecutorObjectPrivate::from_instance(&object);
+        *private.executor.borrow_mut() = Some(executor.clone());
+
+        let weak = object.downgrade();
+        executor.add_task_observer(move |event| {
+            let object = match weak.upgrade() {
+                Some(object) => object,
+                None => return,
+            };
+
+            let result = match *event {
+                TaskEvent::Started { id, name } => {
+                    object.emit("task-started", &[&(id as u64), &name.unwrap_or("")])
+                },
+                TaskEvent::Finished { id, name } => {
+                    object.emit("task-finished", &[&(id as u64), &name.unwrap_or(""), &true])
+                },
+                TaskEvent::Failed { id, name } | TaskEvent::Cancelled { id, name } => {
+                    object.emit("task-finished", &[&(id as u64), &name.unwrap_or(""), &false])
+                },
+            };
+
+            if let Err(error) = result {
+                logging::warning(&format!("Failed to emit executor signal: {}", error));
+            }
+        });
+
+        object
+    }
+
+    /// Returns the wrapped executor
+    pub fn executor(&self) -> GtkEventLoopAsyncExecutor {
+        let private = ExecutorObjectPrivate::from_instance(self);
+        private.executor.borrow().clone().expect("ExecutorObject was not initialized")
+    }
+}
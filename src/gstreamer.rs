@@ -0,0 +1,128 @@
+use futures::prelude::*;
+use futures::sync::mpsc;
+use gst::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+
+/// Messages of a GStreamer pipeline's bus, delivered on Gtk+ main thread.
+///
+/// A bus may have only one watch, so `PipelineBus` installs a single watch and hands out any number of
+/// message streams and futures. The watch is removed when `PipelineBus` is dropped.
+///
+/// Example:
+/// ```rust
+/// let bus = PipelineBus::new(&gtk_executor, &pipeline);
+/// gtk_executor.spawn_fallible(
+///     bus.set_state(gst::State::Playing)
+///         .and_then(move |_| bus.eos())
+///         .map(move |_| player_window.close())
+/// );
+/// ```
+pub struct PipelineBus {
+    pipeline: gst::Pipeline,
+    subscribers: Rc<RefCell<Vec<mpsc::UnboundedSender<gst::Message>>>>,
+    watch: Option<glib::SourceId>,
+}
+
+impl PipelineBus {
+    /// Starts watching the bus of `pipeline`; messages are dispatched by a task of `executor`.
+    /// *Panics* if the pipeline has no bus.
+    pub fn new(executor: &GtkEventLoopAsyncExecutor, pipeline: &gst::Pipeline) -> Self {
+        let bus = pipeline.get_bus().expect("Pipeline has no bus");
+        let subscribers: Rc<RefCell<Vec<mpsc::UnboundedSender<gst::Message>>>> = Rc::new(RefCell::new(Vec::new()));
+
+        let (sender, receiver) = mpsc::unbounded();
+        let watch = bus.add_watch(move |_, message| {
+            glib::Continue(sender.unbounded_send(message.clone()).is_ok())
+        });
+
+        {
+            let subscribers = subscribers.clone();
+            executor.spawn_named("PipelineBus", receiver.for_each(move |message| {
+                let current = std::mem::replace(&mut *subscribers.borrow_mut(), Vec::new());
+                let alive: Vec<_> = current.into_iter()
+                    .filter(|subscriber| subscriber.unbounded_send(message.clone()).is_ok())
+                    .collect();
+                // Subscribers added while the message was delivered are kept
+                subscribers.borrow_mut().extend(alive);
+                Ok(())
+            }));
+        }
+
+        PipelineBus {
+            pipeline: pipeline.clone(),
+            subscribers,
+            watch: Some(watch),
+        }
+    }
+
+    /// Returns a stream of bus messages posted from now on
+    pub fn messages(&self) -> impl Stream<Item=gst::Message, Error=()> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers.borrow_mut().push(sender);
+        receiver
+    }
+
+    /// Returns a future that resolves when the pipeline reaches `state`,
+    /// or fails when an error message is posted first
+    pub fn state_reached(&self, state: gst::State) -> impl Future<Item=(), Error=glib::Error> {
+        let pipeline: gst::Object = self.pipeline.clone().upcast();
+
+        self.wait_for(move |message| {
+            match message.view() {
+                gst::MessageView::StateChanged(change) =>
+                    message.get_src().as_ref() == Some(&pipeline) && change.get_current() == state,
+                _ => false,
+            }
+        })
+    }
+
+    /// Returns a future that resolves at the end of the stream, or fails when an error message is posted first
+    pub fn eos(&self) -> impl Future<Item=(), Error=glib::Error> {
+        self.wait_for(|message| {
+            match message.view() {
+                gst::MessageView::Eos(_) => true,
+                _ => false,
+            }
+        })
+    }
+
+    /// Changes the state of the pipeline and returns a future that resolves when the state is reached
+    pub fn set_state(&self, state: gst::State) -> impl Future<Item=(), Error=glib::Error> {
+        let reached = self.state_reached(state);
+
+        futures::future::result(self.pipeline.set_state(state))
+            .map_err(|_| glib::Error::new(gst::CoreError::StateChange, "Failed to change the state of the pipeline"))
+            .and_then(|_| reached)
+    }
+
+    fn wait_for<F: Fn(&gst::Message) -> bool + 'static>(&self, matches: F) -> impl Future<Item=(), Error=glib::Error> {
+        self.messages()
+            .map_err(|()| glib::Error::new(gst::CoreError::Failed, "Pipeline bus watch was removed"))
+            .and_then(|message| {
+                match message.view() {
+                    gst::MessageView::Error(error) => Err(error.get_error()),
+                    _ => Ok(message),
+                }
+            })
+            .filter(move |message| matches(message))
+            .into_future()
+            .map_err(|(error, _)| error)
+            .and_then(|(message, _)| {
+                match message {
+                    Some(_) => Ok(()),
+                    None => Err(glib::Error::new(gst::CoreError::Failed, "Pipeline bus watch was removed")),
+                }
+            })
+    }
+}
+
+impl Drop for PipelineBus {
+    fn drop(&mut self) {
+        if let Some(watch) = self.watch.take() {
+            glib::source_remove(watch);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use futures::prelude::*;
+use futures::future;
+use gtk::prelude::*;
+
+use crate::promise::Promise;
+
+/// Error of loading an icon with `load_icon_async` or `load_gicon_async`
+#[derive(Debug, Clone)]
+pub enum IconError {
+    /// The icon theme has no icon with this name (or the `gio::Icon` rendered with `to_string()`)
+    NotFound(String),
+    /// The icon was found, but its image could not be loaded
+    Load(glib::Error),
+}
+
+impl std::fmt::Display for IconError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            IconError::NotFound(ref name) => write!(f, "icon {:?} not found in icon theme", name),
+            IconError::Load(ref error) => write!(f, "failed to load icon: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for IconError {}
+
+fn load_icon_info(icon_info: Option<gtk::IconInfo>, name: String) -> impl Future<Item=gdk_pixbuf::Pixbuf, Error=IconError> {
+    let icon_info = match icon_info {
+        Some(icon_info) => icon_info,
+        None => return future::Either::A(future::err(IconError::NotFound(name))),
+    };
+
+    let pixbuf = Promise::new();
+    {
+        let pixbuf = pixbuf.clone();
+        icon_info.load_icon_async(None::<&gio::Cancellable>, move |result| {
+            match result {
+                Ok(loaded) => pixbuf.resolve(loaded),
+                Err(error) => pixbuf.reject(error),
+            }
+        });
+    }
+
+    future::Either::B(pixbuf.map_err(IconError::Load))
+}
+
+/// Loads the icon `name` of `theme` at `size` pixels.
+///
+/// The icon is looked up on Gtk+ main thread (icon themes are not thread-safe, and lookups are cached by the theme);
+/// reading and decoding the image file, the expensive part, runs on the GIO worker pool,
+/// so toolbars and file lists with hundreds of icons don't hitch on first display.
+/// Returned future must be executed on Gtk+ main thread (e.g. with `GtkEventLoopAsyncExecutor::spawn`).
+///
+/// Example:
+/// ```rust
+/// let theme = gtk::IconTheme::get_default().unwrap();
+/// let image = image.clone();
+/// gtk_executor.spawn(
+///     load_icon_async(&theme, "folder-documents", 48, gtk::IconLookupFlags::FORCE_SIZE)
+///         .map(move |pixbuf| image.set_from_pixbuf(Some(&pixbuf)))
+///         .map_err(|error| eprintln!("{}", error))
+/// );
+/// ```
+pub fn load_icon_async(theme: &gtk::IconTheme, name: &str, size: i32, flags: gtk::IconLookupFlags)
+    -> impl Future<Item=gdk_pixbuf::Pixbuf, Error=IconError>
+{
+    load_icon_info(theme.lookup_icon(name, size, flags), name.to_string())
+}
+
+/// Loads `icon` (e.g. the icon of a file from `gio::FileInfo::get_icon`) using `theme` at `size` pixels,
+/// like `load_icon_async()`
+pub fn load_gicon_async(theme: &gtk::IconTheme, icon: &gio::Icon, size: i32, flags: gtk::IconLookupFlags)
+    -> impl Future<Item=gdk_pixbuf::Pixbuf, Error=IconError>
+{
+    let name = gio::IconExt::to_string(icon).map(|name| name.to_string()).unwrap_or_default();
+    load_icon_info(theme.lookup_by_gicon(icon, size, flags), name)
+}
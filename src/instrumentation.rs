@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+/// Timing of a single poll of a task, passed to `TaskInstrumentation::poll_finished`
+#[derive(Debug, Clone, Copy)]
+pub struct PollTiming<'a> {
+    /// Id of the polled task
+    pub id: usize,
+    /// Name of the task if it was spawned with a name
+    pub name: Option<&'a str>,
+    /// Time spent inside the task's `poll`
+    pub poll_duration: Duration,
+    /// Time between the wake notification and the start of the poll
+    pub wake_latency: Duration,
+}
+
+/// Cumulative time spent polling a task (or all tasks with the same name) on Gtk+ main thread.
+/// Returned by `GtkEventLoopAsyncExecutor::pending_task_poll_stats` and `poll_stats_by_name`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PollStats {
+    /// Number of polls
+    pub polls: u64,
+    /// Total time spent inside `poll`
+    pub total: Duration,
+    /// Longest single poll
+    pub max: Duration,
+}
+
+impl PollStats {
+    pub(crate) fn record(&mut self, poll_duration: Duration) {
+        self.polls += 1;
+        self.total += poll_duration;
+        self.max = self.max.max(poll_duration);
+    }
+}
+
+/// Hooks invoked around every poll of a task, for feeding external benchmarks and histograms.
+/// Installed with `GtkEventLoopAsyncExecutor::set_instrumentation`; hooks are invoked on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// struct Histogram(RefCell<hdrhistogram::Histogram<u64>>);
+///
+/// impl TaskInstrumentation for Histogram {
+///     fn poll_finished(&self, timing: &PollTiming) {
+///         self.0.borrow_mut().record(timing.poll_duration.subsec_micros() as u64).unwrap();
+///     }
+/// }
+/// ```
+pub trait TaskInstrumentation {
+    /// Called right before a task is polled
+    fn poll_started(&self, _id: usize, _name: Option<&str>) {
+    }
+
+    /// Called right after a task is polled
+    fn poll_finished(&self, _timing: &PollTiming) {
+    }
+}
@@ -1,10 +1,218 @@
 //! This crate provides basic building blocks for writing async GUI code with Gtk-rs:
 //! 1. `GtkEventLoopAsyncExecutor` - an executor for executing futures that may manipulate GUI widgets
 //! 2. `Promise` - an implementation of [futures::Future] that is often useful for GUI code
+//! 3. Bindings that feed streams of data into widgets (`bind_listbox`, `bind_progress`)
+//! 4. `run_app` - integration of async main functions with `gio::Application`
+//! 5. `WindowManager` - tracking of open windows for multi-window applications
+//! 6. Helpers for long-running GUI operations (`load_file_into_buffer`, `CancellationToken`, `BusyGuard`)
 
+#[cfg(feature = "gobject")]
+#[macro_use]
+extern crate glib;
+
+mod app;
+mod assistant;
+mod autosave;
+mod bind;
+mod builder;
+mod busy;
+mod cache;
+mod cancel;
+mod combinators;
+mod cooperative;
+#[cfg(feature = "compat")]
+pub mod compat;
+mod dbus_service;
+mod dialogs;
+#[cfg(feature = "download")]
+pub mod download;
+mod errors;
 mod executor;
+#[cfg(feature = "glib-only")]
+mod glib_executor;
+#[cfg(feature = "gobject")]
+mod gobject;
+#[cfg(feature = "gstreamer")]
+mod gstreamer;
+mod icons;
+mod instrumentation;
+mod logging;
+#[macro_use]
+mod macros;
+mod main_thread;
+mod notification;
+mod popover;
+pub mod prelude;
+mod print;
 mod promise;
+mod property;
+mod recording;
+#[cfg(feature = "relm")]
+mod relm_compat;
+mod search;
+mod single_flight;
+mod spawn;
+mod status;
+mod store;
+mod supervisor;
+mod sync;
+mod task;
+mod task_set;
+mod task_slot;
+mod task_table;
+pub mod test_harness;
+mod text;
+mod timer;
+#[cfg(feature = "tokio")]
+mod tokio_compat;
+#[cfg(unix)]
+mod unix_signal;
+mod validation;
+mod view_model;
+mod watchdog;
+mod weak;
+mod windows;
 
+pub use app::app_activations;
+pub use app::app_startup;
+pub use app::run_app;
+pub use assistant::AssistantChoice;
+pub use assistant::AssistantFlow;
+pub use autosave::DebouncedSaver;
+pub use bind::bind_collection;
+pub use bind::bind_entry_two_way;
+pub use bind::bind_image_pixbuf;
+pub use bind::bind_label_text;
+pub use bind::bind_listbox;
+pub use bind::bind_progress;
+pub use bind::bind_range_value;
+pub use bind::ListBoxBinding;
+pub use builder::builder_from_file_async;
+pub use builder::builder_object;
+pub use builder::BuilderError;
+pub use busy::spawn_busy;
+pub use busy::with_busy;
+pub use busy::with_busy_cursor;
+pub use busy::BusyCursorGuard;
+pub use busy::BusyGuard;
+pub use busy::BusyTargets;
+pub use cache::AsyncCache;
+pub use cancel::CancellationToken;
+pub use cancel::Cancelled;
+pub use combinators::join_on_main;
+pub use combinators::select_on_main;
+pub use cooperative::cooperative;
+pub use cooperative::Cooperative;
+pub use dbus_service::DBusMethodError;
+pub use dbus_service::DBusRegistration;
+pub use dbus_service::DBusService;
+pub use dialogs::choose_color;
+pub use dialogs::choose_font;
+pub use dialogs::choose_future;
+pub use dialogs::message_choice;
+pub use errors::bind_errors_to_infobar;
+pub use errors::PanicPolicy;
+pub use errors::TaskError;
+pub use errors::TaskErrorPolicy;
 pub use executor::GtkEventLoopAsyncExecutor;
+pub use executor::RemoteSpawner;
+pub use executor::RunUntilError;
+pub use executor::ShutdownReport;
+pub use executor::SpawnError;
+pub use executor::TaskEvent;
+#[cfg(feature = "glib-only")]
+pub use glib_executor::GlibMainContextExecutor;
+#[cfg(feature = "gobject")]
+pub use gobject::ExecutorObject;
+#[cfg(feature = "gstreamer")]
+pub use gstreamer::PipelineBus;
+pub use icons::load_gicon_async;
+pub use icons::load_icon_async;
+pub use icons::IconError;
+pub use instrumentation::PollStats;
+pub use instrumentation::PollTiming;
+pub use instrumentation::TaskInstrumentation;
+pub use logging::set_glib_logging;
+pub use logging::LOG_DOMAIN;
+pub use main_thread::MainThread;
+pub use main_thread::MainThreadCaller;
+pub use main_thread::MainThreadCell;
+pub use main_thread::MainThreadLazy;
+pub use notification::notify;
+pub use notification::NotificationResponse;
+pub use popover::popover_closed;
+pub use popover::popup_and_wait;
+pub use popover::popup_for_selection;
+pub use print::print_rendered_pages;
+pub use print::run_print_operation;
 pub use promise::Promise;
+pub use property::Property;
+pub use recording::RecordedTaskState;
+pub use recording::RecordingExecutor;
+#[cfg(feature = "relm")]
+pub use relm_compat::relm_messages;
+#[cfg(feature = "relm")]
+pub use relm_compat::spawn_emit;
+pub use search::live_search;
+pub use search::SearchUpdate;
+pub use single_flight::SingleFlight;
+pub use spawn::BoxUnitFuture;
+pub use spawn::GuiSpawn;
+pub use status::TaskStatusReporter;
+pub use store::Dispatcher;
+pub use store::Store;
+pub use supervisor::ChildHealth;
+pub use supervisor::ChildState;
+pub use supervisor::RestartStrategy;
+pub use supervisor::Supervisor;
+pub use supervisor::SupervisorHealth;
+pub use task::JoinError;
+pub use task::Priority;
+pub use task::SharedHandle;
+pub use task::TaskBuilder;
+pub use task::TaskHandle;
+pub use task_set::LocalTaskSet;
+pub use text::load_file_into_buffer;
+pub use text::DEFAULT_CHUNK_SIZE;
+pub use timer::delay;
+pub use timer::Delay;
+pub use timer::MockClock;
+#[cfg(unix)]
+pub use unix_signal::unix_signal_future;
+#[cfg(unix)]
+pub use unix_signal::unix_signal_stream;
+#[cfg(unix)]
+pub use unix_signal::Signal;
+pub use validation::FormValidator;
+pub use view_model::ViewModel;
+pub use view_model::ViewModelProxy;
+pub use watchdog::BlockedMainLoop;
+pub use watchdog::Watchdog;
+pub use weak::with_weak;
+pub use weak::WeakCaptured;
+pub use windows::run_window_modal;
+pub use windows::window_closed;
+pub use windows::WindowManager;
+
+#[cfg(feature = "macros")]
+pub use gtk_future_executor_macros::gtk_async_main;
+#[cfg(feature = "macros")]
+pub use gtk_future_executor_macros::gtk_async_test;
+
+/// Implementation details of the macros. Not a public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use futures;
+    pub use glib;
+    pub use gtk;
+    #[cfg(feature = "macros")]
+    pub use crate::test_harness::run_test;
 
+    /// Converts a `std::future::Future` into a futures 0.1 future that can be spawned on the executor
+    #[cfg(feature = "macros")]
+    pub fn compat<F, T, E>(f: F) -> impl futures::Future<Item=T, Error=E>
+        where F: std::future::Future<Output=Result<T, E>> + 'static
+    {
+        crate::compat::IntoFuture01::into_future01(f)
+    }
+}
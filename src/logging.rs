@@ -0,0 +1,51 @@
+use std::ffi::CString;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// GLib log domain of the crate's diagnostics
+pub const LOG_DOMAIN: &str = "gtk-future-executor";
+
+static GLIB_LOGGING: AtomicBool = AtomicBool::new(false);
+
+/// Routes the crate's diagnostics through GLib logging under the `LOG_DOMAIN` log domain
+/// instead of printing them to stderr.
+///
+/// Warnings (failed tasks reported by `TaskErrorPolicy::LogOnly`, dropped futures, ...) are logged
+/// with `G_LOG_LEVEL_WARNING`; in addition, task lifecycle events (start, finish, failure, cancellation)
+/// are logged with `G_LOG_LEVEL_DEBUG`, which GLib shows only if enabled with
+/// `G_MESSAGES_DEBUG=gtk-future-executor`. Messages end up wherever the GLib log writer sends them,
+/// e.g. the systemd journal.
+///
+/// The setting is process-wide; GLib logging is off by default.
+pub fn set_glib_logging(enabled: bool) {
+    GLIB_LOGGING.store(enabled, Ordering::SeqCst);
+}
+
+pub(crate) fn is_glib_logging() -> bool {
+    GLIB_LOGGING.load(Ordering::SeqCst)
+}
+
+fn g_log(level: glib_sys::GLogLevelFlags, message: &str) {
+    let domain = CString::new(LOG_DOMAIN).unwrap();
+    // A message with NUL bytes can't be passed to GLib as is
+    let message = CString::new(message.replace('\0', "\\0")).unwrap();
+    unsafe {
+        glib_sys::g_log(domain.as_ptr(), level, b"%s\0".as_ptr() as *const _, message.as_ptr());
+    }
+}
+
+/// Logs a diagnostic message: with GLib logging as a warning, otherwise to stderr
+pub(crate) fn warning(message: &str) {
+    if is_glib_logging() {
+        g_log(glib_sys::G_LOG_LEVEL_WARNING, message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// Logs a debug message if GLib logging is enabled
+pub(crate) fn debug(message: &str) {
+    if is_glib_logging() {
+        g_log(glib_sys::G_LOG_LEVEL_DEBUG, message);
+    }
+}
@@ -0,0 +1,226 @@
+/// Clones handles into a closure, eliminating inner-scope `let x = x.clone();` blocks.
+///
+/// Listed names are cloned (use this for `Promise`, `GtkEventLoopAsyncExecutor` and other strong handles).
+/// Names prefixed with `weak` must be GObjects (e.g. widgets); the closure captures a weak reference
+/// and upgrades it on every invocation. If the object was already destroyed, the closure returns `Default::default()`
+/// without running its body, so callbacks neither keep windows alive nor touch freed widgets.
+///
+/// Example:
+/// ```rust
+/// button.connect_clicked(capture!(promise, gtk_executor, weak result_label => move |_| {
+///     result_label.set_text("computing...");
+///     promise.resolve(());
+/// }));
+/// ```
+#[macro_export]
+macro_rules! capture {
+    (@munch [$($strong:ident)*] [$($weak:ident)*] weak $name:ident, $($rest:tt)*) => {
+        $crate::capture!(@munch [$($strong)*] [$($weak)* $name] $($rest)*)
+    };
+    (@munch [$($strong:ident)*] [$($weak:ident)*] weak $name:ident => $($closure:tt)*) => {
+        $crate::capture!(@closure [$($strong)*] [$($weak)* $name] $($closure)*)
+    };
+    (@munch [$($strong:ident)*] [$($weak:ident)*] $name:ident, $($rest:tt)*) => {
+        $crate::capture!(@munch [$($strong)* $name] [$($weak)*] $($rest)*)
+    };
+    (@munch [$($strong:ident)*] [$($weak:ident)*] $name:ident => $($closure:tt)*) => {
+        $crate::capture!(@closure [$($strong)* $name] [$($weak)*] $($closure)*)
+    };
+    (@closure [$($strong:ident)*] [$($weak:ident)*] move || $body:expr) => {
+        {
+            $( let $strong = $strong.clone(); )*
+            $( let $weak = $crate::__private::glib::object::ObjectExt::downgrade(&$weak); )*
+            move || {
+                $(
+                    let $weak = match $weak.upgrade() {
+                        Some(object) => object,
+                        None => return ::std::default::Default::default(),
+                    };
+                )*
+                $body
+            }
+        }
+    };
+    (@closure [$($strong:ident)*] [$($weak:ident)*] move |$($arg:pat),*| $body:expr) => {
+        {
+            $( let $strong = $strong.clone(); )*
+            $( let $weak = $crate::__private::glib::object::ObjectExt::downgrade(&$weak); )*
+            move |$($arg),*| {
+                $(
+                    let $weak = match $weak.upgrade() {
+                        Some(object) => object,
+                        None => return ::std::default::Default::default(),
+                    };
+                )*
+                $body
+            }
+        }
+    };
+    ($($tt:tt)*) => {
+        $crate::capture!(@munch [] [] $($tt)*)
+    };
+}
+
+/// Clones the listed variables and spawns the future on the executor.
+///
+/// `spawn!(executor; clone a, b; future)` is a shorthand for
+/// `{ let a = a.clone(); let b = b.clone(); executor.spawn(future) }`,
+/// cutting the let-clone noise of every `connect_*` + `spawn` pairing.
+/// `spawn!(executor, "name"; ...)` spawns a named task.
+///
+/// Example:
+/// ```rust
+/// button.connect_clicked(move |_| {
+///     spawn!(gtk_executor; clone result_label;
+///         cpu_pool.spawn_fn(move || future::ok(compute_fib(n)))
+///             .map(move |r| result_label.set_text(&format!("fib({}) = {}", n, r)))
+///     );
+/// });
+/// ```
+#[macro_export]
+macro_rules! spawn {
+    ($executor:expr, $task_name:expr; clone $($name:ident),+ ; $future:expr) => {
+        {
+            $( let $name = $name.clone(); )+
+            $executor.spawn_named($task_name, $future)
+        }
+    };
+    ($executor:expr, $task_name:expr; $future:expr) => {
+        $executor.spawn_named($task_name, $future)
+    };
+    ($executor:expr; clone $($name:ident),+ ; $future:expr) => {
+        {
+            $( let $name = $name.clone(); )+
+            $executor.spawn($future)
+        }
+    };
+    ($executor:expr; $future:expr) => {
+        $executor.spawn($future)
+    };
+}
+
+/// Generates a proxy type for a `ViewModel` with a method for every listed command.
+///
+/// Each generated method takes the command arguments, invokes the view model method of the same name
+/// through `ViewModelProxy::call()` (in a task named after the command) and returns a `Promise` of its result.
+///
+/// Example:
+/// ```rust
+/// view_model_proxy! {
+///     pub struct LoginProxy for LoginViewModel {
+///         fn login(user: String, password: String) -> Promise<Session, LoginError>;
+///         fn logout() -> Promise<(), ()>;
+///     }
+/// }
+///
+/// let proxy = LoginProxy::new(LoginViewModel::new(api).into_proxy(gtk_executor.clone()));
+/// login_button.connect_clicked(capture!(proxy, weak user_entry, weak password_entry => move |_| {
+///     proxy.login(entry_text(&user_entry), entry_text(&password_entry));
+/// }));
+/// ```
+#[macro_export]
+macro_rules! view_model_proxy {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $proxy:ident for $model:ty {
+            $(
+                $(#[$method_attr:meta])*
+                fn $method:ident($($arg:ident: $arg_ty:ty),*) -> Promise<$item:ty, $error:ty>;
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone)]
+        $vis struct $proxy($crate::ViewModelProxy<$model>);
+
+        impl $proxy {
+            /// Wraps `proxy` of the view model
+            $vis fn new(proxy: $crate::ViewModelProxy<$model>) -> Self {
+                $proxy(proxy)
+            }
+
+            /// Returns the underlying view model proxy
+            $vis fn inner(&self) -> &$crate::ViewModelProxy<$model> {
+                &self.0
+            }
+
+            $(
+                $(#[$method_attr])*
+                $vis fn $method(&self, $($arg: $arg_ty),*) -> $crate::Promise<$item, $error> {
+                    self.0.call(stringify!($method), move |model| model.$method($($arg),*))
+                }
+            )*
+        }
+    };
+}
+
+/// Races futures on the executor and runs the body of the arm whose future completes first, on Gtk+ main thread.
+/// The other futures are dropped.
+///
+/// Each arm is `pattern = future => body`. The pattern is matched against the `Result` of the future,
+/// so futures of different item and error types can be raced. A `CancellationToken` is raced
+/// with an arm for `token.cancelled()`, and the next item of a stream with an arm for `stream.into_future()`.
+/// `gui_select!(executor, "name", { ... })` spawns a named task.
+///
+/// Example:
+/// ```rust
+/// gui_select!(gtk_executor, {
+///     result = fetch_feed(url) => match result {
+///         Ok(items) => view.show(&items),
+///         Err(error) => view.show_error(&error),
+///     },
+///     _ = delay(Duration::from_secs(10)) => view.show_error("Timed out"),
+///     _ = cancel_token.cancelled() => view.clear(),
+/// });
+/// ```
+#[macro_export]
+macro_rules! gui_select {
+    (@future $future:expr) => {
+        $crate::__private::futures::Future::then(
+            $crate::__private::futures::IntoFuture::into_future($future),
+            |result| Ok::<_, ()>(result)
+        )
+    };
+    (@future $future:expr, $($rest:expr),+) => {
+        $crate::__private::futures::Future::map_err(
+            $crate::__private::futures::Future::map(
+                $crate::__private::futures::Future::select2(
+                    $crate::gui_select!(@future $future),
+                    $crate::gui_select!(@future $($rest),+)
+                ),
+                |winner| match winner {
+                    $crate::__private::futures::future::Either::A((result, _losers)) => $crate::__private::futures::future::Either::A(result),
+                    $crate::__private::futures::future::Either::B((result, _loser)) => $crate::__private::futures::future::Either::B(result),
+                }
+            ),
+            |_| ()
+        )
+    };
+    (@dispatch $winner:ident; $pattern:pat => $body:expr) => {
+        {
+            let $pattern = $winner;
+            $body;
+        }
+    };
+    (@dispatch $winner:ident; $pattern:pat => $body:expr, $($rest_pattern:pat => $rest_body:expr),+) => {
+        match $winner {
+            $crate::__private::futures::future::Either::A(result) => {
+                let $pattern = result;
+                $body;
+            },
+            $crate::__private::futures::future::Either::B(rest) => $crate::gui_select!(@dispatch rest; $($rest_pattern => $rest_body),+),
+        }
+    };
+    (@race $($pattern:pat = $future:expr => $body:expr),+) => {
+        $crate::__private::futures::Future::map(
+            $crate::gui_select!(@future $($future),+),
+            move |winner| $crate::gui_select!(@dispatch winner; $($pattern => $body),+)
+        )
+    };
+    ($executor:expr, { $($pattern:pat = $future:expr => $body:expr),+ $(,)* }) => {
+        $executor.spawn($crate::gui_select!(@race $($pattern = $future => $body),+))
+    };
+    ($executor:expr, $task_name:expr, { $($pattern:pat = $future:expr => $body:expr),+ $(,)* }) => {
+        $executor.spawn_named($task_name, $crate::gui_select!(@race $($pattern = $future => $body),+))
+    };
+}
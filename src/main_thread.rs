@@ -0,0 +1,484 @@
+use futures::prelude::*;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::rc::Rc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::ThreadId;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::executor::RemoteSpawner;
+use crate::executor::SpawnError;
+use crate::logging;
+use crate::promise::Promise;
+
+type Initializer<T> = Box<FnOnce() -> T + Send>;
+
+enum LazyState<T> {
+    Uninitialized(Initializer<T>),
+    Initializing(Vec<Promise<T, SpawnError>>),
+    Ready(T),
+    /// The initializer could not be run because the executor is shutting down or was dropped
+    Failed(SpawnError),
+}
+
+/// Runs the initializer of a `MainThreadLazy` on Gtk+ main thread.
+/// If it is dropped without running (the executor dropped the task), waiters are failed with `SpawnError::ShuttingDown`.
+struct InitTask<T> {
+    state: Arc<Mutex<LazyState<T>>>,
+    init: Option<Initializer<T>>,
+}
+
+impl<T: Clone> InitTask<T> {
+    fn run(mut self) {
+        let init = self.init.take().unwrap();
+        let value = init();
+
+        let previous = std::mem::replace(&mut *self.state.lock().unwrap(), LazyState::Ready(value.clone()));
+        if let LazyState::Initializing(waiters) = previous {
+            for waiter in waiters {
+                waiter.resolve(value.clone());
+            }
+        }
+    }
+}
+
+impl<T> Drop for InitTask<T> {
+    fn drop(&mut self) {
+        if self.init.is_none() {
+            return;
+        }
+
+        let previous = std::mem::replace(&mut *self.state.lock().unwrap(), LazyState::Failed(SpawnError::ShuttingDown));
+        if let LazyState::Initializing(waiters) = previous {
+            for waiter in waiters {
+                waiter.reject(SpawnError::ShuttingDown);
+            }
+        }
+    }
+}
+
+/// A value that is computed on Gtk+ main thread when it is first awaited and cached afterwards.
+///
+/// The initializer runs once, in a task on Gtk+ main loop, no matter from which thread and how many times
+/// the value is awaited. This suits expensive one-time setup that must happen on Gtk+ main thread,
+/// like loading icon themes or installing CSS providers. Awaiting fails with `SpawnError::ShuttingDown`
+/// if the executor is shut down before the initializer runs.
+///
+/// `MainThreadLazy` is cloneable (all clones refer to the same value), `Send` and `Sync`.
+///
+/// Example:
+/// ```rust
+/// let styles = MainThreadLazy::new(&gtk_executor.remote(), || {
+///     let provider = gtk::CssProvider::new();
+///     provider.load_from_data(APP_CSS).unwrap();
+///     gtk::StyleContext::add_provider_for_screen(&gdk::Screen::get_default().unwrap(), &provider, gtk::STYLE_PROVIDER_PRIORITY_APPLICATION);
+/// });
+///
+/// // In every window constructor
+/// gtk_executor.spawn(styles.get().map_err(|_| ()).map(move |()| window.show_all()));
+/// ```
+pub struct MainThreadLazy<T> {
+    spawner: RemoteSpawner,
+    state: Arc<Mutex<LazyState<T>>>,
+}
+
+impl<T> Clone for MainThreadLazy<T> {
+    fn clone(&self) -> Self {
+        MainThreadLazy {
+            spawner: self.spawner.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> MainThreadLazy<T> {
+    /// Constructs a value that is computed with `init` on the main thread of the executor of `spawner`
+    pub fn new<F: FnOnce() -> T + Send + 'static>(spawner: &RemoteSpawner, init: F) -> Self {
+        MainThreadLazy {
+            spawner: spawner.clone(),
+            state: Arc::new(Mutex::new(LazyState::Uninitialized(Box::new(init)))),
+        }
+    }
+
+    /// Returns a future that resolves with the value. The first poll of the first such future starts the initializer.
+    pub fn get(&self) -> impl Future<Item=T, Error=SpawnError> {
+        let lazy = self.clone();
+        futures::future::lazy(move || lazy.start())
+    }
+
+    /// Returns the value if it is already computed
+    pub fn try_get(&self) -> Option<T> {
+        match *self.state.lock().unwrap() {
+            LazyState::Ready(ref value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn start(&self) -> Promise<T, SpawnError> {
+        let promise = Promise::new();
+
+        let init = {
+            let mut state = self.state.lock().unwrap();
+            match *state {
+                LazyState::Ready(ref value) => {
+                    promise.resolve(value.clone());
+                    return promise;
+                },
+                LazyState::Failed(error) => {
+                    promise.reject(error);
+                    return promise;
+                },
+                LazyState::Initializing(ref mut waiters) => {
+                    waiters.push(promise.clone());
+                    return promise;
+                },
+                LazyState::Uninitialized(_) => {},
+            }
+
+            match std::mem::replace(&mut *state, LazyState::Initializing(vec![promise.clone()])) {
+                LazyState::Uninitialized(init) => init,
+                _ => unreachable!(),
+            }
+        };
+
+        let task = InitTask {
+            state: self.state.clone(),
+            init: Some(init),
+        };
+
+        // If spawning fails, the task is dropped and fails the waiters
+        let _ = self.spawner.spawn(futures::future::lazy(move || {
+            task.run();
+            Ok(())
+        }));
+
+        promise
+    }
+}
+
+/// Completes a promise with the result of a closure sent to Gtk+ main thread.
+/// If it is dropped without a result (the executor dropped the task), the promise is failed with `SpawnError::ShuttingDown`.
+struct Reply<R> {
+    promise: Option<Promise<R, SpawnError>>,
+}
+
+impl<R> Reply<R> {
+    fn send(mut self, result: R) {
+        self.promise.take().unwrap().resolve(result);
+    }
+}
+
+impl<R> Drop for Reply<R> {
+    fn drop(&mut self) {
+        if let Some(promise) = self.promise.take() {
+            promise.reject(SpawnError::ShuttingDown);
+        }
+    }
+}
+
+/// Runs `f` in a task on the main thread of the executor of `spawner` and returns a promise of its result
+fn call_on_main<R, F>(spawner: &RemoteSpawner, f: F) -> Promise<R, SpawnError>
+    where F: FnOnce() -> R + Send + 'static,
+          R: Send + 'static
+{
+    let promise = Promise::new();
+    let reply = Reply { promise: Some(promise.clone()) };
+
+    // If spawning fails, the reply is dropped and fails the promise
+    let _ = spawner.spawn(futures::future::lazy(move || {
+        reply.send(f());
+        Ok(())
+    }));
+
+    promise
+}
+
+/// A handle for running closures on Gtk+ main thread from any thread, e.g. for querying widget state
+/// from a worker thread as part of its own async flow.
+///
+/// `MainThreadCaller` is cloneable, `Send` and `Sync`. Calls fail with `SpawnError::ShuttingDown`
+/// if the executor is shut down before they run.
+///
+/// Example:
+/// ```rust
+/// let caller = MainThreadCaller::new(&gtk_executor);
+/// let selection = MainThread::new(tree_view.get_selection());
+///
+/// cpu_pool.spawn_fn(move || {
+///     caller.call(move || selected_paths(&selection))
+///         .map_err(|_| ())
+///         .and_then(|paths| export(paths))
+/// })
+/// ```
+#[derive(Clone)]
+pub struct MainThreadCaller {
+    spawner: RemoteSpawner,
+}
+
+impl MainThreadCaller {
+    /// Constructs caller that runs closures on the main thread of `executor`
+    pub fn new(executor: &GtkEventLoopAsyncExecutor) -> Self {
+        MainThreadCaller {
+            spawner: executor.remote(),
+        }
+    }
+
+    /// Runs `f` on Gtk+ main thread on the next main loop iteration and returns a promise of its result
+    pub fn call<R, F>(&self, f: F) -> Promise<R, SpawnError>
+        where F: FnOnce() -> R + Send + 'static,
+              R: Send + 'static
+    {
+        call_on_main(&self.spawner, f)
+    }
+
+    /// Runs `f` on Gtk+ main thread and blocks the calling thread until it returns.
+    /// This is meant for synchronous APIs (e.g. of plugins) that run on worker threads and must read UI state.
+    ///
+    /// Panics if called on Gtk+ main thread, where waiting for the main loop would deadlock.
+    /// Fails with `SpawnError::ShuttingDown` if the executor is shut down before `f` runs.
+    pub fn block_on_main<R, F>(&self, f: F) -> Result<R, SpawnError>
+        where F: FnOnce() -> R + Send + 'static,
+              R: Send + 'static
+    {
+        if std::thread::current().id() == self.spawner.owner_thread() {
+            panic!("MainThreadCaller::block_on_main() was called on Gtk+ main thread; this would deadlock");
+        }
+
+        call_on_main(&self.spawner, f).wait()
+    }
+}
+
+impl From<RemoteSpawner> for MainThreadCaller {
+    fn from(spawner: RemoteSpawner) -> Self {
+        MainThreadCaller {
+            spawner,
+        }
+    }
+}
+
+static NEXT_CELL_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Values of `MainThreadCell`s owned by this thread, by cell id. Each value is an `Rc<RefCell<T>>`.
+    static CELLS: RefCell<HashMap<usize, Box<Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Identifies the value of a `MainThreadCell`; the value is dropped on its thread when the last token is dropped
+struct CellToken {
+    id: usize,
+    owner: ThreadId,
+    spawner: RemoteSpawner,
+}
+
+impl CellToken {
+    fn remove(id: usize) {
+        // The value is taken out first, so that its destructor may use other cells
+        let value = CELLS.try_with(|cells| cells.borrow_mut().remove(&id)).ok();
+        drop(value);
+    }
+}
+
+impl Drop for CellToken {
+    fn drop(&mut self) {
+        let id = self.id;
+        if std::thread::current().id() == self.owner {
+            CellToken::remove(id);
+        } else {
+            // If the executor is gone, the value is freed with the thread-local storage of its thread
+            let _ = self.spawner.spawn(futures::future::lazy(move || {
+                CellToken::remove(id);
+                Ok(())
+            }));
+        }
+    }
+}
+
+/// Owns data that may only be used on Gtk+ main thread (widgets, other GObjects, `Rc`s) and lets any thread use it.
+///
+/// The data stays on the thread that created the cell; the cell itself is only a token that is `Send` and `Sync`.
+/// `with()` and `with_mut()` send a closure to Gtk+ main loop, run it with the data there and return a `Promise`
+/// of its result. This codifies the pattern of background code needing occasional access to widgets.
+/// Calls fail with `SpawnError::ShuttingDown` if the executor is shut down before they run.
+///
+/// `MainThreadCell` is cloneable (all clones refer to the same data). The data is dropped on Gtk+ main thread
+/// when the last clone is dropped.
+///
+/// Example:
+/// ```rust
+/// let status = MainThreadCell::new(&gtk_executor, status_label.clone());
+///
+/// std::thread::spawn(move || {
+///     for (index, file) in files.iter().enumerate() {
+///         index_file(file);
+///         let text = format!("Indexed {} of {}", index + 1, files.len());
+///         status.with(move |label| label.set_text(&text));
+///     }
+/// });
+/// ```
+pub struct MainThreadCell<T> {
+    token: Arc<CellToken>,
+    _data: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for MainThreadCell<T> {
+    fn clone(&self) -> Self {
+        MainThreadCell {
+            token: self.token.clone(),
+            _data: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> MainThreadCell<T> {
+    /// Moves `value` into a cell that is accessed on the main thread of `executor`. Must be called on that thread.
+    pub fn new(executor: &GtkEventLoopAsyncExecutor, value: T) -> Self {
+        let id = NEXT_CELL_ID.fetch_add(1, Ordering::SeqCst);
+        CELLS.with(|cells| cells.borrow_mut().insert(id, Box::new(Rc::new(RefCell::new(value)))));
+
+        MainThreadCell {
+            token: Arc::new(CellToken {
+                id,
+                owner: std::thread::current().id(),
+                spawner: executor.remote(),
+            }),
+            _data: PhantomData,
+        }
+    }
+
+    /// Runs `f` with the data on Gtk+ main thread and returns a promise of its result
+    pub fn with<R, F>(&self, f: F) -> Promise<R, SpawnError>
+        where F: FnOnce(&T) -> R + Send + 'static,
+              R: Send + 'static
+    {
+        self.run(move |value| f(&value.borrow()))
+    }
+
+    /// Runs `f` with mutable access to the data on Gtk+ main thread and returns a promise of its result
+    pub fn with_mut<R, F>(&self, f: F) -> Promise<R, SpawnError>
+        where F: FnOnce(&mut T) -> R + Send + 'static,
+              R: Send + 'static
+    {
+        self.run(move |value| f(&mut value.borrow_mut()))
+    }
+
+    fn run<R, F>(&self, f: F) -> Promise<R, SpawnError>
+        where F: FnOnce(&RefCell<T>) -> R + Send + 'static,
+              R: Send + 'static
+    {
+        let token = self.token.clone();
+        call_on_main(&self.token.spawner, move || {
+            let value = CELLS.with(|cells| {
+                cells.borrow().get(&token.id)
+                    .and_then(|value| value.downcast_ref::<Rc<RefCell<T>>>())
+                    .cloned()
+            });
+            // The token is alive, so the value is present
+            let value = value.expect("value of MainThreadCell is missing");
+            f(&value)
+        })
+    }
+}
+
+/// A pointer to data that may only be used on the thread that created it (normally Gtk+ main thread).
+///
+/// `MainThread` is `Send` and `Sync`, so structs holding widgets can be moved through futures that must be `Send`,
+/// but its `Deref` checks the current thread: accessing the data on another thread panics with a message naming
+/// both threads instead of corrupting widget state. Hot paths that have already established the thread
+/// may skip the check with the unsafe `get_unchecked()`.
+///
+/// If `MainThread` is dropped on another thread, the data is leaked (with a message on stderr) rather than
+/// dropped on the wrong thread.
+///
+/// Example:
+/// ```rust
+/// let label = MainThread::new(status_label.clone());
+/// // `download` requires a `Send` continuation; it is executed on Gtk+ main thread
+/// gtk_executor.remote().spawn(download(url).map(move |_| label.set_text("Done")))?;
+/// ```
+pub struct MainThread<T> {
+    value: ManuallyDrop<T>,
+    owner: ThreadId,
+}
+
+// Safety: the value is only accessed (and dropped) on the owner thread, which `check()` enforces
+unsafe impl<T> Send for MainThread<T> {}
+unsafe impl<T> Sync for MainThread<T> {}
+
+impl<T> MainThread<T> {
+    /// Wraps `value` that may then be used only on the current thread
+    pub fn new(value: T) -> Self {
+        MainThread {
+            value: ManuallyDrop::new(value),
+            owner: std::thread::current().id(),
+        }
+    }
+
+    /// Returns `true` if the data may be accessed on the current thread
+    pub fn is_accessible(&self) -> bool {
+        std::thread::current().id() == self.owner
+    }
+
+    /// Returns the data; panics if called on another thread
+    pub fn into_inner(mut self) -> T {
+        self.check();
+        let value = unsafe { ManuallyDrop::take(&mut self.value) };
+        std::mem::forget(self);
+        value
+    }
+
+    /// Returns the data without checking the current thread.
+    ///
+    /// # Safety
+    /// Must be called on the thread that created the `MainThread` (see `is_accessible()`).
+    pub unsafe fn get_unchecked(&self) -> &T {
+        debug_assert!(self.is_accessible(), "MainThread::get_unchecked() called on a foreign thread");
+        &self.value
+    }
+
+    fn check(&self) {
+        if !self.is_accessible() {
+            panic!(
+                "MainThread value created on thread {:?} was accessed on thread {:?}; it may only be used on the thread that created it",
+                self.owner,
+                std::thread::current().id()
+            );
+        }
+    }
+}
+
+impl<T> Deref for MainThread<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.check();
+        &self.value
+    }
+}
+
+impl<T> DerefMut for MainThread<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.check();
+        &mut self.value
+    }
+}
+
+impl<T> Drop for MainThread<T> {
+    fn drop(&mut self) {
+        if self.is_accessible() {
+            unsafe { ManuallyDrop::drop(&mut self.value) };
+        } else {
+            logging::warning(&format!(
+                "MainThread value was dropped on thread {:?} instead of {:?}; leaking it",
+                std::thread::current().id(), self.owner
+            ));
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use futures::prelude::*;
+use futures::sync::mpsc;
+use gio::prelude::*;
+use glib::ToVariant;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+static NEXT_NOTIFICATION_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Target of the notification action for a click on the notification itself; buttons have targets prefixed with "button:"
+const DEFAULT_TARGET: &str = "default";
+const BUTTON_TARGET_PREFIX: &str = "button:";
+
+/// Sends a desktop notification with `title`, `body` and one button per `(label, action)` pair,
+/// and returns a future that resolves with the user's response:
+/// `Some(action)` for a click on a button, `None` for a click on the notification itself.
+///
+/// Clicks are delivered through a temporary action of `app` (the application must be registered,
+/// e.g. be running with `run_app`), which is removed when the returned future is dropped.
+/// The future never resolves if the notification is dismissed, as notification servers do not report that;
+/// drop it (e.g. together with the window that awaits it) when the response is not needed anymore.
+/// A notification sent with the same `id` replaces this one.
+///
+/// Example:
+/// ```rust
+/// export_project(&project)
+///     .and_then(move |folder| {
+///         notify(&app, "export", "Export finished", &folder.display().to_string(), &[("Open folder", "open")])
+///             .map(move |action| if action == Some("open".to_string()) { open_folder(&folder) })
+///             .map_err(|()| unreachable!())
+///     })
+/// ```
+pub fn notify<A: IsA<gio::Application>>(app: &A, id: &str, title: &str, body: &str, actions: &[(&str, &str)]) -> NotificationResponse {
+    let app: gio::Application = app.clone().upcast();
+    let action_name = format!("gtk-future-executor-notification-{}", NEXT_NOTIFICATION_ID.fetch_add(1, Ordering::SeqCst));
+    let (sender, receiver) = mpsc::unbounded();
+
+    let action = gio::SimpleAction::new(&action_name, Some(glib::VariantTy::new("s").unwrap()));
+    action.connect_activate(move |_, parameter| {
+        let target = parameter.as_ref().and_then(|parameter| parameter.get_str().map(|target| target.to_string()));
+        let response = match target {
+            Some(ref target) if target.starts_with(BUTTON_TARGET_PREFIX) => Some(target[BUTTON_TARGET_PREFIX.len()..].to_string()),
+            _ => None,
+        };
+        let _ = sender.unbounded_send(response);
+    });
+    app.add_action(&action);
+
+    let detailed_action = format!("app.{}", action_name);
+    let notification = gio::Notification::new(title);
+    notification.set_body(Some(body));
+    notification.set_default_action_and_target_value(&detailed_action, Some(&DEFAULT_TARGET.to_variant()));
+    for &(label, action) in actions {
+        let target = format!("{}{}", BUTTON_TARGET_PREFIX, action);
+        notification.add_button_with_target_value(label, &detailed_action, Some(&target.to_variant()));
+    }
+    app.send_notification(Some(id), &notification);
+
+    NotificationResponse {
+        app,
+        action_name,
+        receiver,
+    }
+}
+
+/// Future returned by `notify`; resolves with the action clicked by the user (`None` for the notification itself)
+#[must_use = "futures do nothing unless polled"]
+pub struct NotificationResponse {
+    app: gio::Application,
+    action_name: String,
+    receiver: mpsc::UnboundedReceiver<Option<String>>,
+}
+
+impl Future for NotificationResponse {
+    type Item = Option<String>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<String>, ()> {
+        match self.receiver.poll()? {
+            Async::Ready(Some(response)) => Ok(Async::Ready(response)),
+            // The sender lives in the action, which is removed only in `drop`
+            Async::Ready(None) | Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+impl Drop for NotificationResponse {
+    fn drop(&mut self) {
+        self.app.remove_action(&self.action_name);
+    }
+}
@@ -0,0 +1,88 @@
+use futures::prelude::*;
+use futures::future::Either;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::promise::Promise;
+
+/// Returns a future that resolves when `popover` is closed next time,
+/// e.g. because the user clicked outside of it or pressed Escape
+pub fn popover_closed<P: IsA<gtk::Popover>>(popover: &P) -> impl Future<Item=(), Error=()> {
+    let promise = Promise::new();
+
+    {
+        // The handler disconnects itself, so that reusing the popover doesn't accumulate handlers
+        let handler_id = Rc::new(RefCell::new(None));
+        let pending = RefCell::new(Some(promise.clone()));
+        let handler = {
+            let handler_id = handler_id.clone();
+            popover.connect_closed(move |popover| {
+                if let Some(id) = handler_id.borrow_mut().take() {
+                    popover.disconnect(id);
+                }
+                if let Some(promise) = pending.borrow_mut().take() {
+                    promise.resolve(());
+                }
+            })
+        };
+        *handler_id.borrow_mut() = Some(handler);
+    }
+
+    promise
+}
+
+/// Shows `popover` and returns a future that resolves when it is closed
+pub fn popup_and_wait<P: IsA<gtk::Popover>>(popover: &P) -> impl Future<Item=(), Error=()> {
+    let closed = popover_closed(popover);
+    popover.show_all();
+    closed
+}
+
+/// Shows `popover` and returns a future that resolves with the selection made in it:
+/// the popover's widgets resolve `selection` with the user's choice, which closes the popover.
+/// Resolves with `None` if the popover is closed without a selection (or `selection` is rejected).
+///
+/// This lets pickers and confirmation popovers be created, shown and awaited inside an async function.
+///
+/// Example:
+/// ```rust
+/// let selection = Promise::new();
+/// let popover = gtk::Popover::new(Some(&color_button));
+/// let palette = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+/// for &color in &["red", "green", "blue"] {
+///     let button = gtk::Button::new_with_label(color);
+///     let selection = selection.clone();
+///     button.connect_clicked(move |_| selection.resolve(color));
+///     palette.add(&button);
+/// }
+/// popover.add(&palette);
+///
+/// popup_for_selection(&popover, &selection).map(move |color| {
+///     if let Some(color) = color {
+///         document.set_highlight(color);
+///     }
+/// })
+/// ```
+pub fn popup_for_selection<P, T>(popover: &P, selection: &Promise<T, ()>) -> impl Future<Item=Option<T>, Error=()>
+    where P: IsA<gtk::Popover>,
+          T: 'static
+{
+    let popover: gtk::Popover = popover.clone().upcast();
+    let closed = popup_and_wait(&popover);
+
+    // A selection that is made right before the popover closes wins, as the selection is polled first
+    selection.clone().select2(closed).then(move |result| {
+        match result {
+            Ok(Either::A((selected, _))) => {
+                popover.hide();
+                Ok(Some(selected))
+            },
+            Ok(Either::B(((), _))) => Ok(None),
+            Err(_) => {
+                popover.hide();
+                Ok(None)
+            },
+        }
+    })
+}
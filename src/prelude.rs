@@ -0,0 +1,35 @@
+//! The common surface of the crate in one import.
+//!
+//! ```rust
+//! use gtk_future_executor::prelude::*;
+//! ```
+//!
+//! brings in the executor, `Promise`, timers, cancellation, the most used helpers,
+//! futures 0.1 traits (`Future`, `Stream`, `IntoFuture`, ...) and Gtk+ extension traits.
+
+pub use futures::prelude::*;
+pub use gtk::prelude::*;
+
+pub use crate::bind::bind_listbox;
+pub use crate::bind::bind_progress;
+pub use crate::busy::spawn_busy;
+pub use crate::busy::with_busy;
+pub use crate::busy::with_busy_cursor;
+pub use crate::busy::BusyTargets;
+pub use crate::cancel::CancellationToken;
+#[cfg(feature = "compat")]
+pub use crate::compat::IntoFuture01;
+#[cfg(feature = "compat")]
+pub use crate::compat::IntoFuture03;
+pub use crate::cooperative::cooperative;
+pub use crate::executor::GtkEventLoopAsyncExecutor;
+pub use crate::promise::Promise;
+pub use crate::property::Property;
+pub use crate::spawn::GuiSpawn;
+pub use crate::task::Priority;
+pub use crate::timer::delay;
+pub use crate::timer::Delay;
+pub use crate::weak::with_weak;
+pub use crate::weak::WeakCaptured;
+pub use crate::windows::window_closed;
+pub use crate::windows::WindowManager;
@@ -0,0 +1,139 @@
+use futures::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::promise::Promise;
+use crate::task::TaskHandle;
+
+/// Runs `operation` (shows the print dialog for `gtk::PrintOperationAction::PrintDialog`) without blocking
+/// in a nested main loop, and returns a future that resolves with the result once printing is finished
+/// (`Apply`) or cancelled (`Cancel`). Fails with the error of the operation if printing fails.
+///
+/// The operation is run asynchronously (see `gtk::PrintOperation::set_allow_async`) where the platform supports it;
+/// elsewhere the future resolves once `run()` returns.
+///
+/// Example:
+/// ```rust
+/// let operation = gtk::PrintOperation::new();
+/// print_rendered_pages(&gtk_executor, &operation, render_report_pages(&report, &cpu_pool), |context, page| {
+///     let cr = context.get_cairo_context().unwrap();
+///     page.draw(&cr);
+/// });
+/// gtk_executor.spawn_fallible(
+///     run_print_operation(&operation, gtk::PrintOperationAction::PrintDialog, Some(&window))
+///         .map(|result| if result == gtk::PrintOperationResult::Apply { status.set_text("Report printed") })
+/// );
+/// ```
+pub fn run_print_operation<W: IsA<gtk::Window>>(operation: &gtk::PrintOperation, action: gtk::PrintOperationAction, parent: Option<&W>)
+    -> impl Future<Item=gtk::PrintOperationResult, Error=glib::Error>
+{
+    let promise = Promise::new();
+    let pending = Rc::new(RefCell::new(Some(promise.clone())));
+
+    {
+        let pending = pending.clone();
+        operation.connect_done(move |operation, result| {
+            if let Some(promise) = pending.borrow_mut().take() {
+                complete(&promise, operation, result);
+            }
+        });
+    }
+
+    operation.set_allow_async(true);
+    match operation.run(action, parent) {
+        Ok(gtk::PrintOperationResult::InProgress) => {},
+        Ok(result) => {
+            // Ran synchronously; `done` may or may not have been emitted
+            if let Some(promise) = pending.borrow_mut().take() {
+                complete(&promise, operation, result);
+            }
+        },
+        Err(error) => {
+            if let Some(promise) = pending.borrow_mut().take() {
+                promise.reject(error);
+            }
+        },
+    }
+
+    promise
+}
+
+fn complete(promise: &Promise<gtk::PrintOperationResult, glib::Error>, operation: &gtk::PrintOperation, result: gtk::PrintOperationResult) {
+    match (result, operation.get_error()) {
+        (gtk::PrintOperationResult::Error, Err(error)) => promise.reject(error),
+        (result, _) => promise.resolve(result),
+    }
+}
+
+struct RenderedPages<T> {
+    pages: Vec<T>,
+    /// Set once all pages are rendered
+    complete: bool,
+    rendering: Option<TaskHandle<(), ()>>,
+}
+
+/// Makes `operation` print the pages produced by `pages`, one page per item, drawing each page with `draw`.
+///
+/// `pages` is executed on `executor` right away, so pages may be rendered (e.g. laid out or rasterized
+/// on a background pool) while the user is still in the print dialog. Pagination waits until the stream ends;
+/// `draw` is called from the `draw-page` signal with the pre-rendered page. If the stream fails, printing is cancelled.
+/// Rendering stops when the operation is done.
+pub fn print_rendered_pages<S, D>(executor: &GtkEventLoopAsyncExecutor, operation: &gtk::PrintOperation, pages: S, draw: D)
+    where S: Stream<Error=()> + 'static,
+          D: Fn(&gtk::PrintContext, &S::Item) + 'static
+{
+    let state = Rc::new(RefCell::new(
+        RenderedPages {
+            pages: Vec::new(),
+            complete: false,
+            rendering: None,
+        }
+    ));
+
+    let rendering = {
+        let state = state.clone();
+        let failed_operation = operation.clone();
+        let rendered = state.clone();
+        executor.task().name("print_rendered_pages").spawn_with_handle(
+            pages
+                .for_each(move |page| {
+                    state.borrow_mut().pages.push(page);
+                    Ok(())
+                })
+                .then(move |result| {
+                    match result {
+                        Ok(()) => rendered.borrow_mut().complete = true,
+                        Err(()) => failed_operation.cancel(),
+                    }
+                    Ok::<(), ()>(())
+                })
+        )
+    };
+    state.borrow_mut().rendering = Some(rendering);
+
+    {
+        let state = state.clone();
+        operation.connect_paginate(move |operation, _| {
+            let state = state.borrow();
+            if state.complete {
+                operation.set_n_pages(state.pages.len() as i32);
+            }
+            state.complete
+        });
+    }
+    {
+        let state = state.clone();
+        operation.connect_draw_page(move |_, context, page| {
+            if let Some(page) = state.borrow().pages.get(page as usize) {
+                draw(context, page);
+            }
+        });
+    }
+    operation.connect_done(move |_, _| {
+        // Dropping the handle stops rendering that is still running, e.g. when printing was cancelled
+        let rendering = state.borrow_mut().rendering.take();
+        drop(rendering);
+    });
+}
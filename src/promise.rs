@@ -1,5 +1,7 @@
 use futures::prelude::*;
 
+use crate::sync::Mutex;
+
 #[derive(Debug)]
 struct PromiseBackend<T, E> {
     result: Option<Result<T, E>>,
@@ -9,6 +11,7 @@ struct PromiseBackend<T, E> {
 /// `Promise` is a future that can be completed or failed with `resolve` or `reject` methods.
 /// 
 /// `Promise` object is freely cloneable (all clones refer to the same underlying object) and is thread-safe.
+/// With feature `parking_lot` its state is guarded by `parking_lot::Mutex` instead of `std::sync::Mutex`.
 /// 
 /// `Promise` objects are handy for integrating `Future`-based code with non-`Future` based code.
 /// 
@@ -39,7 +42,7 @@ struct PromiseBackend<T, E> {
 /// ```
 #[derive(Debug, Clone)]
 pub struct Promise<T, E> {
-    backend: std::sync::Arc<std::sync::Mutex<PromiseBackend<T, E>>>,
+    backend: std::sync::Arc<Mutex<PromiseBackend<T, E>>>,
 }
 
 impl<T, E> Promise<T, E> {
@@ -47,7 +50,7 @@ impl<T, E> Promise<T, E> {
     pub fn new() -> Promise<T, E> {
         Promise {
             backend: std::sync::Arc::new(
-                std::sync::Mutex::new(
+                Mutex::new(
                     PromiseBackend {
                         result: None,
                         waiting_tasks: vec![],
@@ -60,7 +63,7 @@ impl<T, E> Promise<T, E> {
     /// Complete the promise with specified value.
     /// Once this method is called, no further calls to `resolve()` or `reject()` should be made.
     pub fn resolve(&self, result: T) {
-        let mut backend = self.backend.lock().unwrap();
+        let mut backend = self.backend.lock();
 
         backend.result = Some(Ok(result));
         for task in &backend.waiting_tasks {
@@ -73,7 +76,7 @@ impl<T, E> Promise<T, E> {
     /// Complete the promise with specified error.
     /// Once this method is called, no further calls to `resolve()` or `reject()` should be made.
     pub fn reject(&self, error: E) {
-        let mut backend = self.backend.lock().unwrap();
+        let mut backend = self.backend.lock();
 
         backend.result = Some(Err(error));
         for task in &backend.waiting_tasks {
@@ -89,7 +92,7 @@ impl <T, E> Future for Promise<T, E> {
     type Error = E;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let mut guard = self.backend.lock().unwrap();
+        let mut guard = self.backend.lock();
         let backend = &mut *guard;
 
         match backend.result {
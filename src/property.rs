@@ -0,0 +1,292 @@
+use futures::prelude::*;
+use futures::sync::mpsc;
+use glib::object::ObjectExt;
+use glib::object::ObjectType;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::rc::Weak;
+
+/// Observer of a property; returns `false` to unsubscribe
+type Observer<T> = Box<FnMut(&T) -> bool>;
+
+struct PropertyState<T> {
+    value: T,
+    observers: Vec<Observer<T>>,
+}
+
+/// A value living on Gtk+ main thread that notifies about its changes.
+///
+/// `set()` stores the value and notifies observers: streams returned by `changes()`
+/// and GObject properties bound with `bind_to()`. This is the building block for keeping
+/// widgets in sync with application state that is updated by async tasks.
+///
+/// `Property` is cloneable (all clones refer to the same value) and may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let status = Property::new("Idle".to_string());
+/// status.bind_to(&status_label, "label");
+///
+/// gtk_executor.spawn(download(url).then(move |result| {
+///     status.set(match result {
+///         Ok(_) => "Done".to_string(),
+///         Err(error) => format!("Failed: {}", error),
+///     });
+///     Ok(())
+/// }));
+/// ```
+pub struct Property<T> {
+    state: Rc<RefCell<PropertyState<T>>>,
+}
+
+impl<T> Clone for Property<T> {
+    fn clone(&self) -> Self {
+        Property {
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// A reference to a property that does not keep it alive
+struct WeakProperty<T> {
+    state: Weak<RefCell<PropertyState<T>>>,
+}
+
+impl<T> WeakProperty<T> {
+    fn upgrade(&self) -> Option<Property<T>> {
+        self.state.upgrade().map(|state| Property { state })
+    }
+}
+
+impl<T> Property<T> {
+    fn downgrade(&self) -> WeakProperty<T> {
+        WeakProperty {
+            state: Rc::downgrade(&self.state),
+        }
+    }
+}
+
+impl<T: Clone + 'static> Property<T> {
+    /// Constructs a property with initial `value`
+    pub fn new(value: T) -> Self {
+        Property {
+            state: Rc::new(RefCell::new(
+                PropertyState {
+                    value,
+                    observers: Vec::new(),
+                }
+            ))
+        }
+    }
+
+    /// Returns the current value
+    pub fn get(&self) -> T {
+        self.state.borrow().value.clone()
+    }
+
+    /// Stores `value` and notifies observers
+    pub fn set(&self, value: T) {
+        let observers = {
+            let mut state = self.state.borrow_mut();
+            state.value = value.clone();
+            std::mem::replace(&mut state.observers, Vec::new())
+        };
+
+        // Observers may read or set the property, so it is not borrowed while they run
+        let mut subscribed = Vec::with_capacity(observers.len());
+        for mut observer in observers {
+            if observer(&value) {
+                subscribed.push(observer);
+            }
+        }
+
+        let mut state = self.state.borrow_mut();
+        let added = std::mem::replace(&mut state.observers, Vec::new());
+        state.observers = subscribed;
+        state.observers.extend(added);
+    }
+
+    /// Modifies the value in place with `f` and notifies observers
+    pub fn update<F: FnOnce(&mut T)>(&self, f: F) {
+        let mut value = self.get();
+        f(&mut value);
+        self.set(value);
+    }
+
+    /// Registers `observer` that is called with every new value until it returns `false`
+    pub(crate) fn observe<F: FnMut(&T) -> bool + 'static>(&self, observer: F) {
+        self.state.borrow_mut().observers.push(Box::new(observer));
+    }
+
+    /// Returns a stream of values set from now on. The stream ends when the property is dropped.
+    pub fn changes(&self) -> impl Stream<Item=T, Error=()> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.observe(move |value| sender.unbounded_send(value.clone()).is_ok());
+        receiver
+    }
+
+    /// Returns a property that is computed with `f` from the values of `inputs` and recomputed when any of them changes.
+    /// Recomputation is batched to once per main loop iteration, so setting several inputs in a row
+    /// does not produce intermediate ("glitching") values.
+    ///
+    /// Example:
+    /// ```rust
+    /// let total = Property::computed(&[&subtotal, &shipping], |values| values.iter().sum::<f64>());
+    /// total.map(|total| format!("{:.2}", total)).bind_to(&total_label, "label");
+    /// ```
+    pub fn computed<I, F>(inputs: &[&Property<I>], f: F) -> Property<T>
+        where I: Clone + 'static,
+              F: Fn(&[I]) -> T + 'static
+    {
+        let sources: Vec<WeakProperty<I>> = inputs.iter().map(|input| input.downgrade()).collect();
+        let (output, invalidate) = derived(move || {
+            let values: Vec<I> = sources.iter()
+                .map(|source| source.upgrade().map(|source| source.get()))
+                .collect::<Option<_>>()?;
+            Some(f(&values))
+        });
+
+        for input in inputs {
+            let invalidate = invalidate.clone();
+            input.observe(move |_| invalidate());
+        }
+
+        output
+    }
+
+    /// Returns a property that is computed with `f` from the values of two properties of different types.
+    /// See `computed()`.
+    pub fn computed2<A, B, F>(a: &Property<A>, b: &Property<B>, f: F) -> Property<T>
+        where A: Clone + 'static,
+              B: Clone + 'static,
+              F: Fn(&A, &B) -> T + 'static
+    {
+        let (source_a, source_b) = (a.downgrade(), b.downgrade());
+        let (output, invalidate) = derived(move || Some(f(&source_a.upgrade()?.get(), &source_b.upgrade()?.get())));
+
+        {
+            let invalidate = invalidate.clone();
+            a.observe(move |_| invalidate());
+        }
+        b.observe(move |_| invalidate());
+
+        output
+    }
+
+    /// Returns a property that is computed with `f` from the value of this property. See `computed()`.
+    pub fn map<U, F>(&self, f: F) -> Property<U>
+        where U: Clone + 'static,
+              F: Fn(&T) -> U + 'static
+    {
+        let source = self.downgrade();
+        let (output, invalidate) = derived(move || Some(f(&source.upgrade()?.get())));
+        self.observe(move |_| invalidate());
+        output
+    }
+}
+
+/// Creates the output property of a computation and returns it with the invalidation callback for its inputs.
+/// The callback schedules recomputation on the next main loop iteration (once, however many inputs change)
+/// and returns `false` once the output property is dropped, unsubscribing from the inputs.
+///
+/// The callback is an observer of the inputs, so `compute` must reference them weakly (otherwise they would keep
+/// themselves alive); it returns `None` if an input is gone, in which case the output keeps its value.
+fn derived<T, C>(compute: C) -> (Property<T>, Rc<Fn() -> bool>)
+    where T: Clone + 'static,
+          C: Fn() -> Option<T> + 'static
+{
+    let compute = Rc::new(compute);
+    let output = Property::new(compute().expect("inputs of a derived property are alive while it is created"));
+    let output_state = Rc::downgrade(&output.state);
+    let scheduled = Rc::new(Cell::new(false));
+
+    let invalidate = move || {
+        if output_state.upgrade().is_none() {
+            return false;
+        }
+        if scheduled.replace(true) {
+            return true;
+        }
+
+        let output_state = output_state.clone();
+        let scheduled = scheduled.clone();
+        let compute = compute.clone();
+        gtk::idle_add(move || {
+            scheduled.set(false);
+            if let Some(state) = output_state.upgrade() {
+                if let Some(value) = compute() {
+                    Property { state }.set(value);
+                }
+            }
+            gtk::Continue(false)
+        });
+        true
+    };
+
+    (output, Rc::new(invalidate))
+}
+
+impl<T: Clone + glib::ToValue + 'static> Property<T> {
+    /// Keeps property `property_name` of `object` (usually a widget) equal to the value of this property.
+    /// The object is referenced weakly: the binding ends when the object is destroyed.
+    /// *Panics* if the object has no such property or the property has a different type.
+    pub fn bind_to<O: ObjectType>(&self, object: &O, property_name: &str) {
+        let set_property = |object: &O, property_name: &str, value: &T| {
+            object.set_property(property_name, value)
+                .unwrap_or_else(|_| panic!("Failed to set property {} of {}", property_name, object.get_type()));
+        };
+
+        set_property(object, property_name, &self.get());
+
+        let weak = object.downgrade();
+        let property_name = property_name.to_string();
+        self.observe(move |value| {
+            match weak.upgrade() {
+                Some(object) => {
+                    set_property(&object, &property_name, value);
+                    true
+                },
+                None => false,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::Property;
+
+    #[test]
+    fn derived_properties_do_not_keep_inputs_alive() {
+        let a = Property::new(1);
+        let b = Property::new("b".to_string());
+        let mapped = a.map(|a| a * 2);
+        let computed = Property::computed(&[&a, &a], |values| values.iter().sum::<i32>());
+        let computed2 = Property::computed2(&a, &b, |a, b| format!("{}{}", a, b));
+        assert_eq!((mapped.get(), computed.get(), computed2.get()), (2, 2, "1b".to_string()));
+
+        let inputs = (Rc::downgrade(&a.state), Rc::downgrade(&b.state));
+        let outputs = (Rc::downgrade(&mapped.state), Rc::downgrade(&computed.state), Rc::downgrade(&computed2.state));
+        drop((a, b, mapped, computed, computed2));
+
+        assert!(inputs.0.upgrade().is_none());
+        assert!(inputs.1.upgrade().is_none());
+        assert!(outputs.0.upgrade().is_none());
+        assert!(outputs.1.upgrade().is_none());
+        assert!(outputs.2.upgrade().is_none());
+    }
+
+    #[test]
+    fn inputs_do_not_keep_derived_property_alive() {
+        let a = Property::new(1);
+        let mapped = a.map(|a| a + 1);
+        let output = Rc::downgrade(&mapped.state);
+        drop(mapped);
+
+        assert!(output.upgrade().is_none());
+        assert_eq!(a.get(), 1);
+    }
+}
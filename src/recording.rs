@@ -0,0 +1,171 @@
+use futures::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::spawn::BoxUnitFuture;
+use crate::spawn::GuiSpawn;
+
+/// State of a task spawned on `RecordingExecutor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordedTaskState {
+    /// Task is not completed yet
+    Pending,
+    /// Task completed successfully
+    Finished,
+    /// Task returned error
+    Failed,
+}
+
+struct RecordedTask {
+    name: Option<String>,
+    spawn: Option<futures::executor::Spawn<BoxUnitFuture>>,
+    state: RecordedTaskState,
+}
+
+/// Records ids of tasks that were woken
+struct WakeRecorder {
+    woken: Mutex<HashSet<usize>>,
+}
+
+impl futures::executor::Notify for WakeRecorder {
+    fn notify(&self, id: usize) {
+        self.woken.lock().unwrap().insert(id);
+    }
+}
+
+/// An executor for unit tests that records spawned futures instead of running them on a main loop.
+///
+/// Tasks are polled only when the test asks for it (`poll_woken()`, `run_until_stalled()`),
+/// so presenter/view-model code written against `GuiSpawn` can be stepped and inspected without Gtk+.
+///
+/// `RecordingExecutor` is cloneable (all clones refer to the same executor).
+///
+/// Example:
+/// ```rust
+/// let executor = RecordingExecutor::new();
+/// let presenter = Presenter::new(&executor);
+/// presenter.refresh_clicked();
+///
+/// assert_eq!(executor.task_names(), vec!["refresh".to_string()]);
+/// executor.run_until_stalled();
+/// ```
+#[derive(Clone)]
+pub struct RecordingExecutor {
+    tasks: Rc<RefCell<Vec<RecordedTask>>>,
+    wakes: Arc<WakeRecorder>,
+}
+
+impl RecordingExecutor {
+    /// Constructs executor with no tasks
+    pub fn new() -> Self {
+        RecordingExecutor {
+            tasks: Rc::new(RefCell::new(Vec::new())),
+            wakes: Arc::new(WakeRecorder {
+                woken: Mutex::new(HashSet::new()),
+            }),
+        }
+    }
+
+    /// Records specified future. It is first polled by `poll_woken()` or `run_until_stalled()`.
+    pub fn spawn<F: Future<Item=(), Error=()> + 'static>(&self, f: F) {
+        self.record(None, Box::new(f));
+    }
+
+    /// Records specified future with a name
+    pub fn spawn_named<F: Future<Item=(), Error=()> + 'static>(&self, name: &str, f: F) {
+        self.record(Some(name.to_string()), Box::new(f));
+    }
+
+    fn record(&self, name: Option<String>, f: BoxUnitFuture) {
+        let id = {
+            let mut tasks = self.tasks.borrow_mut();
+            tasks.push(RecordedTask {
+                name,
+                spawn: Some(futures::executor::spawn(f)),
+                state: RecordedTaskState::Pending,
+            });
+            tasks.len() - 1
+        };
+        self.wakes.woken.lock().unwrap().insert(id);
+    }
+
+    /// Returns the number of tasks spawned so far (including completed ones)
+    pub fn spawned_count(&self) -> usize {
+        self.tasks.borrow().len()
+    }
+
+    /// Returns the names of all spawned tasks in spawn order; unnamed tasks are skipped
+    pub fn task_names(&self) -> Vec<String> {
+        self.tasks.borrow().iter().filter_map(|task| task.name.clone()).collect()
+    }
+
+    /// Returns the state of the task with specified index (in spawn order)
+    pub fn task_state(&self, index: usize) -> Option<RecordedTaskState> {
+        self.tasks.borrow().get(index).map(|task| task.state)
+    }
+
+    /// Returns the number of tasks that are not completed
+    pub fn pending_count(&self) -> usize {
+        self.tasks.borrow().iter().filter(|task| task.state == RecordedTaskState::Pending).count()
+    }
+
+    /// Polls every task that was spawned or woken since it was last polled, once.
+    /// Returns the number of polled tasks.
+    pub fn poll_woken(&self) -> usize {
+        let mut woken: Vec<usize> = self.wakes.woken.lock().unwrap().drain().collect();
+        woken.sort();
+
+        let notify = futures::executor::NotifyHandle::from(self.wakes.clone());
+        let mut polled = 0;
+
+        for id in woken {
+            let spawn = self.tasks.borrow_mut()[id].spawn.take();
+            let mut spawn = match spawn {
+                Some(spawn) => spawn,
+                None => continue,
+            };
+
+            polled += 1;
+            let state = match spawn.poll_future_notify(&notify, id) {
+                Ok(Async::NotReady) => RecordedTaskState::Pending,
+                Ok(Async::Ready(())) => RecordedTaskState::Finished,
+                Err(()) => RecordedTaskState::Failed,
+            };
+
+            let mut tasks = self.tasks.borrow_mut();
+            tasks[id].state = state;
+            if state == RecordedTaskState::Pending {
+                tasks[id].spawn = Some(spawn);
+            }
+        }
+
+        polled
+    }
+
+    /// Polls woken tasks until no task is woken anymore. Returns the total number of polls.
+    pub fn run_until_stalled(&self) -> usize {
+        let mut total = 0;
+        loop {
+            let polled = self.poll_woken();
+            if polled == 0 {
+                return total;
+            }
+            total += polled;
+        }
+    }
+}
+
+impl Default for RecordingExecutor {
+    fn default() -> Self {
+        RecordingExecutor::new()
+    }
+}
+
+impl GuiSpawn for RecordingExecutor {
+    fn spawn_boxed(&self, name: Option<&str>, f: BoxUnitFuture) {
+        self.record(name.map(|name| name.to_string()), f);
+    }
+}
@@ -0,0 +1,43 @@
+use futures::prelude::*;
+use futures::sync::mpsc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+
+/// Returns a stream of the messages emitted on a relm event stream (e.g. `component.stream()`),
+/// so relm components can be consumed with the usual stream combinators on this executor.
+///
+/// The stream never ends: relm event streams have no end-of-stream notification.
+pub fn relm_messages<MSG: Clone + 'static>(stream: &relm::EventStream<MSG>) -> impl Stream<Item=MSG, Error=()> {
+    let (sender, receiver) = mpsc::unbounded();
+
+    stream.observe(move |msg: &MSG| {
+        let _ = sender.unbounded_send(msg.clone());
+    });
+
+    receiver
+}
+
+/// Executes `f` on `executor` and emits the message built from its result on `stream`
+/// (e.g. `relm.stream()` of a relm widget), so relm components can await futures driven by this executor.
+///
+/// Example:
+/// ```rust
+/// fn update(&mut self, event: Msg) {
+///     match event {
+///         Msg::Refresh => spawn_emit(&self.model.executor, self.model.relm.stream(), fetch_feed(), Msg::Loaded),
+///         Msg::Loaded(result) => self.show(result),
+///     }
+/// }
+/// ```
+pub fn spawn_emit<F, M, MSG>(executor: &GtkEventLoopAsyncExecutor, stream: &relm::StreamHandle<MSG>, f: F, to_msg: M)
+    where F: Future + 'static,
+          M: FnOnce(Result<F::Item, F::Error>) -> MSG + 'static,
+          MSG: 'static
+{
+    let stream = stream.clone();
+
+    executor.spawn(f.then(move |result| {
+        stream.emit(to_msg(result));
+        Ok(())
+    }));
+}
@@ -0,0 +1,89 @@
+use futures::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::task::TaskHandle;
+use crate::timer::delay;
+
+/// An update delivered by `live_search()` to its results sink
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchUpdate<T, E> {
+    /// The query is shorter than the minimal length; shown results should be cleared
+    TooShort,
+    /// Search for the query was started (e.g. show a spinner)
+    Searching(String),
+    /// Search finished with results
+    Results(T),
+    /// Search failed
+    Failed(E),
+}
+
+/// Runs `search` for the text of `entry` as the user types and delivers updates to `results` on Gtk+ main thread.
+///
+/// The search starts once the text stays unchanged for `debounce` and is at least `min_len` characters long.
+/// When the text changes, the search for the previous text is cancelled: its future is dropped, its cancellation token
+/// is cancelled (so that work moved to a background pool can stop early) and its results are never delivered.
+/// `search` should move expensive work off the main thread, e.g. with `CpuPool::spawn_fn`.
+///
+/// The search runs until `entry` is destroyed.
+///
+/// Example:
+/// ```rust
+/// live_search(&gtk_executor, &search_entry, 2, Duration::from_millis(300),
+///     move |query, token| cpu_pool.spawn_fn(move || index.search(&query, &token)),
+///     move |update| match update {
+///         SearchUpdate::Searching(_) => spinner.start(),
+///         SearchUpdate::Results(items) => { spinner.stop(); show_results(&items); },
+///         SearchUpdate::Failed(error) => { spinner.stop(); show_error(&error); },
+///         SearchUpdate::TooShort => show_results(&[]),
+///     });
+/// ```
+pub fn live_search<T, E, F, R, K>(executor: &GtkEventLoopAsyncExecutor, entry: &gtk::Entry, min_len: usize, debounce: Duration, search: F, results: K)
+    where T: 'static,
+          E: 'static,
+          F: Fn(String, CancellationToken) -> R + 'static,
+          R: IntoFuture<Item=T, Error=E> + 'static,
+          K: Fn(SearchUpdate<T, E>) + 'static
+{
+    let executor = executor.clone();
+    let search = Rc::new(search);
+    let results = Rc::new(results);
+    let running: RefCell<Option<(TaskHandle<(), ()>, CancellationToken)>> = RefCell::new(None);
+
+    entry.connect_changed(move |entry| {
+        let previous = running.borrow_mut().take();
+        if let Some((handle, token)) = previous {
+            token.cancel();
+            handle.abort();
+        }
+
+        let query = entry.get_text().map(|text| text.to_string()).unwrap_or_default();
+        if query.chars().count() < min_len {
+            results(SearchUpdate::TooShort);
+            return;
+        }
+
+        let token = CancellationToken::new();
+        let search = search.clone();
+        let results = results.clone();
+        let search_token = token.clone();
+
+        let task = delay(debounce).then(move |_| {
+            results(SearchUpdate::Searching(query.clone()));
+            search(query, search_token).into_future().then(move |outcome| {
+                results(match outcome {
+                    Ok(items) => SearchUpdate::Results(items),
+                    Err(error) => SearchUpdate::Failed(error),
+                });
+                Ok::<(), ()>(())
+            })
+        });
+
+        let handle = executor.task().name("live_search").spawn_with_handle(task);
+        *running.borrow_mut() = Some((handle, token));
+    });
+}
@@ -0,0 +1,117 @@
+use futures::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::task::SharedHandle;
+
+struct InFlight<K, T, E> {
+    /// Operations in progress by key, with the number that identifies the operation
+    calls: HashMap<K, (u64, SharedHandle<T, E>)>,
+    next_call: u64,
+}
+
+/// `SingleFlight` deduplicates identical async operations that are in progress at the same time.
+///
+/// `run()` starts the operation for a key in a task on Gtk+ main loop, unless an operation for that key
+/// is already in progress; in that case the caller gets a handle of the running operation instead.
+/// All callers are completed with (clones of) the same result. Once the operation completes,
+/// the next `run()` for the key starts a new one. This keeps repeated user actions, like double-clicking
+/// "Refresh", from launching the same request several times.
+///
+/// `SingleFlight` is cloneable (all clones refer to the same set of operations) and may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let refreshes = SingleFlight::new(&gtk_executor);
+///
+/// refresh_button.connect_clicked(move |_| {
+///     let view = view.clone();
+///     gtk_executor.spawn(
+///         refreshes.run(folder.clone(), || fetch_listing(&folder))
+///             .map(move |listing| view.show(&listing))
+///             .map_err(|_| ())
+///     );
+/// });
+/// ```
+pub struct SingleFlight<K, T, E> {
+    executor: GtkEventLoopAsyncExecutor,
+    in_flight: Rc<RefCell<InFlight<K, T, E>>>,
+}
+
+impl<K, T, E> Clone for SingleFlight<K, T, E> {
+    fn clone(&self) -> Self {
+        SingleFlight {
+            executor: self.executor.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+impl<K, T, E> SingleFlight<K, T, E>
+    where K: Hash + Eq + Clone + 'static,
+          T: Clone + 'static,
+          E: Clone + 'static
+{
+    /// Constructs `SingleFlight` with no operations in progress
+    pub fn new(executor: &GtkEventLoopAsyncExecutor) -> Self {
+        SingleFlight {
+            executor: executor.clone(),
+            in_flight: Rc::new(RefCell::new(
+                InFlight {
+                    calls: HashMap::new(),
+                    next_call: 0,
+                }
+            )),
+        }
+    }
+
+    /// Returns a handle of the operation for `key`, starting it with `f` if it is not in progress
+    pub fn run<F, R>(&self, key: K, f: F) -> SharedHandle<T, E>
+        where F: FnOnce() -> R,
+              R: IntoFuture<Item=T, Error=E> + 'static
+    {
+        let call = {
+            let mut in_flight = self.in_flight.borrow_mut();
+            if let Some((_, handle)) = in_flight.calls.get(&key) {
+                return handle.clone();
+            }
+            in_flight.next_call += 1;
+            in_flight.next_call
+        };
+
+        let in_flight = Rc::downgrade(&self.in_flight);
+        let finished_key = key.clone();
+        let handle = self.executor.task().name("SingleFlight").spawn_shared(f().into_future().then(move |result| {
+            if let Some(in_flight) = in_flight.upgrade() {
+                let mut in_flight = in_flight.borrow_mut();
+                let is_current = in_flight.calls.get(&finished_key).map(|(current, _)| *current == call).unwrap_or(false);
+                if is_current {
+                    in_flight.calls.remove(&finished_key);
+                }
+            }
+            result
+        }));
+
+        // An operation that completed on its first poll or could not be spawned (the executor is shutting down)
+        // is not in flight
+        if !handle.is_finished() {
+            self.in_flight.borrow_mut().calls.insert(key, (call, handle.clone()));
+        }
+
+        handle
+    }
+
+    /// Returns `true` if the operation for `key` is in progress
+    pub fn is_in_flight(&self, key: &K) -> bool {
+        self.in_flight.borrow().calls.contains_key(key)
+    }
+
+    /// Makes the next `run()` for `key` start a new operation even if the current one is still in progress.
+    /// Callers of the current operation still receive its result.
+    pub fn forget(&self, key: &K) {
+        self.in_flight.borrow_mut().calls.remove(key);
+    }
+}
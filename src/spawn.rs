@@ -0,0 +1,49 @@
+use futures::prelude::*;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+
+/// A boxed future that can be spawned on an executor
+pub type BoxUnitFuture = Box<Future<Item=(), Error=()>>;
+
+/// Object-safe interface for spawning GUI tasks.
+///
+/// Code that accepts `&GuiSpawn` instead of a concrete executor can be driven by `GtkEventLoopAsyncExecutor`
+/// in the application and by `RecordingExecutor` in unit tests.
+/// The trait is also implemented for references, `Box` and `Rc` of implementors, so widget libraries
+/// may store the spawner as `Rc<GuiSpawn>`.
+pub trait GuiSpawn {
+    /// Executes specified future; `name` is used in diagnostics
+    fn spawn_boxed(&self, name: Option<&str>, f: BoxUnitFuture);
+
+    /// Boxes and executes specified future
+    fn spawn_future<F: Future<Item=(), Error=()> + 'static>(&self, f: F) where Self: Sized {
+        self.spawn_boxed(None, Box::new(f));
+    }
+}
+
+impl GuiSpawn for GtkEventLoopAsyncExecutor {
+    fn spawn_boxed(&self, name: Option<&str>, f: BoxUnitFuture) {
+        match name {
+            Some(name) => self.spawn_named(name, f),
+            None => self.spawn(f),
+        }
+    }
+}
+
+impl<'a, S: GuiSpawn + ?Sized> GuiSpawn for &'a S {
+    fn spawn_boxed(&self, name: Option<&str>, f: BoxUnitFuture) {
+        (**self).spawn_boxed(name, f)
+    }
+}
+
+impl<S: GuiSpawn + ?Sized> GuiSpawn for Box<S> {
+    fn spawn_boxed(&self, name: Option<&str>, f: BoxUnitFuture) {
+        (**self).spawn_boxed(name, f)
+    }
+}
+
+impl<S: GuiSpawn + ?Sized> GuiSpawn for std::rc::Rc<S> {
+    fn spawn_boxed(&self, name: Option<&str>, f: BoxUnitFuture) {
+        (**self).spawn_boxed(name, f)
+    }
+}
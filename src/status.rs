@@ -0,0 +1,79 @@
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::executor::TaskEvent;
+
+/// `TaskStatusReporter` tells the user what background activity is going on.
+///
+/// When a named task (see `GtkEventLoopAsyncExecutor::spawn_named`) starts, the reporter's start callback is called
+/// with the task name; when it ends, fails or is cancelled, the end callback is called.
+/// Unnamed tasks are not reported.
+///
+/// Example:
+/// ```rust
+/// TaskStatusReporter::for_statusbar(&statusbar).attach(&gtk_executor);
+///
+/// // "Saving document" is shown in the status bar until saving finishes
+/// gtk_executor.spawn_named("Saving document", save_document(doc));
+/// ```
+pub struct TaskStatusReporter {
+    on_start: Box<Fn(usize, &str)>,
+    on_end: Box<Fn(usize, &str, bool)>,
+}
+
+impl TaskStatusReporter {
+    /// Constructs reporter that invokes `on_start(task_id, name)` when a named task starts
+    /// and `on_end(task_id, name, succeeded)` when it finishes or fails
+    pub fn new<S, E>(on_start: S, on_end: E) -> Self
+        where S: Fn(usize, &str) + 'static,
+              E: Fn(usize, &str, bool) + 'static
+    {
+        TaskStatusReporter {
+            on_start: Box::new(on_start),
+            on_end: Box::new(on_end),
+        }
+    }
+
+    /// Constructs reporter that pushes the name of a running task onto `statusbar`
+    /// and removes it once the task ends
+    pub fn for_statusbar(statusbar: &gtk::Statusbar) -> Self {
+        let context_id = statusbar.get_context_id("gtk-future-executor tasks");
+        let messages: Rc<RefCell<HashMap<usize, u32>>> = Rc::new(RefCell::new(HashMap::new()));
+
+        let on_start = {
+            let statusbar = statusbar.clone();
+            let messages = messages.clone();
+            move |id: usize, name: &str| {
+                let message_id = statusbar.push(context_id, name);
+                messages.borrow_mut().insert(id, message_id);
+            }
+        };
+
+        let on_end = {
+            let statusbar = statusbar.clone();
+            move |id: usize, _: &str, _: bool| {
+                if let Some(message_id) = messages.borrow_mut().remove(&id) {
+                    statusbar.remove(context_id, message_id);
+                }
+            }
+        };
+
+        TaskStatusReporter::new(on_start, on_end)
+    }
+
+    /// Starts reporting tasks of `executor`
+    pub fn attach(self, executor: &GtkEventLoopAsyncExecutor) {
+        executor.add_task_observer(move |event| {
+            match *event {
+                TaskEvent::Started { id, name: Some(name) } => (self.on_start)(id, name),
+                TaskEvent::Finished { id, name: Some(name) } => (self.on_end)(id, name, true),
+                TaskEvent::Failed { id, name: Some(name) } => (self.on_end)(id, name, false),
+                TaskEvent::Cancelled { id, name: Some(name) } => (self.on_end)(id, name, false),
+                _ => {},
+            }
+        });
+    }
+}
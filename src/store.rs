@@ -0,0 +1,114 @@
+use futures::prelude::*;
+use futures::sync::mpsc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::property::Property;
+
+/// A thread-safe handle for dispatching actions to a `Store`. See `Store::dispatcher()`.
+pub struct Dispatcher<A> {
+    sender: mpsc::UnboundedSender<A>,
+}
+
+impl<A> Clone for Dispatcher<A> {
+    fn clone(&self) -> Self {
+        Dispatcher {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<A> Dispatcher<A> {
+    /// Sends `action` to the store. The reducer is run with it on Gtk+ main thread.
+    pub fn dispatch(&self, action: A) {
+        // The store task ends only when every sender is dropped, so sending never fails while `self` is alive
+        let _ = self.sender.unbounded_send(action);
+    }
+}
+
+/// `Store` holds the application state that is changed only by dispatching actions (Redux-style).
+///
+/// Actions are applied in dispatch order by the reducer, which runs in a task on Gtk+ main loop.
+/// `subscribe()` returns a stream of state snapshots taken after every action, and `property()`
+/// exposes the state as a `Property` for binding it (or values computed from it) to widgets.
+///
+/// `Store` is cloneable (all clones refer to the same store) and may only be used on Gtk+ main thread.
+/// Actions are dispatched from other threads with a `Dispatcher` obtained with `dispatcher()`.
+///
+/// Example:
+/// ```rust
+/// let store = Store::new(&gtk_executor, AppState::default(), |state: &mut AppState, action| match action {
+///     Action::Loaded(items) => state.items = items,
+///     Action::Select(index) => state.selected = Some(index),
+/// });
+///
+/// let dispatcher = store.dispatcher();
+/// thread::spawn(move || dispatcher.dispatch(Action::Loaded(load_items())));
+///
+/// gtk_executor.spawn(store.subscribe().for_each(move |state| {
+///     render(&state);
+///     Ok(())
+/// }));
+/// ```
+pub struct Store<S, A> {
+    state: Property<S>,
+    dispatcher: Dispatcher<A>,
+}
+
+impl<S, A> Clone for Store<S, A> {
+    fn clone(&self) -> Self {
+        Store {
+            state: self.state.clone(),
+            dispatcher: self.dispatcher.clone(),
+        }
+    }
+}
+
+impl<S: Clone + 'static, A: 'static> Store<S, A> {
+    /// Constructs store with `initial` state that applies actions with `reducer`.
+    /// The store task runs until the store and all its dispatchers are dropped.
+    pub fn new<R>(executor: &GtkEventLoopAsyncExecutor, initial: S, reducer: R) -> Self
+        where R: Fn(&mut S, A) + 'static
+    {
+        let (sender, receiver) = mpsc::unbounded();
+        let state = Property::new(initial);
+
+        {
+            let state = state.clone();
+            executor.spawn_named("Store", receiver.for_each(move |action| {
+                state.update(|value| reducer(value, action));
+                Ok(())
+            }));
+        }
+
+        Store {
+            state,
+            dispatcher: Dispatcher { sender },
+        }
+    }
+
+    /// Sends `action` to the store. The reducer is run with it on the next main loop iteration,
+    /// after actions that were dispatched earlier.
+    pub fn dispatch(&self, action: A) {
+        self.dispatcher.dispatch(action);
+    }
+
+    /// Returns a handle that dispatches actions to this store and may be sent to other threads
+    pub fn dispatcher(&self) -> Dispatcher<A> {
+        self.dispatcher.clone()
+    }
+
+    /// Returns a snapshot of the current state
+    pub fn state(&self) -> S {
+        self.state.get()
+    }
+
+    /// Returns a stream of state snapshots taken after every action dispatched from now on
+    pub fn subscribe(&self) -> impl Stream<Item=S, Error=()> {
+        self.state.changes()
+    }
+
+    /// Returns the state as a property, e.g. for deriving values with `Property::map()`
+    pub fn property(&self) -> &Property<S> {
+        &self.state
+    }
+}
@@ -0,0 +1,358 @@
+use futures::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::rc::Weak;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::errors::panic_message;
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::logging;
+use crate::property::Property;
+use crate::task::TaskHandle;
+use crate::timer::delay;
+
+/// Which children a `Supervisor` restarts when a child fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartStrategy {
+    /// Restart only the failed child
+    OneForOne,
+    /// Stop all other children and restart all of them; for children that depend on each other
+    AllForOne,
+}
+
+/// State of a supervised child task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildState {
+    /// The child is running
+    Running,
+    /// The child failed (or was stopped by an all-for-one restart) and waits for its restart
+    Restarting,
+    /// The child completed successfully; completed children are not restarted
+    Stopped,
+    /// The child failed more often than the supervisor allows and is not restarted anymore
+    Failed,
+}
+
+/// Health of a supervised child task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildHealth {
+    /// Name passed to `Supervisor::add_child`
+    pub name: String,
+    pub state: ChildState,
+    /// Number of times the child was restarted
+    pub restarts: usize,
+    /// Error (or panic message) of the last failure
+    pub last_error: Option<String>,
+}
+
+/// Aggregate health of a `Supervisor`'s children, in the order they were added
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SupervisorHealth {
+    /// Health of every child
+    pub children: Vec<ChildHealth>,
+}
+
+impl SupervisorHealth {
+    /// Returns `true` if no child is waiting for a restart or has given up
+    pub fn is_healthy(&self) -> bool {
+        self.children.iter().all(|child| child.state == ChildState::Running || child.state == ChildState::Stopped)
+    }
+}
+
+/// Delay before the first restart of a failed child, unless configured with `Supervisor::with_backoff`
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// Maximum delay between restarts, unless configured with `Supervisor::with_backoff`
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+type ChildFuture = Box<Future<Item=(), Error=String>>;
+
+struct Child {
+    name: String,
+    make: Rc<RefCell<FnMut() -> ChildFuture>>,
+    /// Handle of the running task; dropping it stops the child
+    running: Option<TaskHandle<(), ()>>,
+    /// Incremented on every start, so that exits of stopped incarnations are ignored
+    generation: u64,
+    state: ChildState,
+    restarts: usize,
+    /// Number of failures since the child last ran long enough to be considered recovered
+    consecutive_failures: u32,
+    started_at: Instant,
+    last_error: Option<String>,
+}
+
+struct SupervisorState {
+    executor: GtkEventLoopAsyncExecutor,
+    strategy: RestartStrategy,
+    /// Initial and maximum delay before a restart
+    backoff: (Duration, Duration),
+    max_restarts: Option<usize>,
+    children: Vec<Child>,
+    health: Property<SupervisorHealth>,
+}
+
+impl SupervisorState {
+    fn health(&self) -> SupervisorHealth {
+        SupervisorHealth {
+            children: self.children.iter()
+                .map(|child| ChildHealth {
+                    name: child.name.clone(),
+                    state: child.state,
+                    restarts: child.restarts,
+                    last_error: child.last_error.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Delay before restarting `child` after its latest failure
+    fn restart_delay(&self, child: &Child) -> Duration {
+        let (initial, max) = self.backoff;
+        let factor = 1u32 << child.consecutive_failures.saturating_sub(1).min(16);
+        (initial * factor).min(max)
+    }
+}
+
+/// `Supervisor` keeps a group of long-lived background tasks ("children") alive.
+///
+/// Each child is made by a factory; when a child fails (its future fails or panics), the supervisor restarts it
+/// according to the restart strategy after an exponentially growing delay (see `with_backoff()`), so that a child
+/// that fails right away doesn't keep the main loop busy.
+/// A child that completes successfully is not restarted. The aggregate health of the children is available
+/// with `health()` and as a stream with `health_changes()`, e.g. for a status indicator.
+///
+/// Dropping the supervisor stops all children. `Supervisor` may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let supervisor = Supervisor::new(&gtk_executor, RestartStrategy::OneForOne)
+///     .with_backoff(Duration::from_secs(1), Duration::from_secs(300));
+/// supervisor.add_child("sync", move || sync_with_server(&account));
+/// supervisor.add_child("file watcher", move || watch_directory(&library_path));
+///
+/// gtk_executor.spawn(supervisor.health_changes().for_each(move |health| {
+///     status_icon.set_visible(!health.is_healthy());
+///     Ok(())
+/// }));
+/// ```
+pub struct Supervisor {
+    state: Rc<RefCell<SupervisorState>>,
+}
+
+impl Supervisor {
+    /// Constructs a supervisor without children that restarts failed children without limit,
+    /// first after 100 ms, doubling the delay for every consecutive failure up to 30 s
+    pub fn new(executor: &GtkEventLoopAsyncExecutor, strategy: RestartStrategy) -> Self {
+        Supervisor {
+            state: Rc::new(RefCell::new(
+                SupervisorState {
+                    executor: executor.clone(),
+                    strategy,
+                    backoff: (DEFAULT_INITIAL_BACKOFF, DEFAULT_MAX_BACKOFF),
+                    max_restarts: None,
+                    children: Vec::new(),
+                    health: Property::new(SupervisorHealth::default()),
+                }
+            )),
+        }
+    }
+
+    /// Delays restarts: the first restart after `initial`, doubling the delay for every consecutive failure
+    /// up to `max`. A child that ran for at least `max` before failing starts over with `initial`.
+    pub fn with_backoff(self, initial: Duration, max: Duration) -> Self {
+        self.state.borrow_mut().backoff = (initial, max);
+        self
+    }
+
+    /// Gives up on a child after it was restarted `max_restarts` times; the child is then `ChildState::Failed`
+    pub fn with_max_restarts(self, max_restarts: usize) -> Self {
+        self.state.borrow_mut().max_restarts = Some(max_restarts);
+        self
+    }
+
+    /// Starts a child task named `name` with a future made by `make`; `make` is called again for every restart
+    pub fn add_child<M, R>(&self, name: &str, mut make: M)
+        where M: FnMut() -> R + 'static,
+              R: IntoFuture<Item=()> + 'static,
+              R::Error: std::fmt::Display
+    {
+        let make: Rc<RefCell<FnMut() -> ChildFuture>> = Rc::new(RefCell::new(move || {
+            Box::new(make().into_future().map_err(|error| error.to_string())) as ChildFuture
+        }));
+
+        let index = {
+            let mut state = self.state.borrow_mut();
+            state.children.push(Child {
+                name: name.to_string(),
+                make,
+                running: None,
+                generation: 0,
+                state: ChildState::Restarting,
+                restarts: 0,
+                consecutive_failures: 0,
+                started_at: Instant::now(),
+                last_error: None,
+            });
+            state.children.len() - 1
+        };
+
+        start_child(&self.state, index);
+        publish_health(&self.state);
+    }
+
+    /// Returns the current health of the children
+    pub fn health(&self) -> SupervisorHealth {
+        self.state.borrow().health.get()
+    }
+
+    /// Returns a stream of the health of the children, emitted every time a child changes its state.
+    /// The stream ends when the supervisor is dropped.
+    pub fn health_changes(&self) -> impl Stream<Item=SupervisorHealth, Error=()> {
+        self.state.borrow().health.changes()
+    }
+}
+
+fn publish_health(state: &Rc<RefCell<SupervisorState>>) {
+    let (health, property) = {
+        let state = state.borrow();
+        (state.health(), state.health.clone())
+    };
+    if property.get() != health {
+        property.set(health);
+    }
+}
+
+fn start_child(state: &Rc<RefCell<SupervisorState>>, index: usize) {
+    let (executor, name, make, generation) = {
+        let mut state = state.borrow_mut();
+        let executor = state.executor.clone();
+        let child = &mut state.children[index];
+        if child.state != ChildState::Restarting {
+            // A stale restart (e.g. the supervisor restarted all children in the meantime)
+            return;
+        }
+        child.generation += 1;
+        child.state = ChildState::Running;
+        child.started_at = Instant::now();
+        (executor, child.name.clone(), child.make.clone(), child.generation)
+    };
+
+    // The factory is user code; the supervisor is not borrowed while it runs
+    let future = (&mut *make.borrow_mut())();
+
+    let weak_state = Rc::downgrade(state);
+    let running = executor.task().name(&name).spawn_with_handle(CatchPanic(future).then(move |result| {
+        child_exited(&weak_state, index, generation, result);
+        Ok::<(), ()>(())
+    }));
+
+    let mut state = state.borrow_mut();
+    let child = &mut state.children[index];
+    if child.generation == generation && child.state == ChildState::Running {
+        child.running = Some(running);
+    } else {
+        // The child exited during spawning
+        running.detach();
+    }
+}
+
+fn child_exited(weak_state: &Weak<RefCell<SupervisorState>>, index: usize, generation: u64, result: Result<(), String>) {
+    let state = match weak_state.upgrade() {
+        Some(state) => state,
+        None => return,
+    };
+
+    let (restart, stopped) = {
+        let mut guard = state.borrow_mut();
+        let state_ref = &mut *guard;
+        let child = &mut state_ref.children[index];
+        if child.generation != generation || child.state != ChildState::Running {
+            return;
+        }
+        // The handle belongs to the task that is completing right now
+        if let Some(running) = child.running.take() {
+            running.detach();
+        }
+
+        let error = match result {
+            Ok(()) => {
+                child.state = ChildState::Stopped;
+                drop(guard);
+                publish_health(&state);
+                return;
+            },
+            Err(error) => error,
+        };
+        logging::warning(&format!("Supervised task {} failed: {}", child.name, error));
+        child.last_error = Some(error);
+
+        let recovered = child.started_at.elapsed() >= state_ref.backoff.1;
+        child.consecutive_failures = if recovered { 1 } else { child.consecutive_failures + 1 };
+
+        if state_ref.max_restarts.map(|max_restarts| child.restarts >= max_restarts).unwrap_or(false) {
+            child.state = ChildState::Failed;
+            drop(guard);
+            publish_health(&state);
+            return;
+        }
+
+        let restart_delay = state_ref.restart_delay(&state_ref.children[index]);
+        let restarted: Vec<usize> = match state_ref.strategy {
+            RestartStrategy::OneForOne => vec![index],
+            RestartStrategy::AllForOne => (0..state_ref.children.len())
+                .filter(|&other| {
+                    let state = state_ref.children[other].state;
+                    state == ChildState::Running || state == ChildState::Restarting || other == index
+                })
+                .collect(),
+        };
+
+        let mut stopped = Vec::new();
+        for &restarted_index in &restarted {
+            let child = &mut state_ref.children[restarted_index];
+            child.state = ChildState::Restarting;
+            child.restarts += 1;
+            stopped.extend(child.running.take());
+        }
+        ((restarted, restart_delay, state_ref.executor.clone()), stopped)
+    };
+
+    // Dropping the handles cancels the other children; their futures may run user code when dropped
+    drop(stopped);
+    publish_health(&state);
+
+    let (restarted, restart_delay, executor) = restart;
+    if restart_delay == Duration::from_secs(0) {
+        for index in restarted {
+            start_child(&state, index);
+        }
+        publish_health(&state);
+    } else {
+        let weak_state = Rc::downgrade(&state);
+        executor.spawn_named("Supervisor restart", delay(restart_delay).then(move |_| {
+            if let Some(state) = weak_state.upgrade() {
+                for index in restarted {
+                    start_child(&state, index);
+                }
+                publish_health(&state);
+            }
+            Ok(())
+        }));
+    }
+}
+
+/// Turns a panic of the future into its error
+struct CatchPanic(ChildFuture);
+
+impl Future for CatchPanic {
+    type Item = ();
+    type Error = String;
+
+    fn poll(&mut self) -> Poll<(), String> {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.0.poll())) {
+            Ok(result) => result,
+            Err(payload) => Err(format!("panicked: {}", panic_message(&*payload).unwrap_or("<no message>"))),
+        }
+    }
+}
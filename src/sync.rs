@@ -0,0 +1,23 @@
+//! Locks of the thread-safe primitives (`Promise`, `SharedHandle`, `CancellationToken`).
+//! With feature `parking_lot` they are `parking_lot` locks, which are smaller and cheaper to lock
+//! when resolving and polling happen at high frequency across threads; otherwise std locks are used.
+
+#[cfg(feature = "parking_lot")]
+pub(crate) use parking_lot::Mutex;
+
+/// `std::sync::Mutex` with the interface of `parking_lot::Mutex`
+#[cfg(not(feature = "parking_lot"))]
+#[derive(Debug)]
+pub(crate) struct Mutex<T>(std::sync::Mutex<T>);
+
+#[cfg(not(feature = "parking_lot"))]
+impl<T> Mutex<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Mutex(std::sync::Mutex::new(value))
+    }
+
+    /// Locks the mutex; panics if a thread panicked while holding the lock (like `lock().unwrap()`)
+    pub(crate) fn lock(&self) -> std::sync::MutexGuard<T> {
+        self.0.lock().unwrap()
+    }
+}
@@ -0,0 +1,567 @@
+use futures::prelude::*;
+use glib::translate::from_glib;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::errors::panic_message;
+use crate::errors::ErrorText;
+use crate::errors::PanicPolicy;
+use crate::errors::TaskError;
+use crate::errors::TaskFailure;
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::executor::SpawnError;
+use crate::logging;
+use crate::promise::Promise;
+use crate::sync::Mutex;
+use crate::timer::delay;
+use crate::timer::Delay;
+use crate::timer::Timestamp;
+
+/// Priority of the main loop sources that poll a task.
+/// Tasks with higher priority (lower value) are polled before tasks with lower priority
+/// and before main loop events of lower priority (e.g. redrawing happens at `Priority::HIGH_IDLE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Priority(i32);
+
+impl Priority {
+    /// Same as `glib::PRIORITY_HIGH`
+    pub const HIGH: Priority = Priority(-100);
+    /// Same as `glib::PRIORITY_DEFAULT`
+    pub const DEFAULT: Priority = Priority(0);
+    /// Same as `glib::PRIORITY_HIGH_IDLE`
+    pub const HIGH_IDLE: Priority = Priority(100);
+    /// Same as `glib::PRIORITY_DEFAULT_IDLE`; the priority of tasks spawned with `spawn()`
+    pub const DEFAULT_IDLE: Priority = Priority(200);
+    /// Same as `glib::PRIORITY_LOW`
+    pub const LOW: Priority = Priority(300);
+
+    /// Constructs priority with specified glib priority value
+    pub fn new(value: i32) -> Priority {
+        Priority(value)
+    }
+
+    /// Returns glib priority value
+    pub fn value(self) -> i32 {
+        self.0
+    }
+
+    pub(crate) fn to_glib(self) -> glib::Priority {
+        from_glib(self.0)
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::DEFAULT_IDLE
+    }
+}
+
+/// Per-task configuration collected by `TaskBuilder`
+#[derive(Clone, Default)]
+pub(crate) struct TaskOptions {
+    pub name: Option<String>,
+    pub priority: Priority,
+    pub on_error: Option<Rc<Fn(&TaskError)>>,
+    pub panic_policy: PanicPolicy,
+    pub timeout: Option<Duration>,
+}
+
+/// Builder for spawning a task with per-task options, created by `GtkEventLoopAsyncExecutor::task()`.
+///
+/// A configured builder may be cloned and kept as a profile for a category of tasks.
+///
+/// Example:
+/// ```rust
+/// gtk_executor.task()
+///     .name("fetch")
+///     .priority(Priority::LOW)
+///     .on_error(|error| eprintln!("fetch failed: {}", error))
+///     .spawn(fetch_feed(url));
+///
+/// // Network tasks fail fast instead of hanging spinners forever
+/// let network = gtk_executor.task().priority(Priority::LOW).timeout(Duration::from_secs(10));
+/// network.clone().name("fetch").spawn(fetch_feed(url));
+/// network.clone().name("upload").no_timeout().spawn(upload_library(files));
+/// ```
+#[derive(Clone)]
+pub struct TaskBuilder {
+    executor: GtkEventLoopAsyncExecutor,
+    options: TaskOptions,
+}
+
+impl TaskBuilder {
+    pub(crate) fn new(executor: &GtkEventLoopAsyncExecutor) -> Self {
+        TaskBuilder {
+            executor: executor.clone(),
+            options: TaskOptions::default(),
+        }
+    }
+
+    /// Sets the name of the task that is passed to task observers and used in diagnostics
+    pub fn name(mut self, name: &str) -> Self {
+        self.options.name = Some(name.to_string());
+        self
+    }
+
+    /// Sets the priority of the task. Default is `Priority::DEFAULT_IDLE`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.options.priority = priority;
+        self
+    }
+
+    /// Sets the handler that is invoked if the task fails, instead of the executor's task error policy
+    pub fn on_error<F: Fn(&TaskError) + 'static>(mut self, handler: F) -> Self {
+        self.options.on_error = Some(Rc::new(handler));
+        self
+    }
+
+    /// Sets what happens if the task panics. Default is `PanicPolicy::Rethrow`.
+    pub fn on_panic(mut self, policy: PanicPolicy) -> Self {
+        self.options.panic_policy = policy;
+        self
+    }
+
+    /// Fails the task if it does not complete within `timeout` after spawning: the future is dropped
+    /// and the task is reported as failed with a "timed out" message (to the task's error handler or the task error policy).
+    /// Handles of tasks spawned with `spawn_with_handle()` and `spawn_shared()` resolve with `JoinError::Cancelled`.
+    /// No timeout by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Removes the timeout set with `timeout()`, e.g. for a long task spawned through a profile with a timeout
+    pub fn no_timeout(mut self) -> Self {
+        self.options.timeout = None;
+        self
+    }
+
+    /// Executes specified future on Gtk+ main thread with the configured options
+    pub fn spawn<F: Future<Item=(), Error=()> + Sized + 'static>(self, f: F) {
+        self.executor.spawn_task(self.options, f.map_err(|()| None));
+    }
+
+    /// Executes specified future on Gtk+ main thread with the configured options.
+    /// If the future fails, its error is rendered with `Display` and `Debug` and passed to the error handler
+    /// as `TaskError::message` and `TaskError::debug`.
+    pub fn spawn_fallible<F>(self, f: F)
+        where F: Future<Item=()> + Sized + 'static,
+              F::Error: std::fmt::Display + std::fmt::Debug
+    {
+        self.executor.spawn_task(self.options, f.map_err(|error| Some(ErrorText::new(&error))));
+    }
+
+    /// Executes specified future on Gtk+ main thread with the configured options like `spawn_fallible()`;
+    /// the causes of the error are passed to the error handler as well, as `TaskError::causes`.
+    #[cfg(feature = "anyhow")]
+    pub fn spawn_anyhow<F: Future<Item=(), Error=anyhow::Error> + Sized + 'static>(self, f: F) {
+        self.executor.spawn_task(self.options, f.map_err(|error| Some(ErrorText::from_anyhow(&error))));
+    }
+
+    /// Executes specified future on Gtk+ main thread with the configured options like `spawn_fallible()`;
+    /// the causes of the error are passed to the error handler as well, as `TaskError::causes`.
+    #[cfg(feature = "failure")]
+    pub fn spawn_failure<F: Future<Item=(), Error=failure::Error> + Sized + 'static>(self, f: F) {
+        self.executor.spawn_task(self.options, f.map_err(|error| Some(ErrorText::from_failure(&error))));
+    }
+
+    /// Executes specified future on Gtk+ main thread with the configured options.
+    /// Returns an error instead of panicking if called on a thread other than the one that created the executor,
+    /// if the executor is shutting down or if its task limit is reached.
+    pub fn try_spawn<F: Future<Item=(), Error=()> + Sized + 'static>(self, f: F) -> Result<(), SpawnError> {
+        self.executor.check_owner_thread()?;
+        if !self.executor.has_capacity() {
+            return Err(SpawnError::AtCapacity);
+        }
+        self.executor.try_spawn_task(self.options, f.map_err(|()| None)).map(|_| ())
+    }
+
+    /// Executes the future made by `make` on Gtk+ main thread with the configured options.
+    /// If the future panics, it is dropped and replaced by a new one made by `make`, up to `max_restarts` times;
+    /// the panic after that is handled according to the panic policy (see `on_panic()`).
+    /// Meant for long-lived "listener" tasks, e.g. ones that watch a channel or a socket.
+    ///
+    /// Example:
+    /// ```rust
+    /// let socket_path = socket_path.clone();
+    /// gtk_executor.task()
+    ///     .name("control socket")
+    ///     .on_panic(PanicPolicy::Report)
+    ///     .spawn_restartable(3, move || serve_control_socket(&socket_path));
+    /// ```
+    pub fn spawn_restartable<M, F>(self, max_restarts: usize, mut make: M)
+        where M: FnMut() -> F + 'static,
+              F: Future<Item=(), Error=()> + 'static
+    {
+        let future = make();
+        self.spawn(Restartable {
+            make,
+            future,
+            restarts_left: max_restarts,
+        });
+    }
+
+    /// Executes specified future on Gtk+ main thread with the configured options and returns a handle that owns the task.
+    /// See `TaskHandle`.
+    pub fn spawn_with_handle<F: Future + 'static>(self, f: F) -> TaskHandle<F::Item, F::Error> {
+        let promise = Promise::new();
+        let mut completion = TaskCompletion { promise: Some(promise.clone()) };
+
+        let task = self.executor.spawn_task(self.options, f.then(move |result| {
+            completion.complete(result.map_err(JoinError::Failed));
+            Ok::<(), TaskFailure>(())
+        }));
+
+        if task.is_none() {
+            promise.reject(JoinError::Cancelled);
+        }
+
+        TaskHandle {
+            executor: self.executor,
+            task,
+            promise,
+            detached: false,
+        }
+    }
+
+    /// Executes specified future on Gtk+ main thread with the configured options and returns a cloneable handle
+    /// that resolves with a clone of its result. See `SharedHandle`.
+    pub fn spawn_shared<F>(self, f: F) -> SharedHandle<F::Item, F::Error>
+        where F: Future + 'static,
+              F::Item: Clone + 'static,
+              F::Error: Clone + 'static
+    {
+        let shared = SharedHandle {
+            state: Arc::new(Mutex::new(
+                SharedState {
+                    result: None,
+                    waiting_tasks: Vec::new(),
+                }
+            )),
+        };
+        let mut completion = SharedCompletion { handle: Some(shared.clone()) };
+
+        let task = self.executor.spawn_task(self.options, f.then(move |result| {
+            completion.complete(result.map_err(JoinError::Failed));
+            Ok::<(), TaskFailure>(())
+        }));
+
+        if task.is_none() {
+            shared.complete(Err(JoinError::Cancelled));
+        }
+
+        shared
+    }
+}
+
+/// Future of a task spawned with `TaskBuilder::spawn_restartable`
+struct Restartable<M, F> {
+    make: M,
+    future: F,
+    restarts_left: usize,
+}
+
+impl<M: FnMut() -> F, F: Future> Future for Restartable<M, F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        loop {
+            let payload = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.future.poll())) {
+                Ok(result) => return result,
+                Err(payload) => payload,
+            };
+            if self.restarts_left == 0 {
+                std::panic::resume_unwind(payload);
+            }
+            self.restarts_left -= 1;
+            logging::warning(&format!(
+                "Task panicked ({}), restarting it; {} restarts left",
+                panic_message(&*payload).unwrap_or("<no message>"),
+                self.restarts_left
+            ));
+            self.future = (self.make)();
+        }
+    }
+}
+
+/// Future of a task spawned with a timeout (see `TaskBuilder::timeout`); fails once the deadline passes.
+/// The deadline counts from spawning, not from the first poll, which may happen much later for a low priority task.
+pub(crate) struct TimeLimited<F> {
+    future: F,
+    timeout: Option<Duration>,
+    spawned: Timestamp,
+    deadline: Option<Delay>,
+}
+
+impl<F> TimeLimited<F> {
+    pub(crate) fn new(future: F, timeout: Option<Duration>) -> Self {
+        TimeLimited {
+            future,
+            timeout,
+            spawned: Timestamp::now(),
+            deadline: None,
+        }
+    }
+}
+
+impl<F: Future<Item=(), Error=TaskFailure>> Future for TimeLimited<F> {
+    type Item = ();
+    type Error = TaskFailure;
+
+    fn poll(&mut self) -> Poll<(), TaskFailure> {
+        if let Async::Ready(()) = self.future.poll()? {
+            return Ok(Async::Ready(()));
+        }
+        if let Some(timeout) = self.timeout {
+            let expired = match self.deadline {
+                Some(ref mut deadline) => deadline.poll() == Ok(Async::Ready(())),
+                None => {
+                    let remaining = timeout.checked_sub(self.spawned.elapsed()).unwrap_or_else(|| Duration::from_secs(0));
+                    if remaining == Duration::from_secs(0) {
+                        true
+                    } else {
+                        // The delay starts when polled, so it is made for the time left until the deadline
+                        let mut deadline = delay(remaining);
+                        let expired = deadline.poll() == Ok(Async::Ready(()));
+                        self.deadline = Some(deadline);
+                        expired
+                    }
+                },
+            };
+            if expired {
+                let message = format!("timed out after {:?}", timeout);
+                return Err(Some(ErrorText {
+                    display: message.clone(),
+                    debug: message,
+                    causes: Vec::new(),
+                }));
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+/// Error of a task awaited through `TaskHandle`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError<E> {
+    /// The task's future failed with this error
+    Failed(E),
+    /// The task was dropped before completing: aborted, or cancelled by executor shutdown
+    Cancelled,
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for JoinError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            JoinError::Failed(ref error) => write!(f, "Task failed: {}", error),
+            JoinError::Cancelled => write!(f, "Task was cancelled"),
+        }
+    }
+}
+
+/// Lives inside the spawned future and completes the handle's promise;
+/// if the future is dropped before completing, the handle fails with `JoinError::Cancelled`
+struct TaskCompletion<T, E> {
+    promise: Option<Promise<T, JoinError<E>>>,
+}
+
+impl<T, E> TaskCompletion<T, E> {
+    fn complete(&mut self, result: Result<T, JoinError<E>>) {
+        if let Some(promise) = self.promise.take() {
+            match result {
+                Ok(item) => promise.resolve(item),
+                Err(error) => promise.reject(error),
+            }
+        }
+    }
+}
+
+impl<T, E> Drop for TaskCompletion<T, E> {
+    fn drop(&mut self) {
+        self.complete(Err(JoinError::Cancelled));
+    }
+}
+
+/// A handle that owns a task spawned with `spawn_with_handle()`.
+///
+/// Dropping the handle cancels the task, so a background task can't silently outlive the view that started it.
+/// Call `detach()` to let the task run to completion on its own (fire-and-forget, like `spawn()`).
+///
+/// The handle is a future that resolves with the result of the task;
+/// it fails with `JoinError::Cancelled` if the task is dropped before completing (e.g. by executor shutdown).
+///
+/// Example:
+/// ```rust
+/// // Refreshing stops when the view (and its handle) is dropped
+/// view.refresh_task = Some(gtk_executor.spawn_with_handle(refresh_periodically(view.model())));
+///
+/// // Saving must finish even if the window is closed
+/// gtk_executor.spawn_with_handle(save(document)).detach();
+/// ```
+#[must_use = "the task is cancelled when the handle is dropped; call detach() to let it run"]
+pub struct TaskHandle<T, E> {
+    executor: GtkEventLoopAsyncExecutor,
+    /// Id and generation of the task; `None` if the task was not spawned because the executor is shutting down
+    task: Option<(usize, u64)>,
+    promise: Promise<T, JoinError<E>>,
+    detached: bool,
+}
+
+impl<T, E> TaskHandle<T, E> {
+    /// Returns the id of the task as reported to task observers,
+    /// or `None` if the task was not spawned because the executor is shutting down
+    pub fn id(&self) -> Option<usize> {
+        self.task.map(|(id, _)| id)
+    }
+
+    /// Returns `true` if the task has completed, failed or was cancelled
+    pub fn is_finished(&self) -> bool {
+        self.task.map(|(id, generation)| !self.executor.is_task_pending(id, generation)).unwrap_or(true)
+    }
+
+    /// Lets the task run to completion without the handle
+    pub fn detach(mut self) {
+        self.detached = true;
+    }
+
+    /// Cancels the task now. Same as dropping the handle.
+    pub fn abort(self) {
+    }
+}
+
+impl<T, E> Future for TaskHandle<T, E> {
+    type Item = T;
+    type Error = JoinError<E>;
+
+    fn poll(&mut self) -> Poll<T, JoinError<E>> {
+        self.promise.poll()
+    }
+}
+
+impl<T, E> Drop for TaskHandle<T, E> {
+    fn drop(&mut self) {
+        if self.detached {
+            return;
+        }
+        if let Some((id, generation)) = self.task {
+            self.executor.cancel_task(id, generation);
+        }
+    }
+}
+
+struct SharedState<T, E> {
+    result: Option<Result<T, JoinError<E>>>,
+    waiting_tasks: Vec<futures::task::Task>,
+}
+
+/// A handle of a task spawned with `spawn_shared()`: the task runs once and every clone of the handle
+/// is a future that resolves with a clone of its result.
+///
+/// This suits results that many parts of the application wait for independently, like "the configuration
+/// has been loaded". The task runs to completion even if all handles are dropped; the handle fails
+/// with `JoinError::Cancelled` if the task is dropped before completing (e.g. by executor shutdown).
+///
+/// `SharedHandle` is cloneable and, if the result is `Send`, thread-safe.
+///
+/// Example:
+/// ```rust
+/// let config = gtk_executor.spawn_shared(load_config(path));
+///
+/// gtk_executor.spawn(config.clone().map(move |config| toolbar.apply(&config)).map_err(|_| ()));
+/// gtk_executor.spawn(config.clone().map(move |config| editor.apply(&config)).map_err(|_| ()));
+/// ```
+pub struct SharedHandle<T, E> {
+    state: Arc<Mutex<SharedState<T, E>>>,
+}
+
+impl<T, E> Clone for SharedHandle<T, E> {
+    fn clone(&self) -> Self {
+        SharedHandle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T, E> SharedHandle<T, E> {
+    /// Returns `true` if the task has completed, failed or was cancelled
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().result.is_some()
+    }
+
+    fn complete(&self, result: Result<T, JoinError<E>>) {
+        let mut state = self.state.lock();
+        state.result = Some(result);
+        for task in state.waiting_tasks.drain(..) {
+            task.notify();
+        }
+    }
+}
+
+impl<T: Clone, E: Clone> Future for SharedHandle<T, E> {
+    type Item = T;
+    type Error = JoinError<E>;
+
+    fn poll(&mut self) -> Poll<T, JoinError<E>> {
+        let mut state = self.state.lock();
+        match state.result {
+            Some(Ok(ref item)) => Ok(Async::Ready(item.clone())),
+            Some(Err(ref error)) => Err(error.clone()),
+            None => {
+                // A task that polls the handle repeatedly is registered once
+                if !state.waiting_tasks.iter().any(|task| task.will_notify_current()) {
+                    state.waiting_tasks.push(futures::task::current());
+                }
+                Ok(Async::NotReady)
+            },
+        }
+    }
+}
+
+/// Lives inside the future of a shared task and completes its handles;
+/// if the future is dropped before completing, the handles fail with `JoinError::Cancelled`
+struct SharedCompletion<T, E> {
+    handle: Option<SharedHandle<T, E>>,
+}
+
+impl<T, E> SharedCompletion<T, E> {
+    fn complete(&mut self, result: Result<T, JoinError<E>>) {
+        if let Some(handle) = self.handle.take() {
+            handle.complete(result);
+        }
+    }
+}
+
+impl<T, E> Drop for SharedCompletion<T, E> {
+    fn drop(&mut self) {
+        self.complete(Err(JoinError::Cancelled));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use std::time::Duration;
+
+    use crate::executor::GtkEventLoopAsyncExecutor;
+    use crate::timer::MockClock;
+    use super::JoinError;
+
+    #[test]
+    fn timeout_counts_from_spawn() {
+        let clock = MockClock::install();
+        let executor = GtkEventLoopAsyncExecutor::with_context(glib::MainContext::new());
+        let handle = executor.task()
+            .timeout(Duration::from_secs(10))
+            .on_error(|_| ())
+            .spawn_with_handle(future::empty::<(), ()>());
+
+        // The task is not polled before its deadline passes
+        clock.advance(Duration::from_secs(10));
+        let result = executor.run_until(handle, 100);
+        clock.uninstall();
+
+        assert_eq!(result.unwrap(), Err(JoinError::Cancelled));
+    }
+}
@@ -0,0 +1,276 @@
+use futures::prelude::*;
+use futures::stream::FuturesUnordered;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::rc::Weak;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+
+type BoxFuture<T, E> = Box<Future<Item=T, Error=E>>;
+
+struct TaskSetState<T, E> {
+    executor: GtkEventLoopAsyncExecutor,
+    /// Futures that are being polled. Taken out of the state while the driver polls them,
+    /// so that futures may insert new futures into the set.
+    running: FuturesUnordered<BoxFuture<T, E>>,
+    /// Futures inserted while the driver is polling `running`
+    incoming: Vec<BoxFuture<T, E>>,
+    results: VecDeque<Result<T, E>>,
+    /// Number of inserted futures that have not completed yet
+    outstanding: usize,
+    /// Incremented by `abort_all()`, so that the driver drops the futures it took out instead of putting them back
+    abort_epoch: u64,
+    driver_spawned: bool,
+    driver_task: Option<futures::task::Task>,
+    waiting_tasks: Vec<futures::task::Task>,
+}
+
+impl<T, E> Drop for TaskSetState<T, E> {
+    fn drop(&mut self) {
+        // Let the driver task finish instead of staying parked forever
+        if let Some(task) = self.driver_task.take() {
+            task.notify();
+        }
+    }
+}
+
+/// A set of futures owned by application code that the executor polls as a unit.
+///
+/// Inserted futures are executed on Gtk+ main thread by a single task of the executor and their results
+/// are collected in completion order; `join_next()` returns them one by one.
+/// When the last clone of the set is dropped, all futures that are still running are dropped too,
+/// which makes `LocalTaskSet` handy for per-view bundles of tasks that should not outlive the view.
+///
+/// `LocalTaskSet` is cloneable (all clones refer to the same set) and may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let thumbnails = LocalTaskSet::new(&gtk_executor);
+/// for path in paths {
+///     thumbnails.insert(load_thumbnail(path));
+/// }
+///
+/// gtk_executor.spawn(loop_fn(thumbnails.clone(), |thumbnails| {
+///     thumbnails.join_next().map(move |result| match result {
+///         Some(result) => { show_thumbnail(result); Loop::Continue(thumbnails) },
+///         None => Loop::Break(()),
+///     })
+/// }));
+/// ```
+pub struct LocalTaskSet<T, E> {
+    state: Rc<RefCell<TaskSetState<T, E>>>,
+}
+
+impl<T, E> Clone for LocalTaskSet<T, E> {
+    fn clone(&self) -> Self {
+        LocalTaskSet {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T: 'static, E: 'static> LocalTaskSet<T, E> {
+    /// Constructs an empty set whose futures are executed on `executor`
+    pub fn new(executor: &GtkEventLoopAsyncExecutor) -> Self {
+        LocalTaskSet {
+            state: Rc::new(RefCell::new(
+                TaskSetState {
+                    executor: executor.clone(),
+                    running: FuturesUnordered::new(),
+                    incoming: Vec::new(),
+                    results: VecDeque::new(),
+                    outstanding: 0,
+                    abort_epoch: 0,
+                    driver_spawned: false,
+                    driver_task: None,
+                    waiting_tasks: Vec::new(),
+                }
+            ))
+        }
+    }
+
+    /// Adds `f` to the set. It starts executing when the set's driver task polls it, at the latest
+    /// on the next main loop iteration.
+    pub fn insert<F: Future<Item=T, Error=E> + 'static>(&self, f: F) {
+        let spawn_driver = {
+            let mut state = self.state.borrow_mut();
+            state.incoming.push(Box::new(f));
+            state.outstanding += 1;
+            if let Some(task) = state.driver_task.take() {
+                task.notify();
+            }
+            !std::mem::replace(&mut state.driver_spawned, true)
+        };
+
+        if spawn_driver {
+            let executor = self.state.borrow().executor.clone();
+            executor.spawn_named("LocalTaskSet", drive(Rc::downgrade(&self.state)));
+        }
+    }
+
+    /// Returns the number of futures that have not completed yet
+    pub fn len(&self) -> usize {
+        self.state.borrow().outstanding
+    }
+
+    /// Returns `true` if there are no running futures and no results that were not taken with `join_next()`
+    pub fn is_empty(&self) -> bool {
+        let state = self.state.borrow();
+        state.outstanding == 0 && state.results.is_empty()
+    }
+
+    /// Drops all running futures and results that were not taken yet
+    pub fn abort_all(&self) {
+        let (running, incoming, results, waiting_tasks) = {
+            let mut state = self.state.borrow_mut();
+            state.outstanding = 0;
+            state.abort_epoch += 1;
+            (
+                std::mem::replace(&mut state.running, FuturesUnordered::new()),
+                std::mem::replace(&mut state.incoming, Vec::new()),
+                std::mem::replace(&mut state.results, VecDeque::new()),
+                std::mem::replace(&mut state.waiting_tasks, Vec::new()),
+            )
+        };
+
+        // Futures are dropped outside of the borrow since their destructors may use the set
+        drop(running);
+        drop(incoming);
+        drop(results);
+        for task in waiting_tasks {
+            task.notify();
+        }
+    }
+
+    /// Returns a future that resolves with the result of the next completed future,
+    /// or with `None` if the set has no running futures and no pending results.
+    pub fn join_next(&self) -> impl Future<Item=Option<Result<T, E>>, Error=()> {
+        let state = self.state.clone();
+
+        futures::future::poll_fn(move || {
+            let mut state = state.borrow_mut();
+            if let Some(result) = state.results.pop_front() {
+                return Ok(Async::Ready(Some(result)));
+            }
+            if state.outstanding == 0 {
+                return Ok(Async::Ready(None));
+            }
+            state.waiting_tasks.push(futures::task::current());
+            Ok(Async::NotReady)
+        })
+    }
+}
+
+fn drive<T: 'static, E: 'static>(state: Weak<RefCell<TaskSetState<T, E>>>) -> impl Future<Item=(), Error=()> {
+    futures::future::poll_fn(move || {
+        let state = match state.upgrade() {
+            Some(state) => state,
+            None => return Ok(Async::Ready(())),
+        };
+
+        loop {
+            let (mut running, epoch) = {
+                let mut state = state.borrow_mut();
+                let mut running = std::mem::replace(&mut state.running, FuturesUnordered::new());
+                for f in state.incoming.drain(..) {
+                    running.push(f);
+                }
+                (running, state.abort_epoch)
+            };
+
+            // The set is not borrowed while futures are polled
+            loop {
+                let result = match running.poll() {
+                    Ok(Async::Ready(Some(item))) => Ok(item),
+                    Err(error) => Err(error),
+                    Ok(Async::Ready(None)) | Ok(Async::NotReady) => break,
+                };
+
+                let waiting_tasks = {
+                    let mut state = state.borrow_mut();
+                    if state.abort_epoch != epoch {
+                        // A future called `abort_all()`; results of the aborted futures are discarded
+                        break;
+                    }
+                    state.outstanding = state.outstanding.saturating_sub(1);
+                    state.results.push_back(result);
+                    std::mem::replace(&mut state.waiting_tasks, Vec::new())
+                };
+                for task in waiting_tasks {
+                    task.notify();
+                }
+            }
+
+            if state.borrow().abort_epoch != epoch {
+                // Dropped outside of the borrow since their destructors may use the set
+                drop(running);
+                continue;
+            }
+
+            let mut state = state.borrow_mut();
+            if !state.incoming.is_empty() {
+                // Futures were inserted during the poll; they must be polled once to register their wakeups
+                state.running = running;
+                continue;
+            }
+
+            if running.is_empty() {
+                state.driver_spawned = false;
+                return Ok(Async::Ready(()));
+            }
+
+            state.running = running;
+            state.driver_task = Some(futures::task::current());
+            return Ok(Async::NotReady);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::prelude::*;
+    use futures::future;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::executor::GtkEventLoopAsyncExecutor;
+    use super::LocalTaskSet;
+
+    struct DropFlag(Rc<Cell<bool>>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[test]
+    fn abort_all_from_member_future() {
+        let executor = GtkEventLoopAsyncExecutor::with_context(glib::MainContext::new());
+        let set = LocalTaskSet::<u32, ()>::new(&executor);
+
+        let dropped = Rc::new(Cell::new(false));
+        {
+            let flag = DropFlag(dropped.clone());
+            set.insert(future::poll_fn(move || {
+                let _ = &flag;
+                Ok(Async::NotReady)
+            }));
+        }
+        let aborting = set.clone();
+        set.insert(future::lazy(move || {
+            aborting.abort_all();
+            Ok(1)
+        }));
+
+        executor.run_until_stalled();
+        assert!(dropped.get());
+        assert_eq!(set.len(), 0);
+        assert!(set.is_empty());
+        assert_eq!(executor.run_until(set.join_next(), 100).unwrap(), Ok(None));
+
+        set.insert(future::ok(2));
+        assert_eq!(executor.run_until(set.join_next(), 100).unwrap(), Ok(Some(Ok(2))));
+    }
+}
@@ -0,0 +1,195 @@
+use futures::prelude::*;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::rc::Rc;
+
+use crate::errors::TaskFailure;
+
+/// Number of machine words a future may occupy to be stored inline
+const INLINE_WORDS: usize = 3;
+
+type Inline = MaybeUninit<[usize; INLINE_WORDS]>;
+
+/// Storage of a task's future with small-buffer optimization.
+///
+/// Futures that fit into `INLINE_WORDS` words (with at most word alignment) are stored in the slot itself,
+/// so tiny continuations don't pay for a heap allocation on spawn; bigger futures are boxed.
+/// A future that is already boxed (e.g. spawned through `GuiSpawn`) fits inline and is not boxed again.
+pub(crate) struct TaskSlot {
+    storage: Storage,
+    /// The inline buffer hides the type of the future, so the slot must not be `Send` whatever it stores
+    _not_send: PhantomData<Rc<()>>,
+}
+
+enum Storage {
+    Inline {
+        data: Inline,
+        poll: unsafe fn(*mut u8) -> Poll<(), TaskFailure>,
+        drop: unsafe fn(*mut u8),
+    },
+    #[cfg(feature = "task-arena")]
+    Arena {
+        block: ArenaBlock,
+        arena: Rc<TaskArena>,
+        poll: unsafe fn(*mut u8) -> Poll<(), TaskFailure>,
+        drop: unsafe fn(*mut u8),
+    },
+    Boxed(Box<Future<Item=(), Error=TaskFailure>>),
+}
+
+unsafe fn poll_inline<F: Future<Item=(), Error=TaskFailure>>(data: *mut u8) -> Poll<(), TaskFailure> {
+    (*(data as *mut F)).poll()
+}
+
+unsafe fn drop_inline<F>(data: *mut u8) {
+    std::ptr::drop_in_place(data as *mut F);
+}
+
+impl TaskSlot {
+    /// Stores `f` inline if it is small enough, in a block of `arena` if one is big enough, or boxes it otherwise
+    #[cfg(feature = "task-arena")]
+    pub(crate) fn in_arena<F: Future<Item=(), Error=TaskFailure> + 'static>(f: F, arena: &Rc<TaskArena>) -> Self {
+        let class = TaskArena::size_class(std::mem::size_of::<F>(), std::mem::align_of::<F>());
+        let class = match class {
+            Some(class) if std::mem::size_of::<F>() > std::mem::size_of::<Inline>() => class,
+            _ => return TaskSlot::new(f),
+        };
+
+        let mut block = arena.allocate(class);
+        // Safety: the block is big enough and aligned for `F` (see `size_class()`), and its address does not change
+        unsafe { std::ptr::write(block.as_mut_ptr() as *mut F, f) };
+
+        TaskSlot {
+            storage: Storage::Arena {
+                block,
+                arena: arena.clone(),
+                poll: poll_inline::<F>,
+                drop: drop_inline::<F>,
+            },
+            _not_send: PhantomData,
+        }
+    }
+
+    pub(crate) fn new<F: Future<Item=(), Error=TaskFailure> + 'static>(f: F) -> Self {
+        let fits = std::mem::size_of::<F>() <= std::mem::size_of::<Inline>()
+            && std::mem::align_of::<F>() <= std::mem::align_of::<Inline>();
+
+        let storage = if fits {
+            let mut data = Inline::uninit();
+            // Safety: the buffer is big enough and aligned for `F` (checked above).
+            // Futures 0.1 futures may be moved between polls, so moving the buffer with the slot is fine.
+            unsafe { std::ptr::write(data.as_mut_ptr() as *mut F, f) };
+            Storage::Inline {
+                data,
+                poll: poll_inline::<F>,
+                drop: drop_inline::<F>,
+            }
+        } else {
+            Storage::Boxed(Box::new(f))
+        };
+
+        TaskSlot {
+            storage,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Future for TaskSlot {
+    type Item = ();
+    type Error = TaskFailure;
+
+    fn poll(&mut self) -> Poll<(), TaskFailure> {
+        match self.storage {
+            // Safety: `data` holds an initialized future of the type `poll` was instantiated with
+            Storage::Inline { ref mut data, poll, .. } => unsafe { poll(data.as_mut_ptr() as *mut u8) },
+            // Safety: the block holds an initialized future of the type `poll` was instantiated with
+            #[cfg(feature = "task-arena")]
+            Storage::Arena { ref mut block, poll, .. } => unsafe { poll(block.as_mut_ptr() as *mut u8) },
+            Storage::Boxed(ref mut f) => f.poll(),
+        }
+    }
+}
+
+impl Drop for TaskSlot {
+    fn drop(&mut self) {
+        match self.storage {
+            // Safety: `data` holds an initialized future of the type `drop` was instantiated with; it is dropped once
+            Storage::Inline { ref mut data, drop, .. } => unsafe { drop(data.as_mut_ptr() as *mut u8) },
+            #[cfg(feature = "task-arena")]
+            Storage::Arena { ref mut block, ref arena, drop, .. } => {
+                // Safety: same as for inline futures
+                unsafe { drop(block.as_mut_ptr() as *mut u8) };
+                arena.recycle(std::mem::replace(block, Vec::new().into_boxed_slice()));
+            },
+            Storage::Boxed(_) => {},
+        }
+    }
+}
+
+/// A 16-byte aligned unit of arena blocks
+#[cfg(feature = "task-arena")]
+#[derive(Clone, Copy)]
+#[repr(align(16))]
+pub(crate) struct Chunk([u8; 16]);
+
+#[cfg(feature = "task-arena")]
+type ArenaBlock = Box<[Chunk]>;
+
+/// Sizes of arena blocks in bytes; futures that are bigger are boxed
+#[cfg(feature = "task-arena")]
+const SIZE_CLASSES: [usize; 4] = [64, 128, 256, 512];
+
+/// Number of free blocks kept per size class; blocks freed beyond that are returned to the allocator
+#[cfg(feature = "task-arena")]
+const MAX_FREE_BLOCKS: usize = 1024;
+
+/// Pool of memory blocks for task futures, owned by an executor (feature `task-arena`).
+///
+/// Futures that don't fit inline are stored in a block of the smallest fitting size class.
+/// When the task completes, the block is kept for the next task instead of being freed, so applications
+/// that spawn thousands of short-lived tasks per second don't allocate for each of them.
+#[cfg(feature = "task-arena")]
+pub(crate) struct TaskArena {
+    free: std::cell::RefCell<Vec<Vec<ArenaBlock>>>,
+}
+
+#[cfg(feature = "task-arena")]
+impl TaskArena {
+    pub(crate) fn new() -> Self {
+        TaskArena {
+            free: std::cell::RefCell::new(SIZE_CLASSES.iter().map(|_| Vec::new()).collect()),
+        }
+    }
+
+    fn size_class(size: usize, align: usize) -> Option<usize> {
+        if align > std::mem::align_of::<Chunk>() {
+            return None;
+        }
+        SIZE_CLASSES.iter().position(|&class_size| size <= class_size)
+    }
+
+    fn allocate(&self, class: usize) -> ArenaBlock {
+        match self.free.borrow_mut()[class].pop() {
+            Some(block) => block,
+            None => vec![Chunk([0; 16]); SIZE_CLASSES[class] / 16].into_boxed_slice(),
+        }
+    }
+
+    fn recycle(&self, block: ArenaBlock) {
+        let size = block.len() * 16;
+        if let Some(class) = SIZE_CLASSES.iter().position(|&class_size| class_size == size) {
+            let mut free = self.free.borrow_mut();
+            if free[class].len() < MAX_FREE_BLOCKS {
+                free[class].push(block);
+            }
+        }
+    }
+
+    /// Frees all blocks that are not in use
+    pub(crate) fn clear(&self) {
+        for blocks in self.free.borrow_mut().iter_mut() {
+            *blocks = Vec::new();
+        }
+    }
+}
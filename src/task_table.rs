@@ -0,0 +1,187 @@
+use std::collections::BTreeSet;
+
+/// Storage of pending tasks indexed by task id.
+///
+/// Ids are indices of slots and are recycled through a free list once a task is gone, so ids stay small
+/// and the slots dense however many tasks were spawned over the executor's lifetime.
+/// Recycled ids are told apart from earlier tasks with the same id by generations, which are never reused.
+///
+/// An id is reserved from `allocate()` until `release()`; while the task is taken out
+/// (e.g. for the duration of its poll) its slot stays empty but the id is not reused.
+pub(crate) struct TaskTable<T> {
+    slots: Vec<Option<T>>,
+    /// Released ids below the last slot; the lowest one is reused first, so that the table stays dense.
+    /// Released trailing slots are dropped right away.
+    free: BTreeSet<usize>,
+    len: usize,
+}
+
+impl<T> TaskTable<T> {
+    pub(crate) fn new() -> Self {
+        TaskTable {
+            slots: Vec::new(),
+            free: BTreeSet::new(),
+            len: 0,
+        }
+    }
+
+    /// Reserves an id for a new task
+    pub(crate) fn allocate(&mut self) -> usize {
+        match self.free.iter().next().cloned() {
+            Some(id) => {
+                self.free.remove(&id);
+                id
+            },
+            None => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            },
+        }
+    }
+
+    /// Makes `id` available for new tasks; its slot must be empty
+    pub(crate) fn release(&mut self, id: usize) {
+        debug_assert!(self.slots[id].is_none(), "released id {} of a stored task", id);
+        if id + 1 != self.slots.len() {
+            self.free.insert(id);
+            return;
+        }
+
+        self.slots.pop();
+        while let Some(&last) = self.free.iter().next_back() {
+            if last + 1 != self.slots.len() {
+                break;
+            }
+            self.free.remove(&last);
+            self.slots.pop();
+        }
+    }
+
+    /// Stores `task` under the reserved `id`
+    pub(crate) fn insert(&mut self, id: usize, task: T) {
+        if self.slots[id].replace(task).is_none() {
+            self.len += 1;
+        }
+    }
+
+    /// Takes the task out of its slot; the id stays reserved
+    pub(crate) fn remove(&mut self, id: usize) -> Option<T> {
+        let task = self.slots.get_mut(id).and_then(|slot| slot.take());
+        if task.is_some() {
+            self.len -= 1;
+        }
+        task
+    }
+
+    pub(crate) fn get(&self, id: usize) -> Option<&T> {
+        self.slots.get(id).and_then(|slot| slot.as_ref())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item=(usize, &T)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(id, slot)| slot.as_ref().map(|task| (id, task)))
+    }
+
+    pub(crate) fn ids(&self) -> impl Iterator<Item=usize> + '_ {
+        self.iter().map(|(id, _)| id)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    /// Releases unused memory if at least half of the capacity is unused.
+    /// Slots below a live task can't be released, so a long-lived task with a high id keeps the table large;
+    /// the threshold makes repeated calls in that state cheap.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        if self.slots.len() * 2 <= self.slots.capacity() {
+            self.slots.shrink_to_fit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TaskTable;
+
+    #[test]
+    fn reuses_lowest_released_id() {
+        let mut table = TaskTable::<()>::new();
+        let ids: Vec<usize> = (0..4).map(|_| table.allocate()).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+
+        table.release(2);
+        table.release(1);
+        assert_eq!(table.allocate(), 1);
+        assert_eq!(table.allocate(), 2);
+        assert_eq!(table.allocate(), 4);
+    }
+
+    #[test]
+    fn reused_id_holds_task_of_new_generation() {
+        let mut table = TaskTable::new();
+        let id = table.allocate();
+        table.insert(id, 0u64);
+        assert_eq!(table.remove(id), Some(0));
+        table.release(id);
+
+        let reused = table.allocate();
+        assert_eq!(reused, id);
+        table.insert(reused, 1u64);
+        assert_eq!(table.get(id), Some(&1));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn reserved_id_is_not_reused_while_task_is_taken_out() {
+        let mut table = TaskTable::new();
+        let id = table.allocate();
+        table.insert(id, ());
+        table.remove(id);
+        assert_ne!(table.allocate(), id);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn releasing_trailing_ids_drops_slots() {
+        let mut table = TaskTable::<()>::new();
+        let ids: Vec<usize> = (0..8).map(|_| table.allocate()).collect();
+        for &id in &ids[2..7] {
+            table.release(id);
+        }
+        table.release(7);
+        assert_eq!(table.slots.len(), 2);
+        assert!(table.free.is_empty());
+        assert_eq!(table.allocate(), 2);
+    }
+
+    #[test]
+    fn shrink_with_live_high_id() {
+        let mut table = TaskTable::new();
+        let ids: Vec<usize> = (0..1000).map(|_| table.allocate()).collect();
+        let high = ids[999];
+        table.insert(high, ());
+        for &id in &ids[..999] {
+            table.release(id);
+        }
+
+        // The live task keeps all slots below it, so there is nothing to release
+        let capacity = table.capacity();
+        table.shrink_to_fit();
+        assert_eq!(table.capacity(), capacity);
+        assert_eq!(table.get(high), Some(&()));
+
+        table.remove(high);
+        table.release(high);
+        table.shrink_to_fit();
+        assert_eq!(table.capacity(), 0);
+        assert_eq!(table.allocate(), 0);
+    }
+}
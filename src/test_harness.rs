@@ -0,0 +1,289 @@
+//! Helpers for end-to-end tests of async GUI code.
+//!
+//! Gtk+ may only be used from the thread that initialized it, while the test runner runs every test on a new thread
+//! (even with `--test-threads=1`). `#[gtk_async_test]` therefore runs all Gtk+ tests of a test binary
+//! on a single dedicated thread that initializes Gtk+ once; tests that call `run()` directly must ensure
+//! that they run on the thread that initialized Gtk+.
+
+use futures::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::promise::Promise;
+use crate::sync::Mutex;
+use crate::timer::duration_to_ms;
+
+type TestJob = Box<FnOnce() + Send>;
+
+lazy_static::lazy_static! {
+    /// Sender of the jobs to the thread that runs Gtk+ tests; the thread is started by the first Gtk+ test
+    static ref GTK_TEST_THREAD: Mutex<mpsc::Sender<TestJob>> = Mutex::new(spawn_gtk_test_thread());
+}
+
+fn spawn_gtk_test_thread() -> mpsc::Sender<TestJob> {
+    let (sender, receiver) = mpsc::channel::<TestJob>();
+    thread::Builder::new()
+        .name("gtk-test".to_string())
+        .spawn(move || {
+            gtk::init().expect("Failed to initialize Gtk+");
+            for job in receiver {
+                job();
+            }
+        })
+        .expect("Failed to start Gtk+ test thread");
+    sender
+}
+
+fn describe_pending_tasks(executor: &GtkEventLoopAsyncExecutor) -> String {
+    let tasks = executor.pending_tasks();
+    if tasks.is_empty() {
+        return "no pending tasks".to_string();
+    }
+
+    let descriptions: Vec<String> = tasks.into_iter()
+        .map(|(id, name)| match name {
+            Some(name) => format!("{} ({})", id, name),
+            None => id.to_string(),
+        })
+        .collect();
+    format!("pending tasks: {}", descriptions.join(", "))
+}
+
+/// Executes specified future on `executor` and runs the real Gtk+ main loop until the future resolves.
+/// Returns the result of the future.
+///
+/// *Panics* (failing the test) if the future does not resolve within `timeout`. The panic message lists
+/// the tasks that were still pending; these tasks are cancelled before panicking, so that they don't leak into the next test.
+/// Essential for writing end-to-end async widget tests that can't hang CI forever.
+///
+/// Must be called on the thread that initialized Gtk+; as the test runner runs every test on a new thread,
+/// a test binary can have only one such test (`#[gtk_async_test]` has no such limitation).
+///
+/// Example:
+/// ```rust
+/// #[test]
+/// fn search_shows_results() {
+///     gtk::init().unwrap();
+///     let executor = GtkEventLoopAsyncExecutor::new();
+///     let view = SearchView::new(&executor);
+///
+///     let rows = test_harness::run(&executor, view.search("gtk"), Duration::from_secs(5)).unwrap();
+///     assert_eq!(rows, 3);
+/// }
+/// ```
+pub fn run<F: Future + 'static>(executor: &GtkEventLoopAsyncExecutor, f: F, timeout: Duration) -> Result<F::Item, F::Error> {
+    let result = Rc::new(RefCell::new(None));
+    {
+        let result = result.clone();
+        executor.spawn(f.then(move |r| {
+            *result.borrow_mut() = Some(r);
+            gtk::main_quit();
+
+            Ok(())
+        }));
+    }
+
+    let timed_out = Rc::new(std::cell::Cell::new(false));
+    let watchdog = {
+        let timed_out = timed_out.clone();
+        gtk::timeout_add(duration_to_ms(timeout), move || {
+            timed_out.set(true);
+            gtk::main_quit();
+            gtk::Continue(false)
+        })
+    };
+
+    // The future may have resolved inside `spawn()`, quitting the main loop before it started
+    if result.borrow().is_none() {
+        gtk::main();
+    }
+
+    if !timed_out.get() {
+        glib::source::source_remove(watchdog);
+    }
+
+    let r = result.borrow_mut().take();
+    match r {
+        Some(r) => r,
+        None => {
+            let pending = describe_pending_tasks(executor);
+            executor.cancel_all();
+            panic!("future did not resolve within {:?}; {}", timeout, pending);
+        }
+    }
+}
+
+/// Runs the future produced by `make` on a fresh executor, iterating its main context until the future resolves.
+/// *Panics* (failing the test) if the future fails or does not resolve within `timeout`;
+/// the panic message lists tasks that are still pending.
+///
+/// With `headless` set, the executor uses a new `glib::MainContext` on the current thread and Gtk+ is not initialized.
+/// Otherwise the test runs on the dedicated Gtk+ test thread, one test at a time, and a panic of the test
+/// is resumed on the calling thread.
+#[doc(hidden)]
+pub fn run_test<M, F>(timeout: Duration, headless: bool, make: M)
+    where M: FnOnce(GtkEventLoopAsyncExecutor) -> F + Send + 'static,
+          F: Future<Item=(), Error=String> + 'static
+{
+    if headless {
+        run_test_on(GtkEventLoopAsyncExecutor::with_context(glib::MainContext::new()), timeout, make);
+        return;
+    }
+
+    let (result_sender, result_receiver) = mpsc::channel();
+    let job: TestJob = Box::new(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(move || {
+            run_test_on(GtkEventLoopAsyncExecutor::new(), timeout, make)
+        }));
+        let _ = result_sender.send(result);
+    });
+
+    let sent = GTK_TEST_THREAD.lock().send(job);
+    match sent.ok().and_then(|()| result_receiver.recv().ok()) {
+        Some(Ok(())) => {},
+        Some(Err(payload)) => panic::resume_unwind(payload),
+        None => panic!("Gtk+ test thread has exited (failed to initialize Gtk+?)"),
+    }
+}
+
+fn run_test_on<M, F>(executor: GtkEventLoopAsyncExecutor, timeout: Duration, make: M)
+    where M: FnOnce(GtkEventLoopAsyncExecutor) -> F,
+          F: Future<Item=(), Error=String> + 'static
+{
+    let context = executor.context();
+    assert!(context.acquire(), "main context of the test is owned by another thread");
+
+    let result = Rc::new(RefCell::new(None));
+    {
+        let result = result.clone();
+        executor.spawn(make(executor.clone()).then(move |r| {
+            *result.borrow_mut() = Some(r);
+
+            Ok(())
+        }));
+    }
+
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let timeout_source = {
+        let timed_out = timed_out.clone();
+        glib::source::timeout_source_new(duration_to_ms(timeout), None, glib::PRIORITY_DEFAULT, move || {
+            timed_out.store(true, Ordering::SeqCst);
+            glib::source::Continue(false)
+        })
+    };
+    timeout_source.attach(Some(&context));
+
+    context.push_thread_default();
+    while result.borrow().is_none() && !timed_out.load(Ordering::SeqCst) {
+        context.iteration(true);
+    }
+    context.pop_thread_default();
+    timeout_source.destroy();
+    context.release();
+
+    let r = result.borrow_mut().take();
+    match r {
+        Some(Ok(())) => {},
+        Some(Err(error)) => panic!("test failed: {}", error),
+        None => {
+            // The Gtk+ test thread outlives the test, so its tasks must not be left on the main context
+            let pending = describe_pending_tasks(&executor);
+            executor.cancel_all();
+            panic!("test did not complete within {:?}; {}", timeout, pending);
+        }
+    }
+}
+
+/// A toplevel window that is rendered offscreen, for tests that drive widgets without showing them on screen.
+///
+/// The widget hierarchy is realized and shown, so signal handlers and size allocation behave as in a real window.
+/// Together with `click()` and `set_text()` this enables headless-ish interaction tests of async handlers.
+///
+/// Example:
+/// ```rust
+/// let view = SearchView::new(&executor);
+/// let window = OffscreenWindow::new(view.widget());
+///
+/// let flow = set_text(&view.entry, "gtk")
+///     .and_then(move |_| click(&view.search_button))
+///     .and_then(move |_| view.results_loaded());
+/// test_harness::run(&executor, flow, Duration::from_secs(5)).unwrap();
+/// ```
+pub struct OffscreenWindow {
+    window: gtk::OffscreenWindow,
+}
+
+impl OffscreenWindow {
+    /// Puts `content` into a new offscreen window and shows it
+    pub fn new<W: IsA<gtk::Widget>>(content: &W) -> Self {
+        let window = gtk::OffscreenWindow::new();
+        window.add(content);
+        window.show_all();
+
+        OffscreenWindow {
+            window,
+        }
+    }
+
+    /// Returns the underlying offscreen window
+    pub fn window(&self) -> &gtk::OffscreenWindow {
+        &self.window
+    }
+}
+
+impl Drop for OffscreenWindow {
+    fn drop(&mut self) {
+        self.window.destroy();
+    }
+}
+
+/// Runs `action` from the main loop (as if it was triggered by an input event) and
+/// resolves on the following main loop iteration, after tasks spawned by signal handlers had a chance to start.
+fn on_main_loop<F: FnOnce() + 'static>(action: F) -> impl Future<Item=(), Error=()> {
+    let promise = Promise::new();
+
+    {
+        let promise = promise.clone();
+        let mut action = Some(action);
+        gtk::idle_add(move || {
+            if let Some(action) = action.take() {
+                action();
+            }
+            promise.resolve(());
+            gtk::Continue(false)
+        });
+    }
+
+    promise
+}
+
+/// Emits `clicked` on `button` through the main loop. Resolves after the handlers ran.
+pub fn click<B: IsA<gtk::Button>>(button: &B) -> impl Future<Item=(), Error=()> {
+    let button = button.clone();
+    on_main_loop(move || button.clicked())
+}
+
+/// Sets the text of `entry` through the main loop, emitting `changed`. Resolves after the handlers ran.
+pub fn set_text<E: IsA<gtk::Entry>>(entry: &E, text: &str) -> impl Future<Item=(), Error=()> {
+    let entry = entry.clone();
+    let text = text.to_string();
+    on_main_loop(move || entry.set_text(&text))
+}
+
+/// Emits `activate` on `entry` (as if Enter was pressed) through the main loop. Resolves after the handlers ran.
+pub fn activate<E: IsA<gtk::Entry>>(entry: &E) -> impl Future<Item=(), Error=()> {
+    let entry = entry.clone();
+    on_main_loop(move || {
+        entry.activate();
+    })
+}
@@ -0,0 +1,158 @@
+use futures::prelude::*;
+use futures::future;
+use futures::future::Loop;
+use gio::prelude::*;
+use gtk::prelude::*;
+
+use crate::cancel::CancellationToken;
+use crate::cancel::Cancelled;
+use crate::promise::Promise;
+
+/// Default amount of bytes that `load_file_into_buffer` inserts per main loop iteration
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+pub(crate) fn read_chunk(stream: &gio::InputStream, chunk_size: usize, cancellable: Option<&gio::Cancellable>) -> Promise<glib::Bytes, glib::Error> {
+    let promise = Promise::new();
+
+    {
+        let promise = promise.clone();
+        stream.read_bytes_async(chunk_size, glib::PRIORITY_DEFAULT, cancellable, move |result| {
+            match result {
+                Ok(bytes) => promise.resolve(bytes),
+                Err(error) => promise.reject(error),
+            }
+        });
+    }
+
+    promise
+}
+
+fn open_file(file: &gio::File, cancellable: &gio::Cancellable) -> Promise<gio::InputStream, glib::Error> {
+    let promise = Promise::new();
+
+    {
+        let promise = promise.clone();
+        file.read_async(glib::PRIORITY_DEFAULT, Some(cancellable), move |result| {
+            match result {
+                Ok(stream) => promise.resolve(stream.upcast()),
+                Err(error) => promise.reject(error),
+            }
+        });
+    }
+
+    promise
+}
+
+/// Cancels `cancellable` once `cancelled` resolves, so that the pending gio operation of `inner` fails right away
+struct CancelOnToken<F> {
+    inner: F,
+    cancelled: Option<Cancelled>,
+    cancellable: gio::Cancellable,
+}
+
+impl<F: Future> Future for CancelOnToken<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<F::Item, F::Error> {
+        let token_cancelled = match self.cancelled {
+            Some(ref mut cancelled) => cancelled.poll() == Ok(Async::Ready(())),
+            None => false,
+        };
+        if token_cancelled {
+            self.cancelled = None;
+            self.cancellable.cancel();
+        }
+        self.inner.poll()
+    }
+}
+
+/// Splits `pending` into the longest prefix that is complete UTF-8 text and
+/// the trailing bytes of a character that continues in the next chunk.
+/// Invalid sequences are replaced with U+FFFD.
+fn take_text(pending: &mut Vec<u8>, at_end: bool) -> String {
+    let mut text = String::new();
+
+    loop {
+        match std::str::from_utf8(pending) {
+            Ok(valid) => {
+                text.push_str(valid);
+                pending.clear();
+                return text;
+            },
+            Err(error) => {
+                let valid_up_to = error.valid_up_to();
+                text.push_str(std::str::from_utf8(&pending[..valid_up_to]).unwrap());
+
+                match error.error_len() {
+                    Some(invalid_len) => {
+                        text.push('\u{FFFD}');
+                        pending.drain(..valid_up_to + invalid_len);
+                    },
+                    None if at_end => {
+                        text.push('\u{FFFD}');
+                        pending.clear();
+                        return text;
+                    },
+                    None => {
+                        pending.drain(..valid_up_to);
+                        return text;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Loads contents of `file` into `buffer`, replacing the existing text.
+///
+/// The file is opened and read with asynchronous gio I/O in chunks of `chunk_size` bytes and every chunk is
+/// appended on its own main loop iteration, so opening a huge (or remote) file does not freeze the window.
+/// Returned future must be executed on Gtk+ main thread (e.g. with `GtkEventLoopAsyncExecutor::spawn`).
+/// It resolves when the whole file is loaded; when `token` is cancelled the pending gio operation is cancelled,
+/// the future fails with `gio::IOErrorEnum::Cancelled` and the text loaded so far is left in the buffer.
+pub fn load_file_into_buffer(file: &gio::File, buffer: &gtk::TextBuffer, chunk_size: usize, token: &CancellationToken)
+    -> impl Future<Item=(), Error=glib::Error>
+{
+    let buffer = buffer.clone();
+    let cancellable = gio::Cancellable::new();
+    let chunk_cancellable = cancellable.clone();
+    let checked_token = token.clone();
+
+    let load = open_file(file, &cancellable)
+        .and_then(move |stream| {
+            buffer.set_text("");
+
+            future::loop_fn(Vec::new(), move |mut pending| {
+                let buffer = buffer.clone();
+                let token = checked_token.clone();
+
+                read_chunk(&stream, chunk_size, Some(&chunk_cancellable))
+                    .and_then(move |bytes| {
+                        if token.is_cancelled() {
+                            return Err(glib::Error::new(gio::IOErrorEnum::Cancelled, "Loading was cancelled"));
+                        }
+
+                        let at_end = bytes.is_empty();
+                        pending.extend_from_slice(&bytes);
+
+                        let text = take_text(&mut pending, at_end);
+                        if !text.is_empty() {
+                            buffer.insert(&mut buffer.get_end_iter(), &text);
+                        }
+
+                        if at_end {
+                            Ok(Loop::Break(()))
+                        } else {
+                            Ok(Loop::Continue(pending))
+                        }
+                    })
+            })
+        });
+
+    CancelOnToken {
+        inner: load,
+        cancelled: Some(token.cancelled()),
+        cancellable,
+    }
+}
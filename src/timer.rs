@@ -0,0 +1,265 @@
+use futures::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::sync::Mutex;
+
+pub(crate) fn duration_to_ms(duration: Duration) -> u32 {
+    let ms = duration.as_secs() * 1000 + u64::from(duration.subsec_millis());
+    if ms > u64::from(u32::max_value()) { u32::max_value() } else { ms as u32 }
+}
+
+/// State of a `Delay` shared with its timeout source or mock timer
+struct TimerState {
+    fired: bool,
+    /// The task that polled the delay most recently
+    task: Option<futures::task::Task>,
+}
+
+type SharedTimer = Arc<Mutex<TimerState>>;
+
+fn fire(timer: &SharedTimer) {
+    let task = {
+        let mut state = timer.lock();
+        state.fired = true;
+        state.task.take()
+    };
+    if let Some(task) = task {
+        task.notify();
+    }
+}
+
+struct MockTimer {
+    deadline: Duration,
+    /// Registration order, which breaks ties between timers with the same deadline
+    seq: u64,
+    timer: SharedTimer,
+}
+
+struct MockClockState {
+    now: Duration,
+    next_seq: u64,
+    timers: Vec<MockTimer>,
+}
+
+thread_local! {
+    static MOCK_CLOCK: RefCell<Option<MockClock>> = RefCell::new(None);
+}
+
+/// `MockClock` replaces real time for timer futures (`Delay`) created on the current thread.
+///
+/// While a mock clock is installed, delays do not create glib timeout sources; instead they fire when
+/// the mock time is advanced past their deadline with `advance()`. This makes tests of debounce, timeout and retry logic
+/// instant and deterministic.
+///
+/// Example:
+/// ```rust
+/// let clock = MockClock::install();
+/// saver.changed();
+/// clock.advance(Duration::from_secs(2));
+/// executor.run_until_stalled();
+/// assert!(!saver.is_dirty());
+/// clock.uninstall();
+/// ```
+#[derive(Clone)]
+pub struct MockClock {
+    state: Rc<RefCell<MockClockState>>,
+}
+
+impl MockClock {
+    /// Installs a new mock clock for the current thread, replacing the previously installed one.
+    /// Mock time starts at zero.
+    pub fn install() -> MockClock {
+        let clock = MockClock {
+            state: Rc::new(RefCell::new(
+                MockClockState {
+                    now: Duration::from_secs(0),
+                    next_seq: 0,
+                    timers: Vec::new(),
+                }
+            ))
+        };
+
+        MOCK_CLOCK.with(|current| *current.borrow_mut() = Some(clock.clone()));
+
+        clock
+    }
+
+    /// Removes the mock clock from the current thread; timers created afterwards use real time
+    pub fn uninstall(&self) {
+        MOCK_CLOCK.with(|current| *current.borrow_mut() = None);
+    }
+
+    /// Returns the mock time elapsed since the clock was installed
+    pub fn now(&self) -> Duration {
+        self.state.borrow().now
+    }
+
+    /// Advances mock time by `duration` and wakes all delays whose deadline has passed,
+    /// in the order of their deadlines (delays with the same deadline in the order they were first polled).
+    /// Woken tasks are polled when the main context is iterated (e.g. with `run_until_stalled()`).
+    pub fn advance(&self, duration: Duration) {
+        let expired = {
+            let mut state = self.state.borrow_mut();
+            state.now += duration;
+
+            let now = state.now;
+            let (mut expired, pending): (Vec<MockTimer>, Vec<MockTimer>) = state.timers.drain(..).partition(|timer| timer.deadline <= now);
+            state.timers = pending;
+            expired.sort_by_key(|timer| (timer.deadline, timer.seq));
+            expired
+        };
+
+        for expired in expired {
+            fire(&expired.timer);
+        }
+    }
+
+    fn register(&self, duration: Duration, timer: SharedTimer) {
+        let mut state = self.state.borrow_mut();
+        let deadline = state.now + duration;
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.timers.push(MockTimer {
+            deadline,
+            seq,
+            timer,
+        });
+    }
+
+    fn current() -> Option<MockClock> {
+        MOCK_CLOCK.with(|current| current.borrow().clone())
+    }
+}
+
+/// A point in time of the mock clock if one is installed, or of real time otherwise
+#[derive(Clone, Copy)]
+pub(crate) enum Timestamp {
+    Real(Instant),
+    Mock(Duration),
+}
+
+impl Timestamp {
+    pub(crate) fn now() -> Timestamp {
+        match MockClock::current() {
+            Some(clock) => Timestamp::Mock(clock.now()),
+            None => Timestamp::Real(Instant::now()),
+        }
+    }
+
+    /// Returns the time passed since this timestamp, measured by the same clock
+    pub(crate) fn elapsed(&self) -> Duration {
+        match *self {
+            Timestamp::Real(instant) => instant.elapsed(),
+            Timestamp::Mock(then) => MockClock::current()
+                .and_then(|clock| clock.now().checked_sub(then))
+                .unwrap_or_else(|| Duration::from_secs(0)),
+        }
+    }
+}
+
+/// A future that resolves after the specified time has passed.
+/// The timer is a glib timeout source attached to the thread-default main context of the thread that polls it
+/// (Gtk+ main loop for tasks of `GtkEventLoopAsyncExecutor`).
+/// Dropping the `Delay` removes the timeout source.
+/// If a `MockClock` is installed when the delay is first polled, the delay follows mock time instead.
+pub struct Delay {
+    duration: Duration,
+    timer: SharedTimer,
+    source: Option<glib::Source>,
+    registered_with_mock: bool,
+}
+
+/// Returns a future that resolves after `duration`
+pub fn delay(duration: Duration) -> Delay {
+    Delay {
+        duration,
+        timer: Arc::new(Mutex::new(
+            TimerState {
+                fired: false,
+                task: None,
+            }
+        )),
+        source: None,
+        registered_with_mock: false,
+    }
+}
+
+impl Future for Delay {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        {
+            let mut state = self.timer.lock();
+            if state.fired {
+                return Ok(Async::Ready(()));
+            }
+            // The delay may be polled by another task than last time (e.g. after being moved), which must be woken
+            state.task = Some(futures::task::current());
+        }
+
+        if self.registered_with_mock || self.source.is_some() {
+            return Ok(Async::NotReady);
+        }
+
+        if let Some(clock) = MockClock::current() {
+            clock.register(self.duration, self.timer.clone());
+            self.registered_with_mock = true;
+            return Ok(Async::NotReady);
+        }
+
+        let timer = self.timer.clone();
+        let source = glib::source::timeout_source_new(duration_to_ms(self.duration), None, glib::PRIORITY_DEFAULT, move || {
+            fire(&timer);
+            glib::source::Continue(false)
+        });
+        source.attach(Some(&glib::MainContext::ref_thread_default()));
+        self.source = Some(source);
+
+        Ok(Async::NotReady)
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        if let Some(source) = self.source.take() {
+            source.destroy();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::prelude::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use crate::executor::GtkEventLoopAsyncExecutor;
+    use super::delay;
+    use super::MockClock;
+
+    #[test]
+    fn mock_clock_fires_delays_in_deadline_order() {
+        let clock = MockClock::install();
+        let executor = GtkEventLoopAsyncExecutor::with_context(glib::MainContext::new());
+        let fired = Rc::new(RefCell::new(Vec::new()));
+
+        for &(label, secs) in &[("c", 3), ("a", 1), ("b1", 2), ("b2", 2)] {
+            let fired = fired.clone();
+            executor.spawn(delay(Duration::from_secs(secs)).map(move |()| fired.borrow_mut().push(label)));
+            // Registers the delay, so that delays with the same deadline are registered in spawning order
+            executor.run_until_stalled();
+        }
+
+        clock.advance(Duration::from_secs(5));
+        executor.run_until_stalled();
+        clock.uninstall();
+
+        assert_eq!(*fired.borrow(), vec!["a", "b1", "b2", "c"]);
+    }
+}
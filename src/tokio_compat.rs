@@ -0,0 +1,32 @@
+use futures::prelude::*;
+
+use crate::executor::RemoteSpawner;
+use crate::executor::SpawnError;
+
+fn to_tokio_error(error: SpawnError) -> tokio_executor::SpawnError {
+    match error {
+        SpawnError::AtCapacity => tokio_executor::SpawnError::at_capacity(),
+        _ => tokio_executor::SpawnError::shutdown(),
+    }
+}
+
+/// Lets libraries that require a tokio executor schedule their futures onto Gtk+ main loop
+impl tokio_executor::Executor for RemoteSpawner {
+    fn spawn(&mut self, future: Box<Future<Item=(), Error=()> + Send>) -> Result<(), tokio_executor::SpawnError> {
+        RemoteSpawner::spawn(self, future).map_err(to_tokio_error)
+    }
+
+    fn status(&self) -> Result<(), tokio_executor::SpawnError> {
+        RemoteSpawner::status(self).map_err(to_tokio_error)
+    }
+}
+
+impl<F: Future<Item=(), Error=()> + Send + 'static> tokio_executor::TypedExecutor<F> for RemoteSpawner {
+    fn spawn(&mut self, future: F) -> Result<(), tokio_executor::SpawnError> {
+        RemoteSpawner::spawn(self, future).map_err(to_tokio_error)
+    }
+
+    fn status(&self) -> Result<(), tokio_executor::SpawnError> {
+        RemoteSpawner::status(self).map_err(to_tokio_error)
+    }
+}
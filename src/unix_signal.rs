@@ -0,0 +1,95 @@
+use futures::prelude::*;
+use futures::sync::mpsc;
+
+/// Unix signals that can be awaited with `unix_signal_future` and `unix_signal_stream`.
+/// These are the signals supported by `glib::unix_signal_add`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// SIGHUP
+    Hup,
+    /// SIGINT (sent by Ctrl+C in a terminal)
+    Int,
+    /// SIGTERM
+    Term,
+}
+
+impl Signal {
+    fn signum(self) -> i32 {
+        match self {
+            Signal::Hup => 1,
+            Signal::Int => 2,
+            Signal::Term => 15,
+        }
+    }
+}
+
+/// Returns a stream that yields an item every time the process receives `signal`.
+///
+/// Signals are delivered through glib main loop, so the items arrive on Gtk+ main thread
+/// and it's safe to react to them by manipulating widgets.
+/// The signal handler is removed when the stream is dropped.
+pub fn unix_signal_stream(signal: Signal) -> impl Stream<Item=Signal, Error=()> {
+    SignalStream::new(signal)
+}
+
+/// Returns a future that resolves when the process receives `signal`.
+/// The signal handler is removed once the signal arrives (so that the next signal gets its default handling
+/// or reaches another handler) or when the future is dropped.
+///
+/// Example:
+/// ```rust
+/// // Close the main window gracefully on Ctrl+C
+/// let ctrl_c = unix_signal_future(Signal::Int);
+/// gtk_executor.run_main_until(
+///     ctrl_c.select(window_closed(&window)).map(|_| ()).map_err(|_| ())
+/// );
+/// ```
+pub fn unix_signal_future(signal: Signal) -> impl Future<Item=Signal, Error=()> {
+    SignalStream::new(signal)
+        .into_future()
+        .map(move |(received, stream)| {
+            // Dropping the stream removes the handler
+            drop(stream);
+            received.unwrap_or(signal)
+        })
+        .map_err(|_| ())
+}
+
+/// Receives signals from a glib unix signal source, which is removed when the stream is dropped
+struct SignalStream {
+    receiver: mpsc::UnboundedReceiver<Signal>,
+    source: Option<glib::SourceId>,
+}
+
+impl SignalStream {
+    fn new(signal: Signal) -> Self {
+        let (sender, receiver) = mpsc::unbounded();
+
+        let source = glib::source::unix_signal_add(signal.signum(), move || {
+            let _ = sender.unbounded_send(signal);
+            glib::source::Continue(true)
+        });
+
+        SignalStream {
+            receiver,
+            source: Some(source),
+        }
+    }
+}
+
+impl Stream for SignalStream {
+    type Item = Signal;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Signal>, ()> {
+        self.receiver.poll()
+    }
+}
+
+impl Drop for SignalStream {
+    fn drop(&mut self) {
+        if let Some(source) = self.source.take() {
+            glib::source::source_remove(source);
+        }
+    }
+}
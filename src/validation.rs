@@ -0,0 +1,203 @@
+use futures::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::task::TaskHandle;
+use crate::weak::WeakCaptured;
+
+type ValidateFn = Rc<Fn(String) -> Box<Future<Item=(), Error=String>>>;
+
+#[derive(Clone, PartialEq)]
+enum FieldResult {
+    /// Validation of the current value is running
+    Pending,
+    Valid,
+    Invalid(String),
+}
+
+struct Field {
+    entry: WeakCaptured<gtk::Entry>,
+    error_label: WeakCaptured<gtk::Label>,
+    validate: ValidateFn,
+    result: FieldResult,
+    /// Incremented on every validation; used to find out whether a finished validation is still the latest one
+    generation: u64,
+    running: Option<TaskHandle<(), ()>>,
+}
+
+struct FormValidatorState {
+    fields: Vec<Field>,
+    submit: Option<WeakCaptured<gtk::Widget>>,
+}
+
+/// `FormValidator` orchestrates asynchronous validation of form fields.
+///
+/// Each field is an entry with an async validator that receives the entry text and fails with an error message
+/// (the validator may e.g. ask a backend whether a user name is taken). The entry is validated when it is added
+/// and every time its text changes. A validation that is still running when the text changes again is cancelled,
+/// so results of outdated values are never shown.
+///
+/// The error message of a field is shown in its error label (the label is hidden while the field is valid),
+/// and the submit button is sensitive only while every field is valid and no validation is running.
+///
+/// `FormValidator` is cloneable (all clones refer to the same validator) and may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let validator = FormValidator::new(&gtk_executor);
+/// validator.set_submit_button(&register_button);
+/// validator.add_entry(&name_entry, &name_error, move |name| {
+///     api.is_name_available(&name).and_then(|available| {
+///         if available { Ok(()) } else { Err("This name is taken".to_string()) }
+///     })
+/// });
+/// validator.add_entry(&email_entry, &email_error, |email| {
+///     if email.contains('@') { Ok(()) } else { Err("Not an e-mail address".to_string()) }
+/// });
+/// ```
+#[derive(Clone)]
+pub struct FormValidator {
+    executor: GtkEventLoopAsyncExecutor,
+    state: Rc<RefCell<FormValidatorState>>,
+}
+
+impl FormValidator {
+    /// Constructs validator with no fields
+    pub fn new(executor: &GtkEventLoopAsyncExecutor) -> Self {
+        FormValidator {
+            executor: executor.clone(),
+            state: Rc::new(RefCell::new(
+                FormValidatorState {
+                    fields: Vec::new(),
+                    submit: None,
+                }
+            )),
+        }
+    }
+
+    /// Sets the widget (usually the submit button) that is sensitive only while the form is valid
+    pub fn set_submit_button<W: IsA<gtk::Widget>>(&self, submit: &W) {
+        let submit: gtk::Widget = submit.clone().upcast();
+        self.state.borrow_mut().submit = Some(WeakCaptured::new(&submit));
+        self.update_submit();
+    }
+
+    /// Adds the field `entry` that is validated with `validate`. Error messages are shown in `error_label`.
+    pub fn add_entry<F, R>(&self, entry: &gtk::Entry, error_label: &gtk::Label, validate: F)
+        where F: Fn(String) -> R + 'static,
+              R: IntoFuture<Item=(), Error=String> + 'static
+    {
+        let index = {
+            let mut state = self.state.borrow_mut();
+            state.fields.push(Field {
+                entry: WeakCaptured::new(entry),
+                error_label: WeakCaptured::new(error_label),
+                validate: Rc::new(move |text| Box::new(validate(text).into_future()) as Box<Future<Item=(), Error=String>>),
+                result: FieldResult::Pending,
+                generation: 0,
+                running: None,
+            });
+            state.fields.len() - 1
+        };
+
+        let validator = self.clone();
+        entry.connect_changed(move |_| validator.validate_field(index));
+
+        self.validate_field(index);
+    }
+
+    /// Validates every field again, e.g. after a change that affects validity of fields that were not edited
+    pub fn revalidate(&self) {
+        let count = self.state.borrow().fields.len();
+        for index in 0..count {
+            self.validate_field(index);
+        }
+    }
+
+    /// Returns `true` if every field is valid and no validation is running
+    pub fn is_valid(&self) -> bool {
+        self.state.borrow().fields.iter().all(|field| field.result == FieldResult::Valid)
+    }
+
+    /// Returns `true` if a validation is running
+    pub fn is_pending(&self) -> bool {
+        self.state.borrow().fields.iter().any(|field| field.result == FieldResult::Pending)
+    }
+
+    fn validate_field(&self, index: usize) {
+        let (text, validate, generation, previous) = {
+            let mut state = self.state.borrow_mut();
+            let field = &mut state.fields[index];
+            let text = match field.entry.get() {
+                Some(entry) => entry.get_text().map(|text| text.to_string()).unwrap_or_default(),
+                None => return,
+            };
+            field.generation += 1;
+            field.result = FieldResult::Pending;
+            (text, field.validate.clone(), field.generation, field.running.take())
+        };
+
+        // Dropping the handle cancels the validation of the previous value
+        drop(previous);
+        self.update_submit();
+
+        let validator = self.clone();
+        let running = self.executor.task().name("FormValidator").spawn_with_handle(validate(text).then(move |result| {
+            validator.validation_finished(index, generation, result);
+            Ok::<(), ()>(())
+        }));
+
+        let mut state = self.state.borrow_mut();
+        let field = &mut state.fields[index];
+        if field.generation == generation && field.result == FieldResult::Pending {
+            field.running = Some(running);
+        } else {
+            // Validator resolved during spawning
+            running.detach();
+        }
+    }
+
+    fn validation_finished(&self, index: usize, generation: u64, result: Result<(), String>) {
+        let (error_label, running) = {
+            let mut state = self.state.borrow_mut();
+            let field = &mut state.fields[index];
+            if field.generation != generation {
+                return;
+            }
+            field.result = match result {
+                Ok(()) => FieldResult::Valid,
+                Err(message) => FieldResult::Invalid(message),
+            };
+            (field.error_label.clone(), field.running.take())
+        };
+
+        // The task that owns the handle is this one; it finishes anyway
+        if let Some(running) = running {
+            running.detach();
+        }
+
+        let result = self.state.borrow().fields[index].result.clone();
+        error_label.with(|label| match result {
+            FieldResult::Invalid(ref message) => {
+                label.set_text(message);
+                label.show();
+            },
+            _ => {
+                label.set_text("");
+                label.hide();
+            },
+        });
+
+        self.update_submit();
+    }
+
+    fn update_submit(&self) {
+        let submit = self.state.borrow().submit.clone();
+        if let Some(submit) = submit {
+            let valid = self.is_valid();
+            submit.with(|submit| submit.set_sensitive(valid));
+        }
+    }
+}
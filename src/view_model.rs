@@ -0,0 +1,77 @@
+use futures::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::promise::Promise;
+use crate::spawn::GuiSpawn;
+
+/// A view model: an object that holds presentation state and implements the commands of a view
+/// without referring to widgets.
+///
+/// Commands are methods that take `&mut self` and return a future. They are invoked through a `ViewModelProxy`
+/// (usually wrapped in a proxy type generated with `view_model_proxy!`), which runs them as tasks
+/// on a `GuiSpawn` and returns a `Promise` of the result. Widgets call the proxy from signal handlers,
+/// and unit tests drive the same proxy with `RecordingExecutor`.
+pub trait ViewModel: 'static {
+    /// Moves the view model into a proxy that runs its commands on `spawner`
+    fn into_proxy<S: GuiSpawn + 'static>(self, spawner: S) -> ViewModelProxy<Self> where Self: Sized {
+        ViewModelProxy::new(spawner, self)
+    }
+}
+
+/// Owns a view model and marshals command calls onto an executor. See `ViewModel`.
+///
+/// `ViewModelProxy` is cloneable (all clones refer to the same view model) and may only be used on Gtk+ main thread.
+pub struct ViewModelProxy<VM> {
+    spawner: Rc<GuiSpawn>,
+    model: Rc<RefCell<VM>>,
+}
+
+impl<VM> Clone for ViewModelProxy<VM> {
+    fn clone(&self) -> Self {
+        ViewModelProxy {
+            spawner: self.spawner.clone(),
+            model: self.model.clone(),
+        }
+    }
+}
+
+impl<VM: ViewModel> ViewModelProxy<VM> {
+    /// Constructs proxy that owns `model` and runs its commands on `spawner`
+    pub fn new<S: GuiSpawn + 'static>(spawner: S, model: VM) -> Self {
+        ViewModelProxy {
+            spawner: Rc::new(spawner),
+            model: Rc::new(RefCell::new(model)),
+        }
+    }
+
+    /// Invokes the command `f` in a task named `name` and returns a promise of its result.
+    /// The view model is borrowed only while `f` runs, not while the returned future is awaited.
+    pub fn call<F, R>(&self, name: &str, f: F) -> Promise<R::Item, R::Error>
+        where F: FnOnce(&mut VM) -> R + 'static,
+              R: IntoFuture + 'static,
+              R::Item: 'static,
+              R::Error: 'static
+    {
+        let promise = Promise::new();
+        let model = self.model.clone();
+
+        let result = promise.clone();
+        let command = futures::future::lazy(move || f(&mut model.borrow_mut()))
+            .then(move |outcome| {
+                match outcome {
+                    Ok(item) => result.resolve(item),
+                    Err(error) => result.reject(error),
+                }
+                Ok(())
+            });
+        self.spawner.spawn_boxed(Some(name), Box::new(command));
+
+        promise
+    }
+
+    /// Calls `f` with the view model, e.g. for reading presentation state in a test
+    pub fn with<R, F: FnOnce(&VM) -> R>(&self, f: F) -> R {
+        f(&self.model.borrow())
+    }
+}
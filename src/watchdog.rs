@@ -0,0 +1,133 @@
+use futures::prelude::*;
+use futures::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::executor::GtkEventLoopAsyncExecutor;
+use crate::task::Priority;
+
+/// Description of a blocked main loop that is passed to the watchdog callback
+#[derive(Debug, Clone)]
+pub struct BlockedMainLoop {
+    /// Time since the main loop last answered a ping
+    pub blocked_for: Duration,
+    /// Names of the tasks that were pending when the main loop last answered a ping.
+    /// The task that blocks the main thread is usually among them.
+    pub pending_tasks: Vec<String>,
+}
+
+struct WatchdogState {
+    /// Time of the latest answered ping; `None` until the main loop answers for the first time
+    answered_at: Option<Instant>,
+    pending_tasks: Vec<String>,
+    /// Set once the current stall is reported, so that a stall is reported once
+    reported: bool,
+}
+
+/// `Watchdog` detects a blocked Gtk+ main thread, e.g. an accidental blocking call in an async handler.
+///
+/// A watchdog thread pings the main loop through a task on the executor; if the main loop doesn't answer
+/// for `timeout`, `on_blocked` is called on the watchdog thread (the main thread is blocked, after all)
+/// with the time it has been blocked and the names of pending tasks. Each stall is reported once.
+/// Stalls are detected only after the main loop has answered the first ping, so a watchdog may be started
+/// before the main loop runs.
+///
+/// The watchdog runs until it is dropped. Its task is pending meanwhile, so drop the watchdog before waiting
+/// for the executor to become idle (`quit_when_idle()`, `shutdown_with_timeout()`).
+///
+/// Example:
+/// ```rust
+/// let _watchdog = Watchdog::start(&gtk_executor, Duration::from_secs(5), |blocked| {
+///     eprintln!("Main loop is blocked for {:?}; pending tasks: {:?}", blocked.blocked_for, blocked.pending_tasks);
+///     std::process::abort();
+/// });
+/// ```
+pub struct Watchdog {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Starts the watchdog thread. Must be called on Gtk+ main thread.
+    pub fn start<F>(executor: &GtkEventLoopAsyncExecutor, timeout: Duration, on_blocked: F) -> Watchdog
+        where F: Fn(&BlockedMainLoop) + Send + 'static
+    {
+        let state = Arc::new(Mutex::new(
+            WatchdogState {
+                answered_at: None,
+                pending_tasks: Vec::new(),
+                reported: false,
+            }
+        ));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (pings, received_pings) = mpsc::unbounded::<()>();
+
+        {
+            let state = state.clone();
+            let responder = executor.clone();
+            executor.task().name("Watchdog").priority(Priority::DEFAULT).spawn(received_pings.for_each(move |()| {
+                let pending_tasks = responder.pending_task_names();
+                let mut state = state.lock().unwrap();
+                state.answered_at = Some(Instant::now());
+                state.pending_tasks = pending_tasks;
+                state.reported = false;
+                Ok(())
+            }));
+        }
+
+        let thread = {
+            let stop = stop.clone();
+            let interval = (timeout / 4).max(Duration::from_millis(10));
+            std::thread::Builder::new()
+                .name("gtk-future-executor watchdog".to_string())
+                .spawn(move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        if pings.unbounded_send(()).is_err() {
+                            // The executor has been dropped
+                            return;
+                        }
+                        std::thread::park_timeout(interval);
+
+                        let blocked = {
+                            let mut state = state.lock().unwrap();
+                            let answered_at = state.answered_at;
+                            match answered_at {
+                                Some(answered_at) if !state.reported && answered_at.elapsed() >= timeout => {
+                                    state.reported = true;
+                                    Some(BlockedMainLoop {
+                                        blocked_for: answered_at.elapsed(),
+                                        pending_tasks: state.pending_tasks.clone(),
+                                    })
+                                },
+                                _ => None,
+                            }
+                        };
+                        if let Some(blocked) = blocked {
+                            on_blocked(&blocked);
+                        }
+                    }
+                })
+                .expect("failed to start watchdog thread")
+        };
+
+        Watchdog {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
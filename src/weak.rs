@@ -0,0 +1,60 @@
+use glib::object::ObjectExt;
+use glib::object::ObjectType;
+
+/// A weak reference to a widget (or any GObject) for capturing in long-lived task continuations.
+///
+/// Unlike a cloned widget, `WeakCaptured` does not keep the widget alive: a task that outlives its window
+/// neither prevents the window from being freed nor touches the destroyed widget, because `get()` returns `None`.
+pub struct WeakCaptured<W: ObjectType> {
+    weak: glib::WeakRef<W>,
+}
+
+impl<W: ObjectType> WeakCaptured<W> {
+    /// Captures a weak reference to `object`
+    pub fn new(object: &W) -> Self {
+        WeakCaptured {
+            weak: object.downgrade(),
+        }
+    }
+
+    /// Returns the object if it is still alive
+    pub fn get(&self) -> Option<W> {
+        self.weak.upgrade()
+    }
+
+    /// Calls `f` with the object if it is still alive
+    pub fn with<R, F: FnOnce(&W) -> R>(&self, f: F) -> Option<R> {
+        self.get().map(|object| f(&object))
+    }
+}
+
+impl<W: ObjectType> Clone for WeakCaptured<W> {
+    fn clone(&self) -> Self {
+        WeakCaptured {
+            weak: self.weak.clone(),
+        }
+    }
+}
+
+/// Wraps a future continuation so that it captures `object` weakly.
+///
+/// The returned closure passes `Some(object)` to `f` if the object is still alive when the continuation runs
+/// and `None` if it was destroyed.
+///
+/// Example:
+/// ```rust
+/// gtk_executor.spawn(
+///     load_text(path).map(with_weak(&label, |label: Option<gtk::Label>, text: String| {
+///         if let Some(label) = label {
+///             label.set_text(&text);
+///         }
+///     }))
+/// );
+/// ```
+pub fn with_weak<W, T, R, F>(object: &W, f: F) -> impl FnOnce(T) -> R
+    where W: ObjectType,
+          F: FnOnce(Option<W>, T) -> R
+{
+    let captured = WeakCaptured::new(object);
+    move |value| f(captured.get(), value)
+}
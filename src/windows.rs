@@ -0,0 +1,180 @@
+use futures::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::promise::Promise;
+
+struct WindowManagerState {
+    windows: Vec<gtk::Window>,
+    all_closed: Vec<Promise<(), ()>>,
+    all_closed_callbacks: Vec<Rc<Fn()>>,
+}
+
+/// `WindowManager` tracks toplevel windows of the application.
+///
+/// Windows are registered with `register()` and are tracked until they are destroyed.
+/// `windows_closed()` returns a future that resolves when the last registered window is closed,
+/// which is the usual condition for quitting a multi-window application.
+///
+/// `WindowManager` is cloneable (all clones refer to the same manager) and may only be used on Gtk+ main thread.
+///
+/// Example:
+/// ```rust
+/// let window_manager = WindowManager::new();
+/// window_manager.register(&main_window);
+/// window_manager.register(&log_window);
+///
+/// gtk_executor.run_main_until(window_manager.windows_closed());
+/// ```
+#[derive(Clone)]
+pub struct WindowManager {
+    state: Rc<RefCell<WindowManagerState>>,
+}
+
+impl WindowManager {
+    /// Constructs a manager with no windows
+    pub fn new() -> Self {
+        WindowManager {
+            state: Rc::new(RefCell::new(
+                WindowManagerState {
+                    windows: Vec::new(),
+                    all_closed: Vec::new(),
+                    all_closed_callbacks: Vec::new(),
+                }
+            ))
+        }
+    }
+
+    /// Starts tracking `window`. The window is forgotten when it is destroyed.
+    pub fn register<W: IsA<gtk::Window>>(&self, window: &W) {
+        let window: gtk::Window = window.clone().upcast();
+
+        {
+            let mut state = self.state.borrow_mut();
+            if state.windows.contains(&window) {
+                return;
+            }
+            state.windows.push(window.clone());
+        }
+
+        let state = self.state.clone();
+        window.connect_destroy(move |window| {
+            let (all_closed, callbacks) = {
+                let mut state = state.borrow_mut();
+                state.windows.retain(|w| w != window);
+                if state.windows.is_empty() {
+                    (std::mem::replace(&mut state.all_closed, Vec::new()), state.all_closed_callbacks.clone())
+                } else {
+                    (Vec::new(), Vec::new())
+                }
+            };
+
+            for promise in all_closed {
+                promise.resolve(());
+            }
+            for callback in callbacks {
+                callback();
+            }
+        });
+    }
+
+    /// Returns the windows that are currently open
+    pub fn windows(&self) -> Vec<gtk::Window> {
+        self.state.borrow().windows.clone()
+    }
+
+    /// Returns `true` if no registered window is open
+    pub fn is_empty(&self) -> bool {
+        self.state.borrow().windows.is_empty()
+    }
+
+    /// Registers a callback that is invoked every time the last open window is closed
+    pub(crate) fn connect_all_closed<F: Fn() + 'static>(&self, callback: F) {
+        self.state.borrow_mut().all_closed_callbacks.push(Rc::new(callback));
+    }
+
+    /// Returns a future that resolves when all registered windows are closed.
+    /// Resolves immediately if there are no open windows.
+    pub fn windows_closed(&self) -> impl Future<Item=(), Error=()> {
+        let promise = Promise::new();
+
+        let mut state = self.state.borrow_mut();
+        if state.windows.is_empty() {
+            promise.resolve(());
+        } else {
+            state.all_closed.push(promise.clone());
+        }
+
+        promise
+    }
+}
+
+impl Default for WindowManager {
+    fn default() -> Self {
+        WindowManager::new()
+    }
+}
+
+/// Returns a future that resolves when `window` is destroyed (e.g. closed by the user).
+/// This replaces the pattern of resolving a `Promise` from a `delete_event` handler.
+pub fn window_closed<W: IsA<gtk::Window>>(window: &W) -> impl Future<Item=(), Error=()> {
+    let promise = Promise::new();
+
+    {
+        let promise = promise.clone();
+        window.connect_destroy(move |_| {
+            promise.resolve(());
+        });
+    }
+
+    promise
+}
+
+/// Shows `window` as a modal window and runs a nested main loop until the window is hidden or destroyed.
+///
+/// Returned future blocks in its first `poll` while the nested loop runs. Other tasks of the executor
+/// continue to be dispatched inside the nested loop (their wakes are delivered through the same main context),
+/// so the rest of the application keeps working. This is meant for plugin-style dialogs that must be modal
+/// while their callers are async.
+pub fn run_window_modal<W: IsA<gtk::Window>>(window: &W) -> impl Future<Item=(), Error=()> {
+    let window: gtk::Window = window.clone().upcast();
+
+    futures::future::lazy(move || {
+        let main_loop = glib::MainLoop::new(None, false);
+
+        // Handlers of a destroyed window are already gone, so the destroy handler takes the ids
+        // to keep them from being disconnected again
+        let hide_handler = Rc::new(RefCell::new(None));
+        let destroy_handler = Rc::new(RefCell::new(None));
+
+        let hide = {
+            let main_loop = main_loop.clone();
+            window.connect_hide(move |_| main_loop.quit())
+        };
+        *hide_handler.borrow_mut() = Some(hide);
+        let destroy = {
+            let main_loop = main_loop.clone();
+            let hide_handler = hide_handler.clone();
+            let destroy_handler = destroy_handler.clone();
+            window.connect_destroy(move |_| {
+                hide_handler.borrow_mut().take();
+                destroy_handler.borrow_mut().take();
+                main_loop.quit();
+            })
+        };
+        *destroy_handler.borrow_mut() = Some(destroy);
+
+        window.set_modal(true);
+        window.show_all();
+
+        main_loop.run();
+
+        let handlers = vec![hide_handler.borrow_mut().take(), destroy_handler.borrow_mut().take()];
+        for handler in handlers.into_iter().flatten() {
+            window.disconnect(handler);
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,116 @@
+//! Bindings must drop the streams they no longer show, together with everything the streams captured.
+
+use futures::prelude::*;
+use futures::stream;
+use futures03::compat::Future01CompatExt;
+use glib::ToValue;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk_future_executor::bind_collection;
+use gtk_future_executor::bind_label_text;
+use gtk_future_executor::delay;
+use gtk_future_executor::gtk_async_test;
+use gtk_future_executor::GtkEventLoopAsyncExecutor;
+use gtk_future_executor::ListBoxBinding;
+
+/// Sets the flag when dropped
+struct DropFlag(Rc<Cell<bool>>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+/// A stream that never produces an item and sets `dropped` when it is dropped
+fn quiet_stream<T>(dropped: &Rc<Cell<bool>>) -> impl Stream<Item=T, Error=()> {
+    let flag = DropFlag(dropped.clone());
+    stream::poll_fn(move || {
+        let _ = &flag;
+        Ok(Async::NotReady)
+    })
+}
+
+/// Lets the executor poll the tasks that were spawned so far
+async fn settle() {
+    delay(Duration::from_millis(20)).compat().await.unwrap();
+}
+
+#[gtk_async_test]
+async fn rebinding_drops_quiet_stream(executor: GtkEventLoopAsyncExecutor) {
+    let listbox = gtk::ListBox::new();
+    let binding = ListBoxBinding::new(&executor, &listbox, |item: &String| gtk::Label::new(Some(item.as_str())));
+
+    let first_dropped = Rc::new(Cell::new(false));
+    binding.bind(quiet_stream(&first_dropped));
+    settle().await;
+    assert!(!first_dropped.get());
+
+    binding.bind(stream::iter_ok(vec!["item".to_string()]));
+    assert!(first_dropped.get());
+}
+
+#[gtk_async_test]
+async fn unbinding_drops_quiet_stream(executor: GtkEventLoopAsyncExecutor) {
+    let listbox = gtk::ListBox::new();
+    let binding = ListBoxBinding::new(&executor, &listbox, |item: &String| gtk::Label::new(Some(item.as_str())));
+
+    let dropped = Rc::new(Cell::new(false));
+    binding.bind(quiet_stream(&dropped));
+    settle().await;
+    assert!(!dropped.get());
+
+    binding.unbind();
+    assert!(dropped.get());
+}
+
+#[gtk_async_test]
+async fn destroying_label_drops_quiet_stream(executor: GtkEventLoopAsyncExecutor) {
+    let label = gtk::Label::new(None);
+
+    let dropped = Rc::new(Cell::new(false));
+    bind_label_text(&executor, &label, quiet_stream::<String>(&dropped));
+    settle().await;
+    assert!(!dropped.get());
+
+    label.destroy();
+    assert!(dropped.get());
+}
+
+fn store_texts(store: &gtk::ListStore) -> Vec<String> {
+    let mut texts = Vec::new();
+    if let Some(iter) = store.get_iter_first() {
+        loop {
+            texts.push(store.get_value(&iter, 0).get::<String>().unwrap());
+            if !store.iter_next(&iter) {
+                break;
+            }
+        }
+    }
+    texts
+}
+
+#[gtk_async_test]
+async fn collection_with_duplicate_keys_stays_in_sync(executor: GtkEventLoopAsyncExecutor) {
+    let store = gtk::ListStore::new(&[gtk::Type::String]);
+    let snapshots = vec![
+        vec![(1, "a"), (2, "b")],
+        vec![(1, "a"), (1, "c"), (2, "b")],
+        vec![(2, "b"), (1, "d")],
+        vec![(1, "e"), (1, "f")],
+        vec![(1, "g")],
+    ];
+
+    let (sender, receiver) = futures::sync::mpsc::unbounded();
+    bind_collection(&executor, receiver, &store, |item: &(u32, &'static str)| item.0, |item| vec![item.1.to_value()]);
+
+    for snapshot in snapshots {
+        let expected: Vec<String> = snapshot.iter().map(|&(_, text)| text.to_string()).collect();
+        sender.unbounded_send(snapshot).unwrap();
+        settle().await;
+        assert_eq!(store_texts(&store), expected);
+    }
+}
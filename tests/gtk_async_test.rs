@@ -0,0 +1,36 @@
+//! Several Gtk+ tests in one test binary: each runs on a new test runner thread,
+//! so all of them must be moved to the thread that initialized Gtk+.
+
+use futures03::compat::Future01CompatExt;
+use gtk::prelude::*;
+use gtk_future_executor::GtkEventLoopAsyncExecutor;
+use gtk_future_executor::Promise;
+use gtk_future_executor::gtk_async_test;
+
+fn set_label_later(executor: &GtkEventLoopAsyncExecutor, label: &gtk::Label, text: &'static str) -> Promise<(), ()> {
+    let done = Promise::new();
+    let label = label.clone();
+    let resolved = done.clone();
+    executor.spawn(futures::future::lazy(move || {
+        label.set_text(text);
+        resolved.resolve(());
+        Ok(())
+    }));
+    done
+}
+
+#[gtk_async_test]
+async fn first_gtk_test(executor: GtkEventLoopAsyncExecutor) {
+    assert!(gtk::is_initialized_main_thread());
+    let label = gtk::Label::new(Some("first"));
+    set_label_later(&executor, &label, "updated by the first test").compat().await.unwrap();
+    assert_eq!(label.get_text().unwrap().as_str(), "updated by the first test");
+}
+
+#[gtk_async_test]
+async fn second_gtk_test(executor: GtkEventLoopAsyncExecutor) {
+    assert!(gtk::is_initialized_main_thread());
+    let label = gtk::Label::new(Some("second"));
+    set_label_later(&executor, &label, "updated by the second test").compat().await.unwrap();
+    assert_eq!(label.get_text().unwrap().as_str(), "updated by the second test");
+}